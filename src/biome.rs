@@ -0,0 +1,330 @@
+use noise::{NoiseFn, Perlin};
+
+use crate::feature::{BirchTree, Boulder, Bush, Feature, OakTree, TallBirchTree};
+use crate::realm::BlockType;
+
+//混合高度时采样的范围，单位为方块
+const BLEND_RADIUS: i32 = 16;
+
+//气候噪声的缩放，越大生物群系越大
+const CONTINENTAL_SCALE: f64 = 512.0;
+const TEMPERATURE_SCALE: f64 = 320.0;
+const HUMIDITY_SCALE: f64 = 256.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Desert,
+    Snowy,
+    Mountains,
+    Ocean,
+}
+
+/*
+ * 生物群系的地形参数，高度都是相对海平面的
+ * 地面高度为 海平面 + (base_height + 地形噪声 * height_scale) * 生成器的振幅，
+ * 地形噪声按ridged在丘陵噪声和山脊噪声之间插值，边界处这三个参数都按周围的群系混合
+ */
+pub struct BiomeInfo {
+    #[allow(unused)]
+    pub name: &'static str,
+    pub base_height: f64,
+    pub height_scale: f64,
+    //0为平缓的丘陵，1为尖锐的山脊
+    pub ridged: f64,
+    //最上面一层
+    pub surface: BlockType,
+    //地表下面几层，再往下是石头
+    pub sub_surface: BlockType,
+    //比海平面高出这么多的地表换成雪块
+    pub snow_line: i32,
+    //每个特征格子中生成各个特征的概率，总和不超过1，见feature::place_features
+    pub features: &'static [(f64, &'static dyn Feature)],
+}
+
+const PLAINS: BiomeInfo = BiomeInfo {
+    name: "平原",
+    base_height: 3.0,
+    height_scale: 4.0,
+    ridged: 0.0,
+    surface: BlockType::Grass,
+    sub_surface: BlockType::Dirt,
+    snow_line: i32::MAX,
+    features: &[(0.03, &OakTree), (0.03, &Bush), (0.01, &Boulder)],
+};
+
+const FOREST: BiomeInfo = BiomeInfo {
+    name: "森林",
+    base_height: 4.0,
+    height_scale: 6.0,
+    ridged: 0.0,
+    surface: BlockType::Grass,
+    sub_surface: BlockType::Dirt,
+    snow_line: i32::MAX,
+    features: &[
+        (0.25, &OakTree),
+        (0.12, &BirchTree),
+        (0.05, &TallBirchTree),
+        (0.05, &Bush),
+    ],
+};
+
+const DESERT: BiomeInfo = BiomeInfo {
+    name: "沙漠",
+    base_height: 3.0,
+    height_scale: 3.0,
+    ridged: 0.0,
+    surface: BlockType::Sand,
+    sub_surface: BlockType::Sand,
+    snow_line: i32::MAX,
+    features: &[],
+};
+
+const SNOWY: BiomeInfo = BiomeInfo {
+    name: "雪原",
+    base_height: 5.0,
+    height_scale: 6.0,
+    ridged: 0.0,
+    surface: BlockType::Snow,
+    sub_surface: BlockType::Dirt,
+    snow_line: i32::MAX,
+    features: &[(0.06, &BirchTree), (0.02, &TallBirchTree)],
+};
+
+const MOUNTAINS: BiomeInfo = BiomeInfo {
+    name: "山地",
+    base_height: 24.0,
+    height_scale: 28.0,
+    ridged: 1.0,
+    surface: BlockType::Stone,
+    sub_surface: BlockType::Stone,
+    snow_line: 36,
+    features: &[(0.02, &OakTree), (0.02, &Bush), (0.04, &Boulder)],
+};
+
+const OCEAN: BiomeInfo = BiomeInfo {
+    name: "海洋",
+    base_height: -14.0,
+    height_scale: 5.0,
+    ridged: 0.0,
+    surface: BlockType::Sand,
+    sub_surface: BlockType::Sand,
+    snow_line: i32::MAX,
+    features: &[],
+};
+
+impl Biome {
+    #[allow(unused)]
+    pub const ALL: [Biome; 6] = [
+        Biome::Plains,
+        Biome::Forest,
+        Biome::Desert,
+        Biome::Snowy,
+        Biome::Mountains,
+        Biome::Ocean,
+    ];
+
+    pub fn info(self) -> &'static BiomeInfo {
+        match self {
+            Biome::Plains => &PLAINS,
+            Biome::Forest => &FOREST,
+            Biome::Desert => &DESERT,
+            Biome::Snowy => &SNOWY,
+            Biome::Mountains => &MOUNTAINS,
+            Biome::Ocean => &OCEAN,
+        }
+    }
+}
+
+//混合后的地形参数，含义和BiomeInfo中的相同
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HeightProfile {
+    pub base_height: f64,
+    pub height_scale: f64,
+    pub ridged: f64,
+}
+
+/*
+ * 根据温度、湿度和大陆噪声选择生物群系
+ * 三个噪声的种子都由世界种子得到，同一个种子在同一个坐标总是相同的群系
+ */
+pub struct BiomeMap {
+    continental: Perlin,
+    temperature: Perlin,
+    humidity: Perlin,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            continental: Perlin::new(seed.wrapping_add(1)),
+            temperature: Perlin::new(seed.wrapping_add(2)),
+            humidity: Perlin::new(seed.wrapping_add(3)),
+        }
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let (x, z) = (x as f64, z as f64);
+        //先用大陆噪声分出海洋和山地，其余的按气候划分
+        let continental = self
+            .continental
+            .get([x / CONTINENTAL_SCALE, z / CONTINENTAL_SCALE]);
+        if continental < -0.25 {
+            return Biome::Ocean;
+        }
+        if continental > 0.35 {
+            return Biome::Mountains;
+        }
+
+        let temperature = self
+            .temperature
+            .get([x / TEMPERATURE_SCALE, z / TEMPERATURE_SCALE]);
+        let humidity = self.humidity.get([x / HUMIDITY_SCALE, z / HUMIDITY_SCALE]);
+        if temperature < -0.25 {
+            Biome::Snowy
+        } else if temperature > 0.25 && humidity < 0.0 {
+            Biome::Desert
+        } else if humidity > 0.15 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /*
+     * 从(area_x, area_z)开始、边长为size的范围内每一列混合后的高度参数，下标为 x * size + z
+     * 先算出范围周围BLEND_RADIUS内每一列的群系，再分别沿x和z做三角形权重的平均，
+     * 离得越近的列权重越大，群系边界处的高度逐渐过渡，不会出现断崖
+     */
+    pub fn blended_heights(&self, area_x: i32, area_z: i32, size: i32) -> Vec<HeightProfile> {
+        let width = (size + BLEND_RADIUS * 2) as usize;
+        let start_x = area_x - BLEND_RADIUS;
+        let start_z = area_z - BLEND_RADIUS;
+        let weight = |d: i32| (BLEND_RADIUS + 1 - d.abs()) as f64;
+        let total_weight: f64 = (-BLEND_RADIUS..=BLEND_RADIUS).map(weight).sum();
+
+        let mut columns = Vec::with_capacity(width * width);
+        for x in 0..width as i32 {
+            for z in 0..width as i32 {
+                let info = self.biome_at(start_x + x, start_z + z).info();
+                columns.push(HeightProfile {
+                    base_height: info.base_height,
+                    height_scale: info.height_scale,
+                    ridged: info.ridged,
+                });
+            }
+        }
+        let average = |get: &dyn Fn(i32) -> HeightProfile| {
+            let mut sum = HeightProfile::default();
+            for d in -BLEND_RADIUS..=BLEND_RADIUS {
+                let profile = get(d);
+                sum.base_height += profile.base_height * weight(d);
+                sum.height_scale += profile.height_scale * weight(d);
+                sum.ridged += profile.ridged * weight(d);
+            }
+            HeightProfile {
+                base_height: sum.base_height / total_weight,
+                height_scale: sum.height_scale / total_weight,
+                ridged: sum.ridged / total_weight,
+            }
+        };
+
+        //沿x方向平均，只保留范围内的x
+        let mut along_x = Vec::with_capacity(size as usize * width);
+        for x in 0..size {
+            for z in 0..width {
+                along_x.push(average(&|d| {
+                    columns[(x + BLEND_RADIUS + d) as usize * width + z]
+                }));
+            }
+        }
+
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        for x in 0..size as usize {
+            for z in 0..size {
+                heights.push(average(&|d| {
+                    along_x[x * width + (z + BLEND_RADIUS + d) as usize]
+                }));
+            }
+        }
+        heights
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realm::{ChunkCoord, CHUNK_SIZE};
+
+    fn chunk_heights(map: &BiomeMap, coord: &ChunkCoord) -> Vec<HeightProfile> {
+        map.blended_heights(coord.x * CHUNK_SIZE, coord.z * CHUNK_SIZE, CHUNK_SIZE)
+    }
+
+    #[test]
+    fn test_biome_map_deterministic() {
+        let map = BiomeMap::new(42);
+        let same = BiomeMap::new(42);
+        let other = BiomeMap::new(7);
+
+        let mut found = Vec::new();
+        let mut differs = false;
+        for x in (-4096..4096).step_by(64) {
+            for z in (-4096..4096).step_by(64) {
+                let biome = map.biome_at(x, z);
+                assert_eq!(biome, same.biome_at(x, z));
+                differs |= biome != other.biome_at(x, z);
+                if !found.contains(&biome) {
+                    found.push(biome);
+                }
+            }
+        }
+        //不同的种子得到不同的分布，足够大的范围内所有群系都会出现
+        assert!(differs);
+        for biome in Biome::ALL {
+            assert!(found.contains(&biome), "没有生成{:?}", biome);
+        }
+    }
+
+    #[test]
+    fn test_blended_heights_are_smooth() {
+        let map = BiomeMap::new(42);
+        //沿x方向连续的一排区块，包括区块之间的边界
+        for chunk_z in (-128..128).step_by(29) {
+            let mut row: Vec<Vec<HeightProfile>> = vec![Vec::new(); CHUNK_SIZE as usize];
+            for chunk_x in -96..-64 {
+                let heights = chunk_heights(&map, &ChunkCoord::new(chunk_x, chunk_z));
+                for (i, height) in heights.into_iter().enumerate() {
+                    row[i % CHUNK_SIZE as usize].push(height);
+                }
+            }
+            for line in row {
+                for pair in line.windows(2) {
+                    assert!((pair[1].base_height - pair[0].base_height).abs() < 2.0);
+                    assert!((pair[1].height_scale - pair[0].height_scale).abs() < 2.0);
+                    assert!((pair[1].ridged - pair[0].ridged).abs() < 0.1);
+                }
+            }
+        }
+
+        //海洋中间的地面在海平面以下
+        let coord = (-256..256)
+            .step_by(4)
+            .flat_map(|x| (-256..256).step_by(4).map(move |z| ChunkCoord::new(x, z)))
+            .filter(|coord| {
+                map.biome_at(coord.x * CHUNK_SIZE, coord.z * CHUNK_SIZE) == Biome::Ocean
+            })
+            .find(|coord| {
+                let heights = chunk_heights(&map, coord);
+                heights
+                    .iter()
+                    .all(|h| (h.base_height - OCEAN.base_height).abs() < 1e-6)
+            })
+            .unwrap();
+        let heights = chunk_heights(&map, &coord);
+        assert!(heights
+            .iter()
+            .all(|h| (h.height_scale - OCEAN.height_scale).abs() < 1e-6));
+        assert!(heights.iter().all(|h| h.base_height + h.height_scale < 0.0));
+    }
+}
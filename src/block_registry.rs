@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::realm::{BlockInfo, BlockType};
+
+//方块定义文件，启动时读取
+pub const BLOCKS_FILE: &str = "res/blocks.ron";
+//贴图目录，贴图名加上.png就是文件名
+pub const TEXTURE_DIR: &str = "res/texture";
+
+//各个面使用的贴图名
+#[derive(Debug, Deserialize)]
+enum FaceTextures {
+    //六个面相同
+    All(String),
+    //四个侧面相同，例如草方块和原木
+    Sides {
+        top: String,
+        bottom: String,
+        side: String,
+    },
+    //顺序为：正、上、后、下、左、右
+    Faces([String; 6]),
+}
+
+impl FaceTextures {
+    fn faces(&self) -> [&str; 6] {
+        match self {
+            FaceTextures::All(name) => [name.as_str(); 6],
+            FaceTextures::Sides { top, bottom, side } => [side, top, side, bottom, side, side],
+            FaceTextures::Faces(names) => [
+                &names[0], &names[1], &names[2], &names[3], &names[4], &names[5],
+            ],
+        }
+    }
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockDefinition {
+    name: String,
+    textures: FaceTextures,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    translucent: bool,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+}
+
+/*
+ * 所有方块的信息，方块编号就是定义文件中的顺序(从1开始，0为空方块)
+ * 纹理数组和着色器中的材质都由这里生成，添加方块只需要修改定义文件
+ */
+pub struct BlockRegistry {
+    blocks: Vec<BlockInfo>,
+    //下标为纹理数组的层
+    textures: Vec<String>,
+}
+
+impl BlockRegistry {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("读取方块定义文件{}失败", path.display()))?;
+        Self::parse(&text).with_context(|| format!("方块定义文件{}有误", path.display()))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let definitions: Vec<BlockDefinition> = ron::from_str(text).context("格式错误")?;
+
+        let mut blocks = vec![BlockInfo {
+            name: "empty".to_string(),
+            block_type: BlockType::Empty,
+            tex_offset: [0; 6],
+            transparent: true,
+            translucent: false,
+            solid: false,
+            hardness: 0.0,
+        }];
+        let mut textures: Vec<String> = Vec::new();
+        let mut layers: HashMap<String, u32> = HashMap::new();
+        for definition in definitions {
+            if blocks.iter().any(|block| block.name == definition.name) {
+                bail!("方块{}重复定义", definition.name);
+            }
+            let tex_offset = definition.textures.faces().map(|name| {
+                *layers.entry(name.to_string()).or_insert_with(|| {
+                    textures.push(name.to_string());
+                    textures.len() as u32 - 1
+                })
+            });
+            blocks.push(BlockInfo {
+                name: definition.name,
+                block_type: BlockType(blocks.len() as u32),
+                tex_offset,
+                transparent: definition.transparent,
+                translucent: definition.translucent,
+                solid: definition.solid,
+                hardness: definition.hardness,
+            });
+        }
+
+        //代码中直接使用的方块必须在约定的位置
+        for (tp, name) in BlockType::BUILTIN {
+            match blocks.get(tp.0 as usize) {
+                Some(block) if block.name == name => {}
+                _ => bail!("第{}个方块应该是{}", tp.0, name),
+            }
+        }
+        Ok(Self { blocks, textures })
+    }
+
+    pub fn blocks(&self) -> &[BlockInfo] {
+        &self.blocks
+    }
+
+    //存档中可能有定义文件里已经删除的方块，当作空方块
+    pub fn get(&self, tp: BlockType) -> &BlockInfo {
+        self.blocks.get(tp.0 as usize).unwrap_or(&self.blocks[0])
+    }
+
+    #[allow(unused)]
+    pub fn find(&self, name: &str) -> Option<BlockType> {
+        self.blocks
+            .iter()
+            .find(|block| block.name == name)
+            .map(|block| block.block_type)
+    }
+
+    pub fn textures(&self) -> &[String] {
+        &self.textures
+    }
+}
+
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+//第一次使用时读取定义文件，之后不再改变
+pub fn registry() -> &'static BlockRegistry {
+    REGISTRY.get_or_init(|| {
+        BlockRegistry::load(Path::new(BLOCKS_FILE)).unwrap_or_else(|e| panic!("{:#}", e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILTIN_DEFINITIONS: &str = r#"
+        (name: "bedrock", textures: All("bedrock"), hardness: -1.0),
+        (name: "stone", textures: All("stone")),
+        (name: "grass_block", textures: Sides(top: "grass_block_top", bottom: "dirt", side: "grass_block_side")),
+        (name: "dirt", textures: All("dirt")),
+        (name: "birch_log", textures: Sides(top: "oak_log_top", bottom: "oak_log_top", side: "oak_log")),
+        (name: "birch_leaves", textures: All("oak_leaves"), transparent: true),
+        (name: "birch_planks", textures: All("birch_planks")),
+        (name: "test_block", textures: All("skin")),
+        (name: "sand", textures: All("sand")),
+        (name: "snow_block", textures: All("snow")),
+        (name: "oak_log", textures: Sides(top: "oak_log_top", bottom: "oak_log_top", side: "oak_log")),
+        (name: "oak_leaves", textures: All("oak_leaves"), transparent: true),
+        (name: "coal_ore", textures: All("coal_ore")),
+        (name: "iron_ore", textures: All("iron_ore")),
+        (name: "gold_ore", textures: All("gold_ore")),
+        (name: "diamond_ore", textures: All("diamond_ore")),
+        (name: "water", textures: All("water"), transparent: true, translucent: true, solid: false),
+    "#;
+
+    #[test]
+    fn test_parse_blocks() {
+        let text = format!(
+            "[{}(name: \"glass\", textures: All(\"glass\"), transparent: true, hardness: 0.3)]",
+            BUILTIN_DEFINITIONS
+        );
+        let registry = BlockRegistry::parse(&text).unwrap();
+        assert_eq!(registry.blocks().len(), 19);
+
+        let grass = registry.get(BlockType::Grass);
+        let layer = |name: &str| registry.textures().iter().position(|t| t == name).unwrap() as u32;
+        assert_eq!(grass.tex_offset[1], layer("grass_block_top"));
+        assert_eq!(grass.tex_offset[3], layer("dirt"));
+        assert_eq!(grass.tex_offset[0], layer("grass_block_side"));
+        //相同的贴图只占用一层
+        assert_eq!(registry.get(BlockType::Dirt).tex_offset, [layer("dirt"); 6]);
+
+        let glass = registry.find("glass").unwrap();
+        assert_eq!(glass, BlockType(18));
+        assert!(registry.get(glass).transparent);
+        assert!(registry.get(glass).solid);
+        assert!(!registry.get(glass).translucent);
+        assert_eq!(registry.get(glass).hardness, 0.3);
+        assert!(registry.get(BlockType::UnderStone).hardness < 0.0);
+        //未知编号当作空方块
+        assert_eq!(registry.get(BlockType(100)).block_type, BlockType::Empty);
+    }
+
+    #[test]
+    fn test_invalid_blocks() {
+        //内置方块位置不对
+        assert!(BlockRegistry::parse(r#"[(name: "stone", textures: All("stone"))]"#).is_err());
+        let text = format!(
+            "[{}(name: \"stone\", textures: All(\"a\"))]",
+            BUILTIN_DEFINITIONS
+        );
+        assert!(BlockRegistry::parse(&text).is_err());
+        assert!(BlockRegistry::parse("[(name: \"a\")]").is_err());
+    }
+
+    #[test]
+    fn test_default_blocks_file() {
+        let registry = registry();
+        for texture in registry.textures() {
+            let path = Path::new(TEXTURE_DIR).join(format!("{}.png", texture));
+            assert!(path.exists(), "缺少贴图{}", path.display());
+        }
+        assert!(BlockType::Empty.is_transparent());
+        assert!(!BlockType::Stone.is_transparent());
+        assert!(BlockType::Stone.is_solid());
+        assert!(BlockType::Water.is_translucent());
+        assert!(BlockType::Water.is_transparent());
+        assert!(!BlockType::Water.is_solid());
+    }
+}
@@ -1,6 +1,7 @@
 // --
 use crate::entity::Player;
 use crate::item;
+use crate::physics::{Aabb, PlayerPhysics};
 use crate::{game_config, realm};
 use cgmath::*;
 use std::f32::consts::FRAC_PI_2;
@@ -35,12 +36,14 @@ impl Camera {
 
     //返回方向向量
     pub fn direction(&self) -> Vector3<f32> {
-        Vector3 {
-            x: self.yaw.0.cos() * self.pitch.0.cos(),
-            y: self.pitch.0.sin(),
-            z: self.yaw.0.sin() * self.pitch.0.cos(),
-        }
-        .normalize()
+        direction_of(self.yaw, self.pitch)
+    }
+
+    //摄像机跟随玩家时每帧重新设置
+    pub fn set_view(&mut self, position: Point3<f32>, yaw: Rad<f32>, pitch: Rad<f32>) {
+        self.position = position;
+        self.yaw = yaw;
+        self.pitch = pitch;
     }
 
     pub fn calc_matrix(&self) -> Matrix4<f32> {
@@ -54,6 +57,46 @@ impl Camera {
     }
 }
 
+//yaw和pitch对应的方向向量
+pub fn direction_of(yaw: Rad<f32>, pitch: Rad<f32>) -> Vector3<f32> {
+    Vector3 {
+        x: yaw.0.cos() * pitch.0.cos(),
+        y: pitch.0.sin(),
+        z: yaw.0.sin() * pitch.0.cos(),
+    }
+    .normalize()
+}
+
+//视距(区块半径)对应的远平面距离，覆盖到已加载区域的对角
+pub fn render_distance_zfar(chunk_rad: i32) -> f32 {
+    ((chunk_rad + 1) * realm::CHUNK_SIZE) as f32 * std::f32::consts::SQRT_2
+}
+
+//第三人称时摄像机到玩家眼睛的距离
+const THIRD_PERSON_DISTANCE: f32 = 4.0;
+//摄像机和方块之间保留的距离，避免看到方块内部
+const THIRD_PERSON_MARGIN: f32 = 0.2;
+
+//摄像机视角，按V键切换
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewMode {
+    FirstPerson,
+    //在玩家身后
+    ThirdPersonBack,
+    //在玩家前方看向玩家
+    ThirdPersonFront,
+}
+
+impl ViewMode {
+    pub fn next(self) -> Self {
+        match self {
+            ViewMode::FirstPerson => ViewMode::ThirdPersonBack,
+            ViewMode::ThirdPersonBack => ViewMode::ThirdPersonFront,
+            ViewMode::ThirdPersonFront => ViewMode::FirstPerson,
+        }
+    }
+}
+
 pub struct Projection {
     aspect: f32,
     fovy: Rad<f32>,
@@ -75,6 +118,11 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    //远平面跟随视距变化
+    pub fn set_zfar(&mut self, zfar: f32) {
+        self.zfar = zfar;
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
@@ -88,6 +136,42 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+/*
+ * 视锥体的六个平面，由 投影矩阵*视图矩阵 得到
+ * 平面为(a,b,c,d)，ax+by+cz+d>=0 在视锥体内侧
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let row = |i: usize| view_proj.row(i);
+        Self {
+            planes: [
+                row(3) + row(0),
+                row(3) - row(0),
+                row(3) + row(1),
+                row(3) - row(1),
+                //wgpu的深度范围是[0,1]，近平面直接取第三行
+                row(2),
+                row(3) - row(2),
+            ],
+        }
+    }
+
+    //包围盒完全在某个平面外侧时不可见，取离平面最近内侧的顶点判断
+    pub fn intersects_aabb(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let x = if plane.x >= 0.0 { max.x } else { min.x };
+            let y = if plane.y >= 0.0 { max.y } else { min.y };
+            let z = if plane.z >= 0.0 { max.z } else { min.z };
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+}
+
 pub struct CameraController {
     pub is_fov: bool,
     pub is_cursor_visible: bool,
@@ -103,6 +187,9 @@ pub struct CameraController {
     pub fov_sensitivity: f32,
     pub selected_block: Option<Point3<i32>>,
     pub pre_selected_block: Option<Point3<i32>>,
+    //飞行模式下自由移动，否则受重力和碰撞影响
+    pub is_flying: bool,
+    pub view_mode: ViewMode,
     dx: f32,
     dy: f32,
 }
@@ -126,6 +213,8 @@ impl CameraController {
             dy: 0.0,
             selected_block: None,
             pre_selected_block: None,
+            is_flying: false,
+            view_mode: ViewMode::FirstPerson,
         }
     }
 
@@ -134,11 +223,9 @@ impl CameraController {
     pub fn process_events(
         &mut self,
         event: &WindowEvent,
-        camera: &mut Camera,
         realm: &mut realm::Realm,
-        queue: &wgpu::Queue,
         game_config: &mut game_config::GameConfig,
-        player: &Player,
+        player: &mut Player,
     ) -> bool {
         match event {
             WindowEvent::KeyboardInput {
@@ -191,16 +278,42 @@ impl CameraController {
                         }
                         false
                     }
+                    KeyCode::KeyF => {
+                        if is_pressed {
+                            self.is_flying = !self.is_flying;
+                            player.physics = PlayerPhysics::default();
+                        }
+                        true
+                    }
+                    KeyCode::KeyV => {
+                        if is_pressed {
+                            self.view_mode = self.view_mode.next();
+                        }
+                        true
+                    }
                     KeyCode::F5 => {
                         if is_pressed {
                             game_config.is_debug_window_open = !game_config.is_debug_window_open;
                         }
                         true
                     }
+                    //调整视距，区块的加载卸载在State::update中跟随
+                    KeyCode::Equal | KeyCode::NumpadAdd => {
+                        if is_pressed {
+                            game_config.set_render_distance(game_config.get_render_distance() + 1);
+                        }
+                        true
+                    }
+                    KeyCode::Minus | KeyCode::NumpadSubtract => {
+                        if is_pressed {
+                            game_config.set_render_distance(game_config.get_render_distance() - 1);
+                        }
+                        true
+                    }
                     KeyCode::KeyC => {
                         if is_pressed {
-                            camera.position.x -= 2.0;
-                            camera.position.z -= 2.0;
+                            player.position.x -= 2.0;
+                            player.position.z -= 2.0;
                         }
                         true
                     }
@@ -211,10 +324,7 @@ impl CameraController {
             //    self.is_fov = true;
             //    true
             //}
-            WindowEvent::Focused(false) => {
-                self.is_fov = false;
-                true
-            }
+            //失去焦点时由State暂停游戏
             WindowEvent::MouseInput {
                 state: ElementState::Pressed,
                 button: MouseButton::Left,
@@ -223,7 +333,10 @@ impl CameraController {
                 //println!("left mouse button pressed");
                 if self.is_fov {
                     if let Some(selected_block) = self.selected_block {
-                        realm.place_block(selected_block, realm::BLOCK_EMPTY, queue);
+                        //硬度为负的方块无法破坏
+                        if realm.data.get_block(selected_block).tp.info().hardness >= 0.0 {
+                            realm.place_block(selected_block, realm::BLOCK_EMPTY);
+                        }
                         return true;
                     }
                 }
@@ -236,15 +349,21 @@ impl CameraController {
             } => {
                 if self.is_fov {
                     if let Some(pre_selected_block) = self.pre_selected_block {
+                        //不能把方块放在玩家身体所在的位置
+                        if !self.is_flying
+                            && Aabb::block(pre_selected_block)
+                                .intersects(&Aabb::player(player.position))
+                        {
+                            return true;
+                        }
                         if let item::ItemType::Block(block) =
-                            player.hotbar[player.slected_hotbar as usize].item_type
+                            &player.hotbar[player.slected_hotbar as usize].item_type
                         {
                             realm.place_block(
                                 pre_selected_block,
                                 realm::Block {
                                     tp: block.block_type,
                                 },
-                                queue,
                             );
                         }
 
@@ -256,12 +375,13 @@ impl CameraController {
             WindowEvent::CursorMoved { position, .. } if self.is_fov => {
                 self.dx = (self.center_x as f64 - position.x) as f32;
                 self.dy = (self.center_y as f64 - position.y) as f32;
-                camera.yaw -= Rad(self.dx) * self.fov_sensitivity;
-                camera.pitch += Rad(self.dy) * self.fov_sensitivity;
-                if camera.pitch < -Rad(SAFE_FRAC_PI_2) {
-                    camera.pitch = -Rad(SAFE_FRAC_PI_2);
-                } else if camera.pitch > Rad(SAFE_FRAC_PI_2) {
-                    camera.pitch = Rad(SAFE_FRAC_PI_2);
+                let sensitivity = self.fov_sensitivity * game_config.mouse_sensitivity_scale;
+                player.yaw -= Rad(self.dx) * sensitivity;
+                player.pitch += Rad(self.dy) * sensitivity;
+                if player.pitch < -Rad(SAFE_FRAC_PI_2) {
+                    player.pitch = -Rad(SAFE_FRAC_PI_2);
+                } else if player.pitch > Rad(SAFE_FRAC_PI_2) {
+                    player.pitch = Rad(SAFE_FRAC_PI_2);
                 }
                 true
             }
@@ -269,36 +389,103 @@ impl CameraController {
         }
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32, data: &mut realm::RealmData) {
-        let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
+    //暂停时松开按键不会传到这里，恢复时清空移动状态
+    pub fn reset_movement(&mut self) {
+        self.is_forward_pressed = false;
+        self.is_backward_pressed = false;
+        self.is_left_pressed = false;
+        self.is_right_pressed = false;
+        self.is_up_pressed = false;
+        self.is_down_pressed = false;
+    }
+
+    //移动玩家，再把摄像机放到当前视角的位置
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        player: &mut Player,
+        dt: f32,
+        data: &mut realm::RealmData,
+    ) {
+        self.move_player(player, dt, data);
+        self.update_wf(player, data);
+        self.attach_camera(camera, player, data);
+    }
+
+    fn move_player(&mut self, player: &mut Player, dt: f32, data: &realm::RealmData) {
+        let (yaw_sin, yaw_cos) = player.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
         //为什么这个是右边？？这不是左边吗？？
         let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
 
+        if !self.is_flying {
+            let mut wish = Vector3::zero();
+            if self.is_forward_pressed {
+                wish += forward;
+            }
+            if self.is_backward_pressed {
+                wish -= forward;
+            }
+            if self.is_left_pressed {
+                wish -= right;
+            }
+            if self.is_right_pressed {
+                wish += right;
+            }
+            //斜着走不会更快
+            if wish.magnitude2() > 0.0 {
+                wish = wish.normalize() * self.speed;
+            }
+            player.position =
+                player
+                    .physics
+                    .update(data, player.position, wish, self.is_up_pressed, dt);
+            return;
+        }
+
         if self.is_forward_pressed {
-            camera.position += forward * self.speed * dt;
+            player.position += forward * self.speed * dt;
         }
         if self.is_backward_pressed {
-            camera.position -= forward * self.speed * dt;
+            player.position -= forward * self.speed * dt;
         }
         if self.is_left_pressed {
-            camera.position -= right * self.speed * dt;
+            player.position -= right * self.speed * dt;
         }
         if self.is_right_pressed {
-            camera.position += right * self.speed * dt;
+            player.position += right * self.speed * dt;
         }
         if self.is_up_pressed {
-            camera.position.y += self.speed * dt;
+            player.position.y += self.speed * dt;
         }
         if self.is_down_pressed {
-            camera.position.y -= self.speed * dt;
+            player.position.y -= self.speed * dt;
         };
+    }
 
-        self.update_wf(camera, data);
+    fn attach_camera(&self, camera: &mut Camera, player: &Player, data: &realm::RealmData) {
+        let eye = player.eye_position();
+        let direction = player.direction();
+        match self.view_mode {
+            ViewMode::FirstPerson => camera.set_view(eye, player.yaw, player.pitch),
+            ViewMode::ThirdPersonBack => {
+                let distance = third_person_distance(eye, -direction, data);
+                camera.set_view(eye - direction * distance, player.yaw, player.pitch);
+            }
+            ViewMode::ThirdPersonFront => {
+                let distance = third_person_distance(eye, direction, data);
+                camera.set_view(
+                    eye + direction * distance,
+                    player.yaw + Rad(std::f32::consts::PI),
+                    -player.pitch,
+                );
+            }
+        }
     }
 
-    fn update_wf(&mut self, camera: &Camera, data: &mut realm::RealmData) {
-        match dda(camera.direction(), camera.position, data) {
+    //总是从玩家的眼睛选择方块，和摄像机的视角无关
+    fn update_wf(&mut self, player: &Player, data: &mut realm::RealmData) {
+        match dda(player.direction(), player.eye_position(), data) {
             Some(new_position) => {
                 data.is_wf_visible = true;
                 data.update_wf_uniform(new_position.0);
@@ -314,6 +501,25 @@ impl CameraController {
     }
 }
 
+//第三人称摄像机从眼睛沿direction后退，碰到方块时拉近
+fn third_person_distance(
+    eye: Point3<f32>,
+    direction: Vector3<f32>,
+    data: &realm::RealmData,
+) -> f32 {
+    const STEP: f32 = 0.05;
+    let mut distance = 0.0;
+    while distance < THIRD_PERSON_DISTANCE + THIRD_PERSON_MARGIN {
+        let point = eye + direction * distance;
+        let block = data.get_block(point.map(|v| v.floor() as i32));
+        if block.tp.is_solid() {
+            return (distance - THIRD_PERSON_MARGIN).max(0.0);
+        }
+        distance += STEP;
+    }
+    THIRD_PERSON_DISTANCE
+}
+
 //0号为选中方块，1号为选中方块的前一个方块
 fn dda(
     direction: Vector3<f32>,
@@ -367,8 +573,9 @@ fn dda(
 
         current_block[axis] += step[axis];
         t_max[axis] += t_delta[axis];
+        //空气和水不能选中，可以穿过水选中水底的方块，也可以把方块放进水里
         let block = data.get_block(current_block);
-        if block.tp != realm::BlockType::Empty {
+        if block.tp.is_solid() {
             return Some((current_block, pre_block));
         }
 
@@ -381,7 +588,7 @@ fn dda(
 #[cfg(test)]
 mod tests {
     use crate::{
-        camera::{dda, Camera},
+        camera::{dda, third_person_distance, Camera, Frustum, Projection, THIRD_PERSON_DISTANCE},
         realm,
     };
     use cgmath::*;
@@ -389,6 +596,11 @@ mod tests {
     #[test]
     fn test_dda1() {
         let mut data = realm::RealmData::new();
+        realm::Realm::generate_terrian_test(
+            &mut data.chunk_map,
+            &realm::ChunkCoord::new(0, 0),
+            data.seed,
+        );
         let mut position = Point3 {
             x: 0.5,
             y: 0.5,
@@ -416,4 +628,58 @@ mod tests {
             ans
         );
     }
+
+    #[test]
+    fn test_third_person_distance() {
+        let mut data = realm::RealmData::new();
+        realm::Realm::generate_terrian_test(
+            &mut data.chunk_map,
+            &realm::ChunkCoord::new(0, 0),
+            data.seed,
+        );
+        let eye = Point3::new(0.5, 5.62, 0.5);
+
+        //向上没有方块，保持完整距离
+        assert_eq!(
+            third_person_distance(eye, Vector3::unit_y(), &data),
+            THIRD_PERSON_DISTANCE
+        );
+        //向下碰到地面时拉近
+        let distance = third_person_distance(eye, -Vector3::unit_y(), &data);
+        assert!(distance > 1.3 && distance < 1.5, "distance:{}", distance);
+    }
+
+    #[test]
+    fn test_frustum_culling() {
+        //在原点看向+x
+        let camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let projection = Projection::new(800, 600, Deg(70.0), 0.1, 100.0);
+        let frustum = Frustum::from_matrix(projection.calc_matrix() * camera.calc_matrix());
+        let visible = |min: (f32, f32, f32), max: (f32, f32, f32)| {
+            frustum.intersects_aabb(Point3::from(min), Point3::from(max))
+        };
+
+        assert!(visible((10.0, -1.0, -1.0), (12.0, 1.0, 1.0)));
+        //身后、太远、偏到视野外
+        assert!(!visible((-12.0, -1.0, -1.0), (-10.0, 1.0, 1.0)));
+        assert!(!visible((10.0, -1.0, 30.0), (12.0, 1.0, 32.0)));
+        //包住摄像机的包围盒总是可见
+        assert!(visible((-16.0, -256.0, -16.0), (16.0, 256.0, 16.0)));
+        //只有一角进入视野
+        assert!(visible((10.0, -1.0, 5.0), (12.0, 1.0, 30.0)));
+
+        //单个点的结果和裁剪空间中的判断一致
+        let view_proj = projection.calc_matrix() * camera.calc_matrix();
+        for x in (-200..200).step_by(7) {
+            for z in (-200..200).step_by(11) {
+                let p = Point3::new(x as f32, 3.0, z as f32);
+                let clip = view_proj * p.to_homogeneous();
+                let inside = clip.x.abs() <= clip.w
+                    && clip.y.abs() <= clip.w
+                    && clip.z >= 0.0
+                    && clip.z <= clip.w;
+                assert_eq!(frustum.intersects_aabb(p, p), inside, "{:?}", p);
+            }
+        }
+    }
 }
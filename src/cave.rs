@@ -0,0 +1,112 @@
+use noise::{NoiseFn, Perlin};
+
+//y=0是基岩，洞穴最低挖到这一层
+const MIN_CAVE_Y: i32 = 1;
+
+//洞穴顶部和地面之间至少留这么多格，地表的树木和群系方块不会悬空
+const SURFACE_MARGIN: i32 = 5;
+
+//两个噪声同时接近0的地方形成细长的隧道，这个值越大隧道越粗
+const TUNNEL_WIDTH: f64 = 0.004;
+const TUNNEL_SCALE: [f64; 3] = [64.0, 32.0, 64.0];
+
+//低频噪声超过阈值的地方形成大的洞室
+const CAVERN_THRESHOLD: f64 = 0.55;
+const CAVERN_SCALE: [f64; 3] = [48.0, 20.0, 48.0];
+
+/*
+ * 用三维噪声挖出洞穴：意面状的隧道和奶酪状的洞室
+ * 噪声的种子都由世界种子得到，同一个位置总是得到相同的结果
+ */
+pub struct CaveCarver {
+    tunnel_a: Perlin,
+    tunnel_b: Perlin,
+    cavern: Perlin,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            tunnel_a: Perlin::new(seed.wrapping_add(20)),
+            tunnel_b: Perlin::new(seed.wrapping_add(21)),
+            cavern: Perlin::new(seed.wrapping_add(22)),
+        }
+    }
+
+    //这一列能挖的范围，surface为地面高度
+    pub fn carve_range(surface: i32) -> std::ops::Range<i32> {
+        MIN_CAVE_Y..(surface - SURFACE_MARGIN).max(MIN_CAVE_Y)
+    }
+
+    //(x, y, z)是否是洞穴，不检查carve_range
+    pub fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        let point = |scale: [f64; 3]| {
+            [
+                x as f64 / scale[0],
+                y as f64 / scale[1],
+                z as f64 / scale[2],
+            ]
+        };
+
+        let tunnel = point(TUNNEL_SCALE);
+        let a = self.tunnel_a.get(tunnel);
+        let b = self.tunnel_b.get(tunnel);
+        if a * a + b * b < TUNNEL_WIDTH {
+            return true;
+        }
+        self.cavern.get(point(CAVERN_SCALE)) > CAVERN_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_generator::ChunkGenerator;
+    use crate::realm::{BlockType, ChunkCoord, CHUNK_SIZE};
+    use crate::terrain::GeneratorPreset;
+
+    #[test]
+    fn test_caves_at_fixed_coordinates() {
+        let carver = CaveCarver::new(42);
+        let same = CaveCarver::new(42);
+        //种子42下(0, 1, 0)在隧道里，(0, 10, 42)在洞室里，(0, 20, 0)是实心的
+        let caves = [(0, 1, 0), (0, 10, 42)];
+        let solid = [(0, 20, 0), (0, 5, 0), (0, 15, 42)];
+        for (x, y, z) in caves {
+            assert!(carver.is_cave(x, y, z));
+            assert!(same.is_cave(x, y, z));
+        }
+        for (x, y, z) in solid {
+            assert!(!carver.is_cave(x, y, z));
+            assert!(!same.is_cave(x, y, z));
+        }
+        assert_eq!(CaveCarver::carve_range(35), 1..30);
+        assert!(CaveCarver::carve_range(3).is_empty());
+
+        //生成的区块中洞穴是空的，基岩不会被挖开
+        let generate = |x, z| {
+            ChunkGenerator::generate_terrain_internal(
+                ChunkCoord::new(x, z),
+                42,
+                &GeneratorPreset::Default,
+            )
+        };
+        let chunk = generate(0, 0);
+        assert_eq!(chunk.get_block(0, 1, 0).tp, BlockType::Empty);
+        assert_eq!(chunk.get_block(0, 20, 0).tp, BlockType::Stone);
+        let chunk = generate(0, 2);
+        assert_eq!(
+            chunk.get_block(0, 10, 42 - CHUNK_SIZE * 2).tp,
+            BlockType::Empty
+        );
+        assert_ne!(
+            chunk.get_block(0, 15, 42 - CHUNK_SIZE * 2).tp,
+            BlockType::Empty
+        );
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                assert_eq!(chunk.get_block(x, 0, z).tp, BlockType::UnderStone);
+            }
+        }
+    }
+}
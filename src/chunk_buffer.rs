@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use wgpu::{util, util::DeviceExt, Buffer, BufferUsages, Device, Queue};
+
+use crate::chunk_mesher::{ChunkMesh, ChunkVertex};
+use crate::realm::ChunkCoord;
+
+//初始容量，够放下几个区块，不够时翻倍
+const INITIAL_VERTICES: u32 = 1 << 14;
+const INITIAL_INDICES: u32 = 1 << 15;
+
+/*
+ * 在一段连续空间中分配区间，空闲区间按起点排序
+ * 释放时和相邻的空闲区间合并
+ */
+#[derive(Debug)]
+pub struct RangeAllocator {
+    capacity: u32,
+    free: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    pub fn new(capacity: u32) -> Self {
+        let mut allocator = Self {
+            capacity: 0,
+            free: Vec::new(),
+        };
+        allocator.grow(capacity);
+        allocator
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    //从前往后找第一个放得下的空闲区间
+    pub fn alloc(&mut self, len: u32) -> Option<Range<u32>> {
+        let index = self
+            .free
+            .iter()
+            .position(|range| range.end - range.start >= len)?;
+        let start = self.free[index].start;
+        self.free[index].start += len;
+        if self.free[index].is_empty() {
+            self.free.remove(index);
+        }
+        Some(start..start + len)
+    }
+
+    pub fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self.free.partition_point(|free| free.start < range.start);
+        self.free.insert(index, range);
+        //先和后一个合并，再和前一个合并
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free[index + 1].end;
+            self.free.remove(index + 1);
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free[index].end;
+            self.free.remove(index);
+        }
+    }
+
+    //扩大容量，新增的部分是空闲的
+    pub fn grow(&mut self, capacity: u32) {
+        if capacity <= self.capacity {
+            return;
+        }
+        let old = self.capacity;
+        self.capacity = capacity;
+        self.free(old..capacity);
+    }
+
+    #[allow(unused)]
+    pub fn free_len(&self) -> u32 {
+        self.free.iter().map(|range| range.end - range.start).sum()
+    }
+}
+
+/*
+ * 一个区块的网格在共享缓冲中的位置，索引是相对于自己顶点起点的
+ * 半透明的索引紧跟在不透明的索引后面，两者一起分配和释放
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshSlot {
+    pub vertices: Range<u32>,
+    pub indices: Range<u32>,
+    pub translucent_indices: Range<u32>,
+    //顶点的包围盒，高度只覆盖实际有方块的部分，用于视锥体剔除
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/*
+ * 所有区块的网格放在同一对顶点、索引缓冲中，CPU上保留一份镜像
+ * 插入、删除只修改镜像并记录写入的区间，每帧flush一次批量写入GPU
+ * 空间不够时容量翻倍，重新创建缓冲并写入整个镜像
+ */
+pub struct ChunkMeshBuffer {
+    vertices: Vec<ChunkVertex>,
+    indices: Vec<u32>,
+    vertex_alloc: RangeAllocator,
+    index_alloc: RangeAllocator,
+    slots: HashMap<ChunkCoord, MeshSlot>,
+    //flush时需要写入的区间
+    dirty_vertices: Vec<Range<u32>>,
+    dirty_indices: Vec<Range<u32>>,
+    //容量变化后GPU缓冲需要重新创建
+    resized: bool,
+    vertex_buffer: Option<Buffer>,
+    index_buffer: Option<Buffer>,
+}
+
+impl ChunkMeshBuffer {
+    pub fn new() -> Self {
+        Self {
+            vertices: vec![ChunkVertex::default(); INITIAL_VERTICES as usize],
+            indices: vec![0; INITIAL_INDICES as usize],
+            vertex_alloc: RangeAllocator::new(INITIAL_VERTICES),
+            index_alloc: RangeAllocator::new(INITIAL_INDICES),
+            slots: HashMap::new(),
+            dirty_vertices: Vec::new(),
+            dirty_indices: Vec::new(),
+            resized: true,
+            vertex_buffer: None,
+            index_buffer: None,
+        }
+    }
+
+    pub fn slots(&self) -> &HashMap<ChunkCoord, MeshSlot> {
+        &self.slots
+    }
+
+    //替换区块的网格，空网格等同于删除
+    pub fn insert(&mut self, coord: ChunkCoord, mesh: &ChunkMesh) {
+        self.remove(&coord);
+        if mesh.is_empty() {
+            return;
+        }
+        let vertex_count = mesh.vertices.len() as u32;
+        let opaque_count = mesh.indices.len() as u32;
+        let index_count = opaque_count + mesh.translucent_indices.len() as u32;
+        let vertices = Self::alloc(&mut self.vertex_alloc, vertex_count, &mut self.resized);
+        let indices = Self::alloc(&mut self.index_alloc, index_count, &mut self.resized);
+        self.vertices.resize(
+            self.vertex_alloc.capacity() as usize,
+            ChunkVertex::default(),
+        );
+        self.indices.resize(self.index_alloc.capacity() as usize, 0);
+
+        self.vertices[vertices.start as usize..vertices.end as usize]
+            .copy_from_slice(&mesh.vertices);
+        let split = indices.start + opaque_count;
+        self.indices[indices.start as usize..split as usize].copy_from_slice(&mesh.indices);
+        self.indices[split as usize..indices.end as usize]
+            .copy_from_slice(&mesh.translucent_indices);
+        self.dirty_vertices.push(vertices.clone());
+        self.dirty_indices.push(indices.clone());
+        let (min, max) = mesh_bounds(mesh);
+        self.slots.insert(
+            coord,
+            MeshSlot {
+                vertices,
+                indices: indices.start..split,
+                translucent_indices: split..indices.end,
+                min,
+                max,
+            },
+        );
+    }
+
+    //只释放空间，镜像中的旧数据不会再被绘制
+    pub fn remove(&mut self, coord: &ChunkCoord) {
+        if let Some(slot) = self.slots.remove(coord) {
+            self.vertex_alloc.free(slot.vertices);
+            self.index_alloc
+                .free(slot.indices.start..slot.translucent_indices.end);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let coords: Vec<ChunkCoord> = self.slots.keys().cloned().collect();
+        for coord in &coords {
+            self.remove(coord);
+        }
+    }
+
+    fn alloc(allocator: &mut RangeAllocator, len: u32, resized: &mut bool) -> Range<u32> {
+        loop {
+            if let Some(range) = allocator.alloc(len) {
+                return range;
+            }
+            let capacity = allocator.capacity();
+            allocator.grow((capacity * 2).max(len));
+            *resized = true;
+        }
+    }
+
+    //把这一帧的修改写入GPU
+    pub fn flush(&mut self, device: &Device, queue: &Queue) {
+        if self.resized {
+            self.vertex_buffer = Some(device.create_buffer_init(&util::BufferInitDescriptor {
+                label: Some("Chunk vertex buffer"),
+                contents: bytemuck::cast_slice(&self.vertices),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            }));
+            self.index_buffer = Some(device.create_buffer_init(&util::BufferInitDescriptor {
+                label: Some("Chunk index buffer"),
+                contents: bytemuck::cast_slice(&self.indices),
+                usage: BufferUsages::INDEX | BufferUsages::COPY_DST,
+            }));
+            self.resized = false;
+            self.dirty_vertices.clear();
+            self.dirty_indices.clear();
+            return;
+        }
+
+        let (Some(vertex_buffer), Some(index_buffer)) = (&self.vertex_buffer, &self.index_buffer)
+        else {
+            return;
+        };
+        let vertex_size = std::mem::size_of::<ChunkVertex>() as u64;
+        for range in self.dirty_vertices.drain(..) {
+            queue.write_buffer(
+                vertex_buffer,
+                range.start as u64 * vertex_size,
+                bytemuck::cast_slice(&self.vertices[range.start as usize..range.end as usize]),
+            );
+        }
+        for range in self.dirty_indices.drain(..) {
+            queue.write_buffer(
+                index_buffer,
+                range.start as u64 * 4,
+                bytemuck::cast_slice(&self.indices[range.start as usize..range.end as usize]),
+            );
+        }
+    }
+
+    //还没有flush过时返回None
+    pub fn buffers(&self) -> Option<(&Buffer, &Buffer)> {
+        Some((self.vertex_buffer.as_ref()?, self.index_buffer.as_ref()?))
+    }
+}
+
+fn mesh_bounds(mesh: &ChunkMesh) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for vertex in &mesh.vertices {
+        for i in 0..3 {
+            min[i] = min[i].min(vertex.position[i]);
+            max[i] = max[i].max(vertex.position[i]);
+        }
+    }
+    (min, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //测试用的伪随机数，不需要额外的依赖
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self, bound: u32) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as u32
+        }
+    }
+
+    //顶点数为len的网格，内容由seed区分
+    fn test_mesh(len: u32, seed: u32) -> ChunkMesh {
+        let mut mesh = ChunkMesh::default();
+        for i in 0..len {
+            mesh.vertices.push(ChunkVertex {
+                position: [i as f32, seed as f32, 0.0],
+                tex_coord: [0.0, 0.0],
+                layer: seed,
+            });
+            mesh.indices.push((i + seed) % len);
+            if i % 3 == seed % 3 {
+                mesh.translucent_indices.push(i);
+            }
+        }
+        mesh
+    }
+
+    #[test]
+    fn test_range_allocator() {
+        let mut alloc = RangeAllocator::new(10);
+        let a = alloc.alloc(4).unwrap();
+        let b = alloc.alloc(4).unwrap();
+        assert_eq!((a.clone(), b.clone()), (0..4, 4..8));
+        assert!(alloc.alloc(3).is_none());
+        alloc.free(a);
+        alloc.free(b);
+        //释放后合并成一整段
+        assert_eq!(alloc.alloc(10), Some(0..10));
+        alloc.grow(20);
+        assert_eq!(alloc.alloc(10), Some(10..20));
+        assert_eq!(alloc.free_len(), 0);
+    }
+
+    #[test]
+    fn test_random_edits_stay_consistent() {
+        let mut rng = XorShift(2025318);
+        let mut buffer = ChunkMeshBuffer::new();
+        let mut expected: HashMap<ChunkCoord, ChunkMesh> = HashMap::new();
+
+        for step in 0..2000 {
+            let coord = ChunkCoord::new(rng.next(8) as i32 - 4, rng.next(8) as i32 - 4);
+            if rng.next(4) == 0 {
+                buffer.remove(&coord);
+                expected.remove(&coord);
+            } else {
+                //偶尔放入很大的网格，触发扩容
+                let len = if rng.next(50) == 0 {
+                    rng.next(INITIAL_VERTICES) + 1
+                } else {
+                    rng.next(600)
+                };
+                let mesh = test_mesh(len, step);
+                buffer.insert(coord, &mesh);
+                if len > 0 {
+                    expected.insert(coord, mesh);
+                } else {
+                    expected.remove(&coord);
+                }
+            }
+
+            assert_eq!(buffer.slots().len(), expected.len());
+            let mut used_vertices = 0;
+            let mut used_indices = 0;
+            for (coord, mesh) in &expected {
+                let slot = &buffer.slots()[coord];
+                let vertices = slot.vertices.start as usize..slot.vertices.end as usize;
+                let indices = slot.indices.start as usize..slot.indices.end as usize;
+                assert_eq!(&buffer.vertices[vertices], &mesh.vertices[..]);
+                assert_eq!(&buffer.indices[indices], &mesh.indices[..]);
+                let translucent =
+                    slot.translucent_indices.start as usize..slot.translucent_indices.end as usize;
+                assert_eq!(&buffer.indices[translucent], &mesh.translucent_indices[..]);
+                used_vertices += slot.vertices.len() as u32;
+                used_indices += (slot.indices.len() + slot.translucent_indices.len()) as u32;
+            }
+            //区间互不重叠，空闲加已用等于容量
+            let mut ranges: Vec<Range<u32>> = buffer
+                .slots()
+                .values()
+                .map(|s| s.vertices.clone())
+                .collect();
+            ranges.sort_by_key(|range| range.start);
+            assert!(ranges.windows(2).all(|w| w[0].end <= w[1].start));
+            assert_eq!(
+                used_vertices + buffer.vertex_alloc.free_len(),
+                buffer.vertex_alloc.capacity()
+            );
+            assert_eq!(
+                used_indices + buffer.index_alloc.free_len(),
+                buffer.index_alloc.capacity()
+            );
+            assert_eq!(buffer.vertices.len() as u32, buffer.vertex_alloc.capacity());
+        }
+        assert!(buffer.vertex_alloc.capacity() > INITIAL_VERTICES);
+
+        buffer.clear();
+        assert_eq!(
+            buffer.vertex_alloc.free_len(),
+            buffer.vertex_alloc.capacity()
+        );
+    }
+}
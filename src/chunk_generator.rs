@@ -1,21 +1,68 @@
-use crossbeam_channel::{bounded, Receiver, Sender};
-use std::collections::HashSet;
-use std::sync::{Arc, Mutex};
-use std::thread;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
+use crate::cave::CaveCarver;
 use crate::realm::{
-    Block, BlockType, Chunk, ChunkCoord, ChunkData, Instance, RealmData, BLOCK_EMPTY,
-    BLOCK_NUM_PER_CHUNK, CHUNK_HEIGHT, CHUNK_SIZE,
+    Block, BlockType, Chunk, ChunkCoord, ChunkData, BLOCK_EMPTY, CHUNK_HEIGHT, CHUNK_SIZE,
 };
+use crate::terrain::{FlatLayer, GeneratorPreset, TerrainGenerator};
+use crate::{chunk_mesher, feature, ore, region};
 
-use noise::{NoiseFn, Perlin};
+//自动选择时最多使用的工作线程数
+const MAX_AUTO_WORKERS: usize = 8;
 
-#[allow(unused)]
 pub struct ChunkGenerator {
-    request_sender: Sender<ChunkRequest>,
+    queue: Arc<(Mutex<RequestQueue>, Condvar)>,
     response_receiver: Receiver<ChunkResponse>,
-    pending_chunks: Arc<Mutex<HashSet<ChunkCoord>>>,
-    num_threads: usize,
+    workers: Vec<JoinHandle<()>>,
+    //下标为工作线程编号
+    stats: Arc<Mutex<Vec<WorkerStats>>>,
+}
+
+//每个工作线程处理区块的耗时统计，包括读取或生成以及生成网格
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorkerStats {
+    //从存档读取的区块数
+    pub loaded: usize,
+    pub generated: usize,
+    pub total_time: Duration,
+    pub max_time: Duration,
+}
+
+impl WorkerStats {
+    fn record(&mut self, is_loaded: bool, time: Duration) {
+        if is_loaded {
+            self.loaded += 1;
+        } else {
+            self.generated += 1;
+        }
+        self.total_time += time;
+        self.max_time = self.max_time.max(time);
+    }
+
+    pub fn count(&self) -> usize {
+        self.loaded + self.generated
+    }
+
+    pub fn average_time(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => self.total_time / count as u32,
+        }
+    }
+}
+
+//留一个核心给主线程，设置中的worker_threads不为0时使用设置的值
+pub fn worker_count(override_count: usize) -> usize {
+    if override_count > 0 {
+        return override_count;
+    }
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    cores.saturating_sub(1).clamp(1, MAX_AUTO_WORKERS)
 }
 
 pub struct ChunkRequest {
@@ -28,213 +75,509 @@ pub struct ChunkResponse {
     pub chunk: Chunk,
 }
 
+/*
+ * 等待加载的区块，工作线程每次取离中心最近的一个
+ * 玩家移动后更新中心，离开范围的请求可以直接取消
+ */
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+    //区块坐标 -> 种子
+    requests: HashMap<ChunkCoord, u32>,
+    //工作线程正在生成的区块
+    in_progress: HashSet<ChunkCoord>,
+    center: ChunkCoord,
+    //生成器销毁后工作线程退出
+    is_closed: bool,
+}
+
+impl RequestQueue {
+    //已经在队列中或正在生成时返回false
+    pub fn push(&mut self, coord: ChunkCoord, seed: u32) -> bool {
+        if self.contains(&coord) {
+            return false;
+        }
+        self.requests.insert(coord, seed);
+        true
+    }
+
+    pub fn pop_nearest(&mut self) -> Option<ChunkRequest> {
+        let center = self.center;
+        let coord = *self
+            .requests
+            .keys()
+            .min_by_key(|coord| (coord.distance_squared(&center), coord.x, coord.z))?;
+        let seed = self.requests.remove(&coord).unwrap();
+        self.in_progress.insert(coord);
+        Some(ChunkRequest { coord, seed })
+    }
+
+    pub fn finish(&mut self, coord: &ChunkCoord) {
+        self.in_progress.remove(coord);
+    }
+
+    pub fn contains(&self, coord: &ChunkCoord) -> bool {
+        self.requests.contains_key(coord) || self.in_progress.contains(coord)
+    }
+
+    //取消还没开始生成、离center超过radius的请求，返回取消的数量
+    pub fn cancel_outside(&mut self, center: ChunkCoord, radius: i32) -> usize {
+        let before = self.requests.len();
+        self.requests
+            .retain(|coord, _| coord.distance_squared(&center) <= radius * radius);
+        before - self.requests.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len() + self.in_progress.len()
+    }
+}
+
 impl ChunkGenerator {
-    pub fn new(num_threads: usize) -> Self {
-        let (req_sender, req_receiver) = bounded(1000);
-        let (resp_sender, resp_receiver) = bounded(1000);
-        let pending_chunks = Arc::new(Mutex::new(HashSet::new()));
+    //存档中有的区块从world_dir读取，没有的用世界的生成器生成
+    pub fn new(num_threads: usize, world_dir: PathBuf, preset: GeneratorPreset) -> Self {
+        //生成结果不限数量，工作线程不会因为主线程处理得慢而阻塞
+        let (resp_sender, resp_receiver) = unbounded();
+        let queue = Arc::new((Mutex::new(RequestQueue::default()), Condvar::new()));
+        let stats = Arc::new(Mutex::new(vec![WorkerStats::default(); num_threads]));
 
         // 创建工作线程
-        for _ in 0..num_threads {
-            let req_receiver: Receiver<ChunkRequest> = req_receiver.clone();
-            let resp_sender = resp_sender.clone();
-            let pending_chunks = Arc::clone(&pending_chunks);
-
-            thread::spawn(move || {
-                while let Ok(request) = req_receiver.recv() {
-                    // 生成区块
-                    //thread::sleep(std::time::Duration::from_millis(2000)); // 模拟生成时间
-                    let mut chunk = Self::generate_terrain_internal(request.coord, request.seed);
-
-                    Self::create_instance(&mut chunk, &request.coord);
-                    // 将生成的区块发送回去
-                    let response = ChunkResponse {
-                        coord: request.coord,
-                        chunk,
-                    };
+        let mut workers = Vec::with_capacity(num_threads);
+        for id in 0..num_threads {
+            let resp_sender: Sender<ChunkResponse> = resp_sender.clone();
+            let queue = Arc::clone(&queue);
+            let stats = Arc::clone(&stats);
+            let world_dir = world_dir.clone();
+            let preset = preset.clone();
 
-                    resp_sender.send(response).unwrap();
+            let worker = thread::Builder::new()
+                .name(format!("chunk worker {}", id))
+                .spawn(move || {
+                    while let Some(request) = Self::wait_request(&queue) {
+                        let start = Instant::now();
+                        let (mut chunk, is_loaded) = Self::load_or_generate(
+                            &world_dir,
+                            request.coord,
+                            request.seed,
+                            &preset,
+                        );
 
-                    // 从待处理集合中移除
-                    pending_chunks.lock().unwrap().remove(&request.coord);
-                }
-            });
+                        //网格也在工作线程中生成，相邻区块先当作空的，
+                        //区块交给主线程后再根据已加载的相邻区块更新侧面
+                        chunk.mesh =
+                            chunk_mesher::mesh_chunk(&chunk, &request.coord, |_, _, _| BLOCK_EMPTY);
+                        stats.lock().unwrap()[id].record(is_loaded, start.elapsed());
+                        // 将生成的区块发送回去
+                        let response = ChunkResponse {
+                            coord: request.coord,
+                            chunk,
+                        };
+                        //先从队列中移除再发送，否则主线程收到区块后马上重新请求时会被当作正在生成而丢掉
+                        queue.0.lock().unwrap().finish(&request.coord);
+                        //主线程已经退出世界
+                        if resp_sender.send(response).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .expect("创建区块工作线程失败");
+            workers.push(worker);
         }
 
         Self {
-            request_sender: req_sender,
+            queue,
             response_receiver: resp_receiver,
-            pending_chunks,
-            num_threads,
+            workers,
+            stats,
         }
     }
 
-    pub fn request_chunk(&self, coord: ChunkCoord, seed: u32) -> bool {
-        // 检查这个区块是否已经在生成中
-        let mut pending = self.pending_chunks.lock().unwrap();
-        if pending.contains(&coord) {
-            return false;
+    //先读取存档，没有保存过或者读取失败时生成，返回的bool表示是否从存档读取
+    fn load_or_generate(
+        world_dir: &Path,
+        coord: ChunkCoord,
+        seed: u32,
+        preset: &GeneratorPreset,
+    ) -> (Chunk, bool) {
+        match Chunk::load(world_dir, &coord) {
+            Ok(Some(data)) => return (Chunk::new(data), true),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("区块({},{})读取失败，重新生成:{:#}", coord.x, coord.z, e);
+                match region::quarantine_chunk(world_dir, &coord) {
+                    Ok(path) => eprintln!("损坏的区块已移到{}", path.display()),
+                    Err(e) => eprintln!("移走损坏的区块失败:{:#}", e),
+                }
+            }
         }
+        (Self::generate_terrain_internal(coord, seed, preset), false)
+    }
 
-        // 添加到待处理集合并发送生成请求
-        pending.insert(coord);
-        let request = ChunkRequest { coord, seed };
-        self.request_sender.send(request).unwrap();
-        true
+    pub fn stats(&self) -> Vec<WorkerStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    //队列为空时等待，生成器销毁后返回None
+    fn wait_request(queue: &(Mutex<RequestQueue>, Condvar)) -> Option<ChunkRequest> {
+        let (lock, condvar) = queue;
+        let mut queue = lock.lock().unwrap();
+        loop {
+            if queue.is_closed {
+                return None;
+            }
+            if let Some(request) = queue.pop_nearest() {
+                return Some(request);
+            }
+            queue = condvar.wait(queue).unwrap();
+        }
     }
 
-    pub fn get_generated_chunks(&self) -> Vec<ChunkResponse> {
-        let mut results = Vec::new();
-        while let Ok(response) = self.response_receiver.try_recv() {
-            results.push(response);
+    //不会阻塞
+    pub fn request_chunk(&self, coord: ChunkCoord, seed: u32) -> bool {
+        let (lock, condvar) = &*self.queue;
+        let pushed = lock.lock().unwrap().push(coord, seed);
+        if pushed {
+            condvar.notify_one();
         }
-        results
+        pushed
+    }
+
+    //玩家进入新的区块后，优先生成离玩家近的区块，并取消半径以外的请求
+    pub fn retarget(&self, center: ChunkCoord, radius: i32) -> usize {
+        let mut queue = self.queue.0.lock().unwrap();
+        queue.center = center;
+        queue.cancel_outside(center, radius)
     }
 
+    //每次最多取max个，剩下的留到下一帧
+    pub fn get_generated_chunks(&self, max: usize) -> Vec<ChunkResponse> {
+        self.response_receiver.try_iter().take(max).collect()
+    }
+
+    #[allow(unused)]
     pub fn is_chunk_pending(&self, coord: &ChunkCoord) -> bool {
-        self.pending_chunks.lock().unwrap().contains(coord)
+        self.queue.0.lock().unwrap().contains(coord)
     }
 
-    fn create_instance(chunk: &mut Chunk, chunk_coord: &ChunkCoord) {
-        let mut index = 0u32;
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_HEIGHT {
+    //排队和正在生成的区块数
+    pub fn pending_count(&self) -> usize {
+        self.queue.0.lock().unwrap().len()
+    }
+
+    //超平坦世界每个区块都相同
+    //层来自level.dat，可能被手动修改过，超出区块高度的部分不生成
+    fn generate_superflat(layers: &[FlatLayer]) -> Chunk {
+        let mut chunk = Chunk::new(ChunkData::new());
+        let blocks = layers
+            .iter()
+            .flat_map(|layer| std::iter::repeat_n(layer.block, layer.thickness as usize));
+        for (y, block) in (0..CHUNK_HEIGHT).zip(blocks) {
+            for x in 0..CHUNK_SIZE {
                 for z in 0..CHUNK_SIZE {
-                    let block = chunk.get_block(x, y, z);
-                    if block.tp != BlockType::Empty {
-                        //let abs_coord = RealmData::relative_to_absolute(chunk_coord, x, y, z);
-                        if chunk.has_any_visible_face(x, y, z) {
-                            chunk.instance[index as usize] = Instance {
-                                position: RealmData::relative_to_absolute_array(
-                                    chunk_coord,
-                                    x,
-                                    y,
-                                    z,
-                                ),
-                                block_type: block.tp as u32,
-                            };
-                            chunk.coord_to_offset[RealmData::relative_to_index(x, y, z)] = index;
-                            chunk.offset_top += 1;
-                            index += 1;
-                        }
-                    }
+                    chunk.set_block(x, y, z, Block::new(block));
                 }
             }
         }
+        chunk
     }
 
-    fn generate_terrain_internal(chunk_coord: ChunkCoord, seed: u32) -> Chunk {
-        let perlin = Perlin::new(seed);
+    pub(crate) fn generate_terrain_internal(
+        chunk_coord: ChunkCoord,
+        seed: u32,
+        preset: &GeneratorPreset,
+    ) -> Chunk {
+        if let GeneratorPreset::Superflat(layers) = preset {
+            return Self::generate_superflat(layers);
+        }
+        let terrain = TerrainGenerator::new(seed, preset);
+        let sea_level = terrain.settings().sea_level;
+        //特征的起点可能在相邻区块，需要周围几列的高度
+        let heights = terrain.chunk_heights(&chunk_coord, feature::FEATURE_RADIUS);
 
-        let blocks = vec![BLOCK_EMPTY; BLOCK_NUM_PER_CHUNK];
-        let mut chunk = Chunk::new(ChunkData { blocks });
-        let mut tree_placed: Vec<Vec<bool>> =
-            vec![vec![false; CHUNK_SIZE as usize]; CHUNK_SIZE as usize];
+        let caves = CaveCarver::new(seed);
+        let mut chunk = Chunk::new(ChunkData::new());
 
         for x in 0..CHUNK_SIZE {
             for z in 0..CHUNK_SIZE {
                 let absolute_x = x + chunk_coord.x * CHUNK_SIZE;
                 let absolute_z = z + chunk_coord.z * CHUNK_SIZE;
 
-                let height = (perlin.get([absolute_x as f64 / 16.0, absolute_z as f64 / 16.0])
-                    * 8.0) as i32
-                    + 24;
-
-                let tree_value = perlin.get([
-                    (absolute_z + CHUNK_SIZE / 2) as f64 / 4.0,
-                    (absolute_x + CHUNK_SIZE / 2) as f64 / 4.0,
-                ]);
-
-                // 树木生成逻辑（与原代码相同）
-                if tree_value > 0.80 {
-                    if x > 2 && z > 2 && x < CHUNK_SIZE - 2 && z < CHUNK_SIZE - 2 {
-                        // ... 现有的树木生成代码 ...
-                        // (从原始的generate_terrian函数复制)
-                        let mut is_place = true;
-                        for i in -2..=2 {
-                            for j in -2..=2 {
-                                if tree_placed[(x + i) as usize][(z + j) as usize] {
-                                    is_place = false;
-                                    break;
-                                }
-                            }
-                        }
-                        if is_place {
-                            let tree_height = ((1.0 - tree_value) * 15.0 + 4.0) as i32;
-                            for y in 0..tree_height {
-                                chunk.set_block(x, height + y, z, Block::new(BlockType::BirchLog));
-                            }
-                            for i in -2..=2 {
-                                for j in -2..=2 {
-                                    tree_placed[(x + i) as usize][(z + j) as usize] = true;
-                                }
-                            }
-
-                            // 生成树叶
-                            for i in -2..=2 {
-                                for j in -2..=2 {
-                                    let y = height + tree_height - 2;
-                                    if i == 0 && j == 0 {
-                                        continue;
-                                    }
-                                    chunk.set_block(
-                                        x + i,
-                                        y,
-                                        z + j,
-                                        Block::new(BlockType::BirchLeaves),
-                                    );
-                                }
-                            }
-                            for i in -2i32..=2 {
-                                for j in -2i32..=2 {
-                                    let y = height + tree_height - 1;
-                                    if i == 0 && j == 0 {
-                                        continue;
-                                    }
-                                    if i.abs() == 2 && j.abs() == 2 {
-                                        continue;
-                                    }
-                                    chunk.set_block(
-                                        x + i,
-                                        y,
-                                        z + j,
-                                        Block::new(BlockType::BirchLeaves),
-                                    );
-                                }
-                            }
-                            for i in -1i32..=1 {
-                                for j in -1i32..=1 {
-                                    let y = height + tree_height;
-                                    if i.abs() == 1 && j.abs() == 1 {
-                                        continue;
-                                    }
-                                    chunk.set_block(
-                                        x + i,
-                                        y,
-                                        z + j,
-                                        Block::new(BlockType::BirchLeaves),
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
+                //地表方块由所在的群系决定，高度在群系边界处混合
+                let biome = terrain.biome_map.biome_at(absolute_x, absolute_z).info();
+                let height = heights.get(absolute_x, absolute_z);
 
-                // 地形生成
+                // 地形生成，水下的地表和下面几层相同，不会有水下的草方块
+                let surface = if height - sea_level > biome.snow_line {
+                    BlockType::Snow
+                } else if height < sea_level {
+                    biome.sub_surface
+                } else {
+                    biome.surface
+                };
+                let carve_range = CaveCarver::carve_range(height);
                 for y in 0..height {
+                    if carve_range.contains(&y) && caves.is_cave(absolute_x, y, absolute_z) {
+                        continue;
+                    }
                     let block = if y == height - 1 {
-                        Block::new(BlockType::Grass)
+                        Block::new(surface)
                     } else if y > height - 5 {
-                        Block::new(BlockType::Dirt)
+                        Block::new(biome.sub_surface)
                     } else {
                         Block::new(BlockType::Stone)
                     };
                     chunk.set_block(x, y, z, block);
                 }
                 chunk.set_block(x, 0, z, Block::new(BlockType::UnderStone));
+                //地面到海平面之间灌满水，洞穴离地面足够远，不会连通
+                for y in height..sea_level {
+                    chunk.set_block(x, y, z, Block::new(BlockType::Water));
+                }
             }
         }
 
+        //矿石只替换石头，在洞穴挖好之后放置，洞壁上可以看到矿石
+        ore::place_ores(&mut chunk, &chunk_coord, seed, terrain.settings().ores);
+
+        //树木等特征在地形之后放置，可以跨越区块边界
+        feature::place_features(&mut chunk, &chunk_coord, seed, &terrain, &heights);
         chunk
     }
 }
+
+//退出世界时通知工作线程退出，并等待正在处理的区块完成
+impl Drop for ChunkGenerator {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.queue;
+        lock.lock().unwrap().is_closed = true;
+        condvar.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                eprintln!("区块工作线程异常退出");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome::Biome;
+    use crate::test_util::test_dir;
+
+    #[test]
+    fn test_request_queue_nearest_first() {
+        let mut queue = RequestQueue::default();
+        for x in -3..=3 {
+            assert!(queue.push(ChunkCoord::new(x, 0), 1));
+        }
+        assert!(!queue.push(ChunkCoord::new(0, 0), 1));
+
+        let first = queue.pop_nearest().unwrap();
+        assert_eq!(first.coord, ChunkCoord::new(0, 0));
+        //正在生成的区块不能重复请求
+        assert!(queue.contains(&first.coord));
+        assert!(!queue.push(first.coord, 1));
+        queue.finish(&first.coord);
+        assert!(!queue.contains(&first.coord));
+
+        //中心移动后优先生成新中心附近的区块，远处的请求被取消
+        queue.center = ChunkCoord::new(3, 0);
+        assert_eq!(queue.cancel_outside(queue.center, 3), 3);
+        let order: Vec<i32> = std::iter::from_fn(|| queue.pop_nearest())
+            .map(|request| request.coord.x)
+            .collect();
+        assert_eq!(order, vec![3, 2, 1]);
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn test_load_or_generate() -> anyhow::Result<()> {
+        let world_dir = test_dir("generator_load");
+        let saved = ChunkCoord::new(0, 0);
+        let corrupted = ChunkCoord::new(1, 0);
+
+        let mut data = ChunkData::new();
+        data.set_block(1, 2, 3, Block::new(BlockType::BirchPlank));
+        region::save_chunk(&world_dir, &saved, &data)?;
+        let mut file = region::RegionFile::open(&region::region_path(&world_dir, &corrupted))?;
+        file.write_chunk(&corrupted, &[2, 0, 0])?;
+        drop(file);
+
+        //存档中的区块直接读取
+        let (chunk, is_loaded) =
+            ChunkGenerator::load_or_generate(&world_dir, saved, 1, &GeneratorPreset::Default);
+        assert!(is_loaded);
+        assert_eq!(chunk.get_block(1, 2, 3).tp, BlockType::BirchPlank);
+        assert_eq!(chunk.get_block(0, 0, 0).tp, BlockType::Empty);
+
+        //损坏的区块移走后重新生成，不会崩溃
+        let (chunk, is_loaded) =
+            ChunkGenerator::load_or_generate(&world_dir, corrupted, 1, &GeneratorPreset::Default);
+        assert!(!is_loaded);
+        assert_eq!(chunk.get_block(0, 0, 0).tp, BlockType::UnderStone);
+        assert_eq!(
+            std::fs::read_dir(world_dir.join(region::CORRUPTED_DIR))?.count(),
+            1
+        );
+        assert!(region::load_chunk(&world_dir, &corrupted)?.is_none());
+
+        //文件头不完整的区域文件整个移走，这个区域的区块之后可以正常读取和保存
+        let truncated = ChunkCoord::new(40, 0);
+        std::fs::write(region::region_path(&world_dir, &truncated), [0u8; 10])?;
+        let (_, is_loaded) =
+            ChunkGenerator::load_or_generate(&world_dir, truncated, 1, &GeneratorPreset::Default);
+        assert!(!is_loaded);
+        assert!(region::load_chunk(&world_dir, &truncated)?.is_none());
+        region::save_chunk(&world_dir, &truncated, &data)?;
+        let (chunk, is_loaded) =
+            ChunkGenerator::load_or_generate(&world_dir, truncated, 1, &GeneratorPreset::Default);
+        assert!(is_loaded);
+        assert_eq!(chunk.get_block(1, 2, 3).tp, BlockType::BirchPlank);
+
+        //保存时遇到打不开的区域文件也会先移走再重新创建
+        let unsaved = ChunkCoord::new(-40, 0);
+        std::fs::write(region::region_path(&world_dir, &unsaved), [0u8; 10])?;
+        region::save_chunk(&world_dir, &unsaved, &data)?;
+        assert!(region::load_chunk(&world_dir, &unsaved)?.is_some());
+        assert_eq!(
+            std::fs::read_dir(world_dir.join(region::CORRUPTED_DIR))?.count(),
+            3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generator_cancel_and_budget() {
+        //没有工作线程，请求只会留在队列中
+        let world_dir = test_dir("generator_budget");
+        let generator = ChunkGenerator::new(0, world_dir.clone(), GeneratorPreset::Default);
+        for x in 0..10 {
+            generator.request_chunk(ChunkCoord::new(x, 0), 1);
+        }
+        assert_eq!(generator.pending_count(), 10);
+        assert_eq!(generator.retarget(ChunkCoord::new(0, 0), 4), 5);
+        assert!(generator.is_chunk_pending(&ChunkCoord::new(4, 0)));
+        assert!(!generator.is_chunk_pending(&ChunkCoord::new(5, 0)));
+        assert!(generator
+            .get_generated_chunks(CHUNK_SIZE as usize)
+            .is_empty());
+
+        //有工作线程时每次最多取出指定数量
+        let generator = ChunkGenerator::new(2, world_dir, GeneratorPreset::Default);
+        for x in 0..3 {
+            generator.request_chunk(ChunkCoord::new(x, 0), 1);
+        }
+        let mut received = 0;
+        for _ in 0..1000 {
+            let chunks = generator.get_generated_chunks(1);
+            assert!(chunks.len() <= 1);
+            received += chunks.len();
+            if received == 3 {
+                break;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(received, 3);
+
+        let stats = generator.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats.iter().map(|s| s.count()).sum::<usize>(), 3);
+        assert!(stats.iter().all(|s| s.max_time >= s.average_time()));
+        //收到的区块已经不在队列中，可以马上重新请求
+        assert_eq!(generator.pending_count(), 0);
+        assert!(generator.request_chunk(ChunkCoord::new(0, 0), 1));
+        //销毁时等待工作线程退出
+        drop(generator);
+    }
+
+    #[test]
+    fn test_biome_surface() {
+        let seed = 42;
+        let biome_map = crate::biome::BiomeMap::new(seed);
+        let center = |coord: &ChunkCoord| (coord.x * CHUNK_SIZE + 8, coord.z * CHUNK_SIZE + 8);
+        let coord = (-64..64)
+            .flat_map(|x| (-64..64).map(move |z| ChunkCoord::new(x, z)))
+            .find(|coord| {
+                let (x, z) = center(coord);
+                biome_map.biome_at(x, z) == Biome::Desert
+            })
+            .unwrap();
+
+        //同一个种子生成的区块完全相同，沙漠的地表是沙子
+        let chunk =
+            ChunkGenerator::generate_terrain_internal(coord, seed, &GeneratorPreset::Default);
+        let again =
+            ChunkGenerator::generate_terrain_internal(coord, seed, &GeneratorPreset::Default);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..80 {
+                    assert_eq!(chunk.get_block(x, y, z), again.get_block(x, y, z));
+                }
+            }
+        }
+        let top = (0..CHUNK_HEIGHT)
+            .rev()
+            .map(|y| chunk.get_block(8, y, 8))
+            .find(|block| block.tp != BlockType::Empty)
+            .unwrap();
+        assert_eq!(top.tp, BlockType::Sand);
+        assert_eq!(chunk.get_block(8, 0, 8).tp, BlockType::UnderStone);
+    }
+
+    #[test]
+    fn test_ocean_water() {
+        let seed = 42;
+        let preset = GeneratorPreset::Default;
+        let terrain = TerrainGenerator::new(seed, &preset);
+        let sea_level = terrain.settings().sea_level;
+        let coord = (-64..64)
+            .flat_map(|x| (-64..64).map(move |z| ChunkCoord::new(x, z)))
+            .find(|coord| {
+                let (x, z) = (coord.x * CHUNK_SIZE + 8, coord.z * CHUNK_SIZE + 8);
+                terrain.biome_map.biome_at(x, z) == Biome::Ocean
+            })
+            .unwrap();
+
+        //海平面以下的空处都是水，海平面以上没有水
+        let chunk = ChunkGenerator::generate_terrain_internal(coord, seed, &preset);
+        let heights = terrain.chunk_heights(&coord, 0);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let height = heights.get(coord.x * CHUNK_SIZE + x, coord.z * CHUNK_SIZE + z);
+                for y in 0..CHUNK_HEIGHT {
+                    let tp = chunk.get_block(x, y, z).tp;
+                    if y >= height && y < sea_level {
+                        assert_eq!(tp, BlockType::Water);
+                    } else if y >= sea_level {
+                        assert_ne!(tp, BlockType::Water);
+                    }
+                }
+            }
+        }
+        assert_eq!(chunk.get_block(8, sea_level - 1, 8).tp, BlockType::Water);
+    }
+
+    #[test]
+    fn test_superflat_too_high() {
+        let layers = vec![
+            FlatLayer {
+                block: BlockType::Stone,
+                thickness: u32::MAX,
+            },
+            FlatLayer {
+                block: BlockType::Grass,
+                thickness: 1,
+            },
+        ];
+        let chunk = ChunkGenerator::generate_superflat(&layers);
+        assert_eq!(chunk.get_block(0, 0, 0).tp, BlockType::Stone);
+        assert_eq!(chunk.get_block(0, CHUNK_HEIGHT - 1, 0).tp, BlockType::Stone);
+    }
+
+    #[test]
+    fn test_worker_count() {
+        assert_eq!(worker_count(3), 3);
+        let auto = worker_count(0);
+        assert!((1..=MAX_AUTO_WORKERS).contains(&auto));
+    }
+}
@@ -0,0 +1,530 @@
+use wgpu::*;
+
+use crate::realm::{Block, BlockType, Chunk, ChunkCoord, BLOCK_EMPTY, CHUNK_HEIGHT, CHUNK_SIZE};
+
+//区块在x、y、z三个方向上的尺寸
+const CHUNK_DIMS: [i32; 3] = [CHUNK_SIZE, CHUNK_HEIGHT, CHUNK_SIZE];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ChunkVertex {
+    pub position: [f32; 3],
+    //合并后的面纹理坐标超过1，采样器重复平铺贴图
+    pub tex_coord: [f32; 2],
+    //纹理数组的层
+    pub layer: u32,
+}
+
+impl ChunkVertex {
+    pub fn desc() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: std::mem::size_of::<ChunkVertex>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &[
+                VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: VertexFormat::Float32x3,
+                },
+                VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as BufferAddress,
+                    shader_location: 1,
+                    format: VertexFormat::Float32x2,
+                },
+                VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as BufferAddress,
+                    shader_location: 2,
+                    format: VertexFormat::Uint32,
+                },
+            ],
+        }
+    }
+}
+
+/*
+ * 不透明和半透明的面共用顶点，索引分开存放
+ * 半透明的面(例如水)在所有不透明的面之后用混合管线绘制
+ */
+#[derive(Debug, Default, Clone)]
+pub struct ChunkMesh {
+    pub vertices: Vec<ChunkVertex>,
+    pub indices: Vec<u32>,
+    pub translucent_indices: Vec<u32>,
+}
+
+impl ChunkMesh {
+    //每个面是一个四边形
+    #[allow(unused)]
+    pub fn face_count(&self) -> usize {
+        (self.indices.len() + self.translucent_indices.len()) / 6
+    }
+
+    #[allow(unused)]
+    pub fn translucent_face_count(&self) -> usize {
+        self.translucent_indices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty() && self.translucent_indices.is_empty()
+    }
+
+    fn append(&mut self, other: &ChunkMesh) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices
+            .extend(other.indices.iter().map(|index| index + base));
+        self.translucent_indices
+            .extend(other.translucent_indices.iter().map(|index| index + base));
+    }
+
+    fn push_quad(&mut self, vertices: [ChunkVertex; 4], translucent: bool) {
+        let base = self.vertices.len() as u32;
+        self.vertices.extend(vertices);
+        let indices = if translucent {
+            &mut self.translucent_indices
+        } else {
+            &mut self.indices
+        };
+        //与realm::INDICES中每个面的顺序相同
+        indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
+
+/*
+ * 区块侧面上朝外的面只取决于相邻区块，单独存放
+ * 相邻区块加载后只需要重新生成这一部分
+ */
+#[derive(Debug, Default, Clone)]
+pub struct ChunkMeshParts {
+    //不受相邻区块影响的面
+    pub interior: ChunkMesh,
+    //顺序与BORDERS相同
+    pub borders: [ChunkMesh; 4],
+}
+
+impl ChunkMeshParts {
+    //合并成一个网格上传到GPU
+    pub fn combined(&self) -> ChunkMesh {
+        let mut mesh = self.interior.clone();
+        for border in &self.borders {
+            mesh.append(border);
+        }
+        mesh
+    }
+
+    #[allow(unused)]
+    pub fn face_count(&self) -> usize {
+        self.interior.face_count()
+            + self
+                .borders
+                .iter()
+                .map(|border| border.face_count())
+                .sum::<usize>()
+    }
+}
+
+//区块的四个侧面：(面的编号, 相邻区块的x偏移, 相邻区块的z偏移)
+pub const BORDERS: [(usize, i32, i32); 4] = [(0, 0, 1), (2, 0, -1), (4, -1, 0), (5, 1, 0)];
+
+//相邻区块中与border相对的侧面
+pub fn opposite_border(border: usize) -> usize {
+    border ^ 1
+}
+
+/*
+ * 每个面的法线方向和面内的两个方向，0为x、1为y、2为z
+ * 按照 正-上-后-下-左-右 的顺序，与realm::VERTICES一致
+ * 纹理坐标的u沿u_axis变化，v沿v_axis变化
+ */
+struct FaceAxes {
+    normal: usize,
+    //法线指向坐标轴的正方向为1，否则为-1
+    dir: i32,
+    u_axis: usize,
+    v_axis: usize,
+}
+
+const FACES: [FaceAxes; 6] = [
+    FaceAxes {
+        normal: 2,
+        dir: 1,
+        u_axis: 0,
+        v_axis: 1,
+    },
+    FaceAxes {
+        normal: 1,
+        dir: 1,
+        u_axis: 0,
+        v_axis: 2,
+    },
+    FaceAxes {
+        normal: 2,
+        dir: -1,
+        u_axis: 0,
+        v_axis: 1,
+    },
+    FaceAxes {
+        normal: 1,
+        dir: -1,
+        u_axis: 0,
+        v_axis: 2,
+    },
+    FaceAxes {
+        normal: 0,
+        dir: -1,
+        u_axis: 2,
+        v_axis: 1,
+    },
+    FaceAxes {
+        normal: 0,
+        dir: 1,
+        u_axis: 2,
+        v_axis: 1,
+    },
+];
+
+/*
+ * 只为露出的面生成顶点，同一平面上材质相同的相邻面合并成一个大的四边形
+ * neighbour用于读取区块水平方向以外的方块，参数为相对本区块的坐标
+ * 区块上下以外视为空方块
+ */
+pub fn mesh_chunk(
+    chunk: &Chunk,
+    chunk_coord: &ChunkCoord,
+    neighbour: impl Fn(i32, i32, i32) -> Block,
+) -> ChunkMeshParts {
+    let mut parts = ChunkMeshParts::default();
+    for (face, axes) in FACES.iter().enumerate() {
+        let border = BORDERS.iter().position(|(f, _, _)| *f == face);
+        for d in 0..CHUNK_DIMS[axes.normal] {
+            //法线为y时整个平面都在同一个分段中
+            if axes.normal == 1 && chunk.is_section_empty(d) {
+                continue;
+            }
+            let mesh = match border {
+                Some(border) if d == border_slice(face) => &mut parts.borders[border],
+                _ => &mut parts.interior,
+            };
+            mesh_slice(chunk, chunk_coord, face, d, &neighbour, mesh);
+        }
+    }
+    parts
+}
+
+//只生成一个侧面上朝外的面
+pub fn mesh_border(
+    chunk: &Chunk,
+    chunk_coord: &ChunkCoord,
+    border: usize,
+    neighbour: impl Fn(i32, i32, i32) -> Block,
+) -> ChunkMesh {
+    let face = BORDERS[border].0;
+    let mut mesh = ChunkMesh::default();
+    mesh_slice(
+        chunk,
+        chunk_coord,
+        face,
+        border_slice(face),
+        &neighbour,
+        &mut mesh,
+    );
+    mesh
+}
+
+//侧面所在的那一层
+fn border_slice(face: usize) -> i32 {
+    let axes = &FACES[face];
+    if axes.dir > 0 {
+        CHUNK_DIMS[axes.normal] - 1
+    } else {
+        0
+    }
+}
+
+//法线方向上第d层中朝向face的面
+fn mesh_slice(
+    chunk: &Chunk,
+    chunk_coord: &ChunkCoord,
+    face: usize,
+    d: i32,
+    neighbour: &impl Fn(i32, i32, i32) -> Block,
+    mesh: &mut ChunkMesh,
+) {
+    let block_at = |p: [i32; 3]| -> Block {
+        if p[1] < 0 || p[1] >= CHUNK_HEIGHT {
+            BLOCK_EMPTY
+        } else if p[0] < 0 || p[2] < 0 || p[0] >= CHUNK_SIZE || p[2] >= CHUNK_SIZE {
+            neighbour(p[0], p[1], p[2])
+        } else {
+            chunk.get_block(p[0], p[1], p[2])
+        }
+    };
+    let axes = &FACES[face];
+    let width = CHUNK_DIMS[axes.u_axis];
+    let height = CHUNK_DIMS[axes.v_axis];
+
+    //mask中为面的纹理层和是否半透明，None表示没有露出的面
+    let mut mask: Vec<Option<FaceMaterial>> = vec![None; (width * height) as usize];
+    for v in 0..height {
+        for u in 0..width {
+            let mut p = [0; 3];
+            p[axes.normal] = d;
+            p[axes.u_axis] = u;
+            p[axes.v_axis] = v;
+            if chunk.is_section_empty(p[1]) {
+                continue;
+            }
+            let block = chunk.get_block(p[0], p[1], p[2]);
+            p[axes.normal] += axes.dir;
+            mask[(v * width + u) as usize] = face_material(block, block_at(p), face);
+        }
+    }
+
+    let origin = [chunk_coord.x * CHUNK_SIZE, 0, chunk_coord.z * CHUNK_SIZE];
+    merge_faces(&mut mask, width, height, |u, v, w, h, material| {
+        let mut start = origin;
+        start[axes.normal] += d;
+        start[axes.u_axis] += u;
+        start[axes.v_axis] += v;
+        mesh.push_quad(
+            quad_vertices(face, start, w, h, material.layer),
+            material.translucent,
+        );
+    });
+}
+
+//纹理层和是否半透明都相同的面才能合并
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FaceMaterial {
+    layer: u32,
+    translucent: bool,
+}
+
+/*
+ * 相邻方块透明且不是同一种方块时，这个面才露出
+ * 水和石头之间只绘制石头的面，水面下的地形透过水可以看到
+ */
+fn face_material(block: Block, adjacent: Block, face: usize) -> Option<FaceMaterial> {
+    if block.tp == BlockType::Empty || !adjacent.tp.is_transparent() || adjacent.tp == block.tp {
+        return None;
+    }
+    let info = block.tp.info();
+    Some(FaceMaterial {
+        layer: info.tex_offset[face],
+        translucent: info.translucent,
+    })
+}
+
+//贪心合并：先沿u方向尽量延长，再沿v方向延长，每次取出一个矩形
+fn merge_faces(
+    mask: &mut [Option<FaceMaterial>],
+    width: i32,
+    height: i32,
+    mut emit: impl FnMut(i32, i32, i32, i32, FaceMaterial),
+) {
+    for v in 0..height {
+        let mut u = 0;
+        while u < width {
+            let Some(material) = mask[(v * width + u) as usize] else {
+                u += 1;
+                continue;
+            };
+            let mut w = 1;
+            while u + w < width && mask[(v * width + u + w) as usize] == Some(material) {
+                w += 1;
+            }
+            let mut h = 1;
+            while v + h < height
+                && (u..u + w).all(|i| mask[((v + h) * width + i) as usize] == Some(material))
+            {
+                h += 1;
+            }
+            for j in v..v + h {
+                for i in u..u + w {
+                    mask[(j * width + i) as usize] = None;
+                }
+            }
+            emit(u, v, w, h, material);
+            u += w;
+        }
+    }
+}
+
+//以单个方块的面为模板，把面内两个方向拉伸到w和h格
+fn quad_vertices(face: usize, start: [i32; 3], w: i32, h: i32, layer: u32) -> [ChunkVertex; 4] {
+    let axes = &FACES[face];
+    let mut size = [1.0; 3];
+    size[axes.u_axis] = w as f32;
+    size[axes.v_axis] = h as f32;
+    std::array::from_fn(|i| {
+        let vertex = &crate::realm::VERTICES[face * 4 + i];
+        ChunkVertex {
+            position: std::array::from_fn(|a| start[a] as f32 + vertex.position[a] * size[a]),
+            tex_coord: [
+                vertex.tex_coord[0] * w as f32,
+                vertex.tex_coord[1] * h as f32,
+            ],
+            layer,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_generator::ChunkGenerator;
+    use crate::realm::{ChunkData, VERTICES};
+    use crate::terrain::GeneratorPreset;
+
+    fn mesh_alone(chunk: &Chunk) -> ChunkMesh {
+        mesh_chunk(chunk, &ChunkCoord::new(0, 0), |_, _, _| BLOCK_EMPTY).combined()
+    }
+
+    #[test]
+    fn test_face_axes_match_vertices() {
+        for (face, axes) in FACES.iter().enumerate() {
+            let v = &VERTICES[face * 4..face * 4 + 4];
+            let plane = if axes.dir > 0 { 1.0 } else { 0.0 };
+            assert!(v.iter().all(|v| v.position[axes.normal] == plane));
+            //第0到第1个顶点纹理u增加，第1到第2个顶点纹理v增加
+            assert_ne!(v[0].position[axes.u_axis], v[1].position[axes.u_axis]);
+            assert_ne!(v[1].position[axes.v_axis], v[2].position[axes.v_axis]);
+        }
+    }
+
+    #[test]
+    fn test_mesh_single_block_and_slab() {
+        let mut chunk = Chunk::new(ChunkData::new());
+        chunk.set_block(3, 10, 5, Block::new(BlockType::Stone));
+        let mesh = mesh_alone(&chunk);
+        assert_eq!(mesh.face_count(), 6);
+        //单个方块的面与立方体模板相同
+        for (vertex, template) in mesh.vertices.iter().zip(VERTICES) {
+            assert_eq!(vertex.tex_coord, template.tex_coord);
+        }
+
+        //16×16的一层方块只需要6个面
+        let mut chunk = Chunk::new(ChunkData::new());
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_block(x, 1, z, Block::new(BlockType::Stone));
+            }
+        }
+        let mesh = mesh_alone(&chunk);
+        assert_eq!(mesh.face_count(), 6);
+        let top = mesh
+            .vertices
+            .iter()
+            .map(|v| v.tex_coord[0])
+            .fold(0.0, f32::max);
+        assert_eq!(top, CHUNK_SIZE as f32);
+
+        //不同材质的面不合并
+        chunk.set_block(0, 1, 0, Block::new(BlockType::Dirt));
+        assert!(mesh_alone(&chunk).face_count() > 6);
+    }
+
+    #[test]
+    fn test_mesh_water() {
+        //一层石头上面三层水
+        let mut chunk = Chunk::new(ChunkData::new());
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                chunk.set_block(x, 1, z, Block::new(BlockType::Stone));
+                for y in 2..5 {
+                    chunk.set_block(x, y, z, Block::new(BlockType::Water));
+                }
+            }
+        }
+        let mesh = mesh_alone(&chunk);
+        //水的顶面和四个侧面是半透明的，水和水之间、水和石头之间没有水的面
+        assert_eq!(mesh.translucent_face_count(), 5);
+        //石头的六个面都露出，顶面透过水可以看到
+        assert_eq!(mesh.face_count(), 11);
+        let top = |indices: &[u32]| {
+            indices
+                .iter()
+                .map(|&i| mesh.vertices[i as usize].position[1])
+                .fold(0.0, f32::max)
+        };
+        assert_eq!(top(&mesh.translucent_indices), 5.0);
+        assert_eq!(top(&mesh.indices), 2.0);
+    }
+
+    #[test]
+    fn test_mesh_neighbour_hides_faces() {
+        let mut chunk = Chunk::new(ChunkData::new());
+        chunk.set_block(CHUNK_SIZE - 1, 10, 0, Block::new(BlockType::Stone));
+        let stone = |x: i32, _: i32, _: i32| {
+            if x == CHUNK_SIZE {
+                Block::new(BlockType::Stone)
+            } else {
+                BLOCK_EMPTY
+            }
+        };
+        let mesh = mesh_chunk(&chunk, &ChunkCoord::new(0, 0), stone);
+        assert_eq!(mesh.face_count(), 5);
+    }
+
+    #[test]
+    fn test_mesh_border_matches_chunk() {
+        let coord = ChunkCoord::new(3, -2);
+        let chunk =
+            ChunkGenerator::generate_terrain_internal(coord, 2025318, &GeneratorPreset::Default);
+        let stone = |_: i32, _: i32, _: i32| Block::new(BlockType::Stone);
+        let parts = mesh_chunk(&chunk, &coord, stone);
+        for border in 0..BORDERS.len() {
+            let mesh = mesh_border(&chunk, &coord, border, stone);
+            assert_eq!(mesh.vertices, parts.borders[border].vertices);
+            //相邻区块全是石头时侧面全部隐藏
+            assert_eq!(mesh.face_count(), 0);
+            let empty = mesh_border(&chunk, &coord, border, |_, _, _| BLOCK_EMPTY);
+            assert!(empty.face_count() > 0);
+            assert_eq!(opposite_border(opposite_border(border)), border);
+        }
+    }
+
+    //每个有可见面的方块一个实例，每个实例绘制全部6个面
+    fn instance_face_count(chunk: &Chunk) -> usize {
+        let mut count = 0;
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_HEIGHT {
+                for z in 0..CHUNK_SIZE {
+                    if chunk.get_block(x, y, z).tp == BlockType::Empty {
+                        continue;
+                    }
+                    let visible = [
+                        (x + 1, y, z),
+                        (x - 1, y, z),
+                        (x, y + 1, z),
+                        (x, y - 1, z),
+                        (x, y, z + 1),
+                        (x, y, z - 1),
+                    ]
+                    .iter()
+                    .any(|&(x, y, z)| chunk.get_block(x, y, z).tp.is_transparent());
+                    if visible {
+                        count += 6;
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_greedy_mesh_face_count() {
+        let coord = ChunkCoord::new(0, 0);
+        let chunk =
+            ChunkGenerator::generate_terrain_internal(coord, 2025318, &GeneratorPreset::Default);
+        let instance_faces = instance_face_count(&chunk);
+        let greedy_faces = mesh_alone(&chunk).face_count();
+        println!(
+            "实例方式绘制{}个面，合并后{}个面",
+            instance_faces, greedy_faces
+        );
+        assert!(greedy_faces > 0);
+        //平坦的地形至少减少到四分之一
+        assert!(greedy_faces * 4 < instance_faces);
+    }
+}
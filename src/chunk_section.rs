@@ -0,0 +1,263 @@
+use serde::{Deserialize, Serialize};
+
+use crate::realm::{Block, BlockType, BLOCK_EMPTY, CHUNK_HEIGHT, CHUNK_SIZE};
+
+//每个区块竖直方向按16格分段
+pub const SECTION_HEIGHT: i32 = 16;
+pub const SECTION_NUM: usize = (CHUNK_HEIGHT / SECTION_HEIGHT) as usize;
+pub const BLOCK_NUM_PER_SECTION: usize = (CHUNK_SIZE * CHUNK_SIZE * SECTION_HEIGHT) as usize;
+//bincode编码后一个分段最多的字节数：Vec长度各8字节，调色板最多2^16项，下标最多16位
+pub const MAX_ENCODED_SECTION_BYTES: usize =
+    8 + (1 << 16) * size_of::<Block>() + 1 + 8 + data_len(16) * size_of::<u64>() + 2;
+
+/*
+ * 调色板压缩的区块分段
+ * palette保存分段内出现过的方块，data中按bits_per_block位保存每个方块在palette中的下标
+ * 一个u64中只保存完整的下标，不跨越两个u64
+ * bits_per_block为0时分段内只有palette[0]一种方块，data为空
+ * 天空中全空的分段就是 palette = [Empty], bits_per_block = 0
+ */
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkSection {
+    palette: Vec<Block>,
+    bits_per_block: u8,
+    data: Vec<u64>,
+    //非空方块数量
+    non_empty_count: u16,
+}
+
+impl Default for ChunkSection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for ChunkSection {
+    //同样的方块可能有不同的调色板顺序，因此按方块逐个比较
+    fn eq(&self, other: &Self) -> bool {
+        if self.non_empty_count != other.non_empty_count {
+            return false;
+        }
+        if self.is_empty() {
+            return true;
+        }
+        (0..BLOCK_NUM_PER_SECTION).all(|i| self.get(i) == other.get(i))
+    }
+}
+
+impl ChunkSection {
+    //全空分段
+    pub fn new() -> Self {
+        Self {
+            palette: vec![BLOCK_EMPTY],
+            bits_per_block: 0,
+            data: Vec::new(),
+            non_empty_count: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.non_empty_count == 0
+    }
+
+    //分段内的下标
+    pub fn index(x: i32, y: i32, z: i32) -> usize {
+        ((y * CHUNK_SIZE + z) * CHUNK_SIZE + x) as usize
+    }
+
+    pub fn get(&self, index: usize) -> Block {
+        if self.bits_per_block == 0 {
+            return self.palette[0];
+        }
+        self.palette[self.get_palette_index(index)]
+    }
+
+    pub fn set(&mut self, index: usize, block: Block) {
+        let old = self.get(index);
+        if old == block {
+            return;
+        }
+
+        if old.tp == BlockType::Empty {
+            self.non_empty_count += 1;
+        } else if block.tp == BlockType::Empty {
+            self.non_empty_count -= 1;
+        }
+
+        //最后一个方块被清空时直接回到全空状态，释放数据
+        if self.non_empty_count == 0 {
+            *self = Self::new();
+            return;
+        }
+
+        let palette_index = match self.palette.iter().position(|b| *b == block) {
+            Some(i) => i,
+            None => {
+                self.palette.push(block);
+                let needed_bits = bits_for_len(self.palette.len());
+                if needed_bits > self.bits_per_block {
+                    self.resize_bits(needed_bits);
+                }
+                self.palette.len() - 1
+            }
+        };
+        self.set_palette_index(index, palette_index);
+    }
+
+    fn get_palette_index(&self, index: usize) -> usize {
+        let bits = self.bits_per_block as usize;
+        let per_long = 64 / bits;
+        let long = self.data[index / per_long];
+        let shift = (index % per_long) * bits;
+        ((long >> shift) & ((1u64 << bits) - 1)) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let bits = self.bits_per_block as usize;
+        let per_long = 64 / bits;
+        let shift = (index % per_long) * bits;
+        let mask = ((1u64 << bits) - 1) << shift;
+        let long = &mut self.data[index / per_long];
+        *long = (*long & !mask) | ((palette_index as u64) << shift);
+    }
+
+    //调色板变大后重新打包所有下标
+    fn resize_bits(&mut self, new_bits: u8) {
+        let old = self.clone();
+        self.bits_per_block = new_bits;
+        self.data = vec![0; data_len(new_bits)];
+        if old.bits_per_block == 0 {
+            //原本只有palette[0]一种方块，下标全为0
+            return;
+        }
+        for i in 0..BLOCK_NUM_PER_SECTION {
+            let palette_index = old.get_palette_index(i);
+            self.set_palette_index(i, palette_index);
+        }
+    }
+
+    //从文件读取后检查数据，避免损坏的数据在访问时越界
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.palette.is_empty() {
+            anyhow::bail!("区块分段损坏：调色板为空");
+        }
+        if self.bits_per_block > 16 || bits_for_len(self.palette.len()) > self.bits_per_block {
+            anyhow::bail!("区块分段损坏：下标位数不匹配");
+        }
+        if self.data.len() != data_len(self.bits_per_block) {
+            anyhow::bail!("区块分段损坏：数据长度不匹配");
+        }
+        let mut non_empty_count = 0;
+        for i in 0..BLOCK_NUM_PER_SECTION {
+            let palette_index = if self.bits_per_block == 0 {
+                0
+            } else {
+                self.get_palette_index(i)
+            };
+            match self.palette.get(palette_index) {
+                Some(block) if block.tp != BlockType::Empty => non_empty_count += 1,
+                Some(_) => {}
+                None => anyhow::bail!("区块分段损坏：调色板下标越界"),
+            }
+        }
+        if non_empty_count != self.non_empty_count as usize {
+            anyhow::bail!("区块分段损坏：非空方块数量不匹配");
+        }
+        Ok(())
+    }
+}
+
+//保存len种方块需要的位数，只有一种方块时不需要数据
+fn bits_for_len(len: usize) -> u8 {
+    if len <= 1 {
+        0
+    } else {
+        (usize::BITS - (len - 1).leading_zeros()) as u8
+    }
+}
+
+const fn data_len(bits: u8) -> usize {
+    if bits == 0 {
+        return 0;
+    }
+    let per_long = 64 / bits as usize;
+    BLOCK_NUM_PER_SECTION.div_ceil(per_long)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_section_get_set() {
+        let mut section = ChunkSection::new();
+        assert!(section.is_empty());
+        assert!(section.data.is_empty());
+
+        let types = [
+            BlockType::Stone,
+            BlockType::Dirt,
+            BlockType::Grass,
+            BlockType::BirchLog,
+            BlockType::BirchLeaves,
+        ];
+        for i in 0..BLOCK_NUM_PER_SECTION {
+            if i % 3 != 0 {
+                section.set(i, Block::new(types[i % types.len()]));
+            }
+        }
+        assert_eq!(section.bits_per_block, 3);
+        for i in 0..BLOCK_NUM_PER_SECTION {
+            let expected = if i % 3 != 0 {
+                Block::new(types[i % types.len()])
+            } else {
+                BLOCK_EMPTY
+            };
+            assert_eq!(section.get(i), expected);
+        }
+        section.validate().unwrap();
+
+        //全部清空后回到全空状态
+        for i in 0..BLOCK_NUM_PER_SECTION {
+            section.set(i, BLOCK_EMPTY);
+        }
+        assert!(section.is_empty());
+        assert_eq!(section, ChunkSection::new());
+        assert!(section.data.is_empty());
+    }
+
+    #[test]
+    fn test_section_single_block_type() {
+        let mut section = ChunkSection::new();
+        section.set(
+            ChunkSection::index(15, 15, 15),
+            Block::new(BlockType::Stone),
+        );
+        assert_eq!(section.bits_per_block, 1);
+        assert_eq!(section.data.len(), BLOCK_NUM_PER_SECTION / 64);
+        assert_eq!(
+            section.get(ChunkSection::index(15, 15, 15)).tp,
+            BlockType::Stone
+        );
+        assert_eq!(section.get(0), BLOCK_EMPTY);
+    }
+
+    #[test]
+    fn test_bits_for_len() {
+        assert_eq!(bits_for_len(1), 0);
+        assert_eq!(bits_for_len(2), 1);
+        assert_eq!(bits_for_len(3), 2);
+        assert_eq!(bits_for_len(4), 2);
+        assert_eq!(bits_for_len(5), 3);
+        assert_eq!(bits_for_len(256), 8);
+        assert_eq!(bits_for_len(257), 9);
+    }
+
+    #[test]
+    fn test_validate_corrupted_section() {
+        let mut section = ChunkSection::new();
+        section.set(0, Block::new(BlockType::Stone));
+        section.palette.truncate(1);
+        assert!(section.validate().is_err());
+    }
+}
@@ -1,8 +1,15 @@
+use std::path::Path;
+
+use crate::camera;
 use crate::item::{Item, ItemType};
+use crate::level::{self, PlayerState};
+use crate::physics::{self, PlayerPhysics};
 use crate::realm;
 use crate::realm::{BlockInfo, BlockType};
 use crate::texture;
 use crate::ui::inventory_renderer;
+use anyhow::Context;
+use cgmath::*;
 use wgpu::util::DeviceExt;
 use wgpu::*;
 
@@ -18,11 +25,37 @@ const ARM_HEIGHT: f32 = 0.7;
 const ARM_SIZE: f32 = 0.25;
 const ARM_OFFSET: f32 = 0.1;
 
+//模型的部位，着色器按部位旋转
+const PART_HEAD: u32 = 0;
+const PART_BODY: u32 = 1;
+const PART_RIGHT_ARM: u32 = 2;
+const PART_LEFT_ARM: u32 = 3;
+const PART_RIGHT_LEG: u32 = 4;
+const PART_LEFT_LEG: u32 = 5;
+
+//行走时手臂和腿摆动的最大弧度
+const MAX_LIMB_SWING: f32 = 0.8;
+//每走一格摆动相位增加的弧度
+const WALK_PHASE_PER_BLOCK: f32 = 2.0;
+//达到这个水平速度时摆动幅度最大
+const WALK_FULL_SPEED: f32 = 4.0;
+//开始和停止行走时摆动幅度的过渡速度
+const WALK_BLEND_RATE: f32 = 10.0;
+
+//皮肤文件名，放在世界目录或玩家档案目录中
+pub const SKIN_FILE_NAME: &str = "skin.png";
+//玩家档案目录，其中的皮肤在所有世界中使用
+pub const PROFILE_DIR: &str = "profile";
+const DEFAULT_SKIN: &[u8] = include_bytes!("../res/texture/skin.png");
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct EntityVertex {
     pub position: [f32; 3],
     pub tex_coord: [f32; 2],
+    //部位旋转时围绕的关节位置
+    pub pivot: [f32; 3],
+    pub part: u32,
 }
 
 impl EntityVertex {
@@ -41,6 +74,16 @@ impl EntityVertex {
                     offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
                 },
+                VertexAttribute {
+                    format: VertexFormat::Float32x3,
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Uint32,
+                    offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
             ],
         }
     }
@@ -50,6 +93,12 @@ impl EntityVertex {
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 struct EntityInstance {
     pub position: [f32; 3],
+    //绕y轴旋转的弧度，0时模型正面朝向z轴正方向
+    pub rotation: f32,
+    //抬头为正
+    pub head_pitch: f32,
+    //右臂和左腿向前摆为正，左臂和右腿相反
+    pub limb_swing: f32,
 }
 
 impl EntityInstance {
@@ -57,17 +106,45 @@ impl EntityInstance {
         VertexBufferLayout {
             array_stride: std::mem::size_of::<EntityInstance>() as wgpu::BufferAddress,
             step_mode: VertexStepMode::Instance,
-            attributes: &[VertexAttribute {
-                format: VertexFormat::Float32x3,
-                offset: 0,
-                shader_location: 2,
-            }],
+            attributes: &[
+                VertexAttribute {
+                    format: VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 5,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32,
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                },
+            ],
         }
     }
 }
 
 #[allow(unused)]
 pub struct Player {
+    //脚底中心的位置
+    pub position: Point3<f32>,
+    //朝向，和摄像机分开，第三人称时摄像机不在玩家眼睛处
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    //速度和是否在地面上
+    pub physics: PlayerPhysics,
+    //行走动画，根据两帧之间的水平移动距离计算
+    walk_phase: f32,
+    walk_amount: f32,
+    last_position: Point3<f32>,
     pub slected_hotbar: i32,
     pub hotbar: Vec<Item>,
     pub all_item_inventory: Vec<Vec<Item>>,
@@ -78,6 +155,7 @@ pub struct Player {
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     texture_bind_group: BindGroup,
+    texture_bind_group_layout: BindGroupLayout,
     render_pipeline: RenderPipeline,
 }
 
@@ -89,28 +167,22 @@ impl Player {
         camera_bind_group_layout: &BindGroupLayout,
         format: TextureFormat,
     ) -> Self {
+        let block_item =
+            |tp: BlockType| Item::new(ItemType::Block(all_block[tp.0 as usize].clone()));
         let mut hotbar = vec![Item::new(ItemType::Empty); 10];
-        hotbar[0] = Item::new(ItemType::Block(all_block[BlockType::Grass as usize]));
-        hotbar[1] = Item::new(ItemType::Block(all_block[BlockType::Dirt as usize]));
-        hotbar[2] = Item::new(ItemType::Block(all_block[BlockType::UnderStone as usize]));
-        hotbar[3] = Item::new(ItemType::Block(all_block[BlockType::BirchLog as usize]));
+        hotbar[0] = block_item(BlockType::Grass);
+        hotbar[1] = block_item(BlockType::Dirt);
+        hotbar[2] = block_item(BlockType::UnderStone);
+        hotbar[3] = block_item(BlockType::BirchLog);
+
+        //物品栏按定义文件的顺序列出所有方块
         let mut all_item_inventory = vec![vec![Item::new(ItemType::Empty); 10]; 4];
+        let slots = all_item_inventory.iter_mut().flatten();
+        for (slot, info) in slots.zip(all_block.iter().skip(1)) {
+            *slot = Item::new(ItemType::Block(info.clone()));
+        }
 
-        all_item_inventory[0][0] =
-            Item::new(ItemType::Block(all_block[BlockType::UnderStone as usize]));
-        all_item_inventory[0][1] =
-            Item::new(ItemType::Block(all_block[BlockType::BirchLog as usize]));
-        all_item_inventory[0][2] =
-            Item::new(ItemType::Block(all_block[BlockType::BirchLeaves as usize]));
-        all_item_inventory[0][3] = Item::new(ItemType::Block(all_block[BlockType::Grass as usize]));
-        all_item_inventory[0][4] = Item::new(ItemType::Block(all_block[BlockType::Dirt as usize]));
-        all_item_inventory[0][5] = Item::new(ItemType::Block(all_block[BlockType::Stone as usize]));
-        all_item_inventory[0][6] =
-            Item::new(ItemType::Block(all_block[BlockType::BirchPlank as usize]));
-        all_item_inventory[0][7] =
-            Item::new(ItemType::Block(all_block[BlockType::TestBlock as usize]));
-
-        let vertices = Self::create_vertices();
+        let vertices = Self::create_vertices(SkinLayout::Modern);
         let indices = Self::create_indices();
         let vertex_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -123,9 +195,14 @@ impl Player {
             usage: BufferUsages::INDEX,
         });
 
+        let position = Point3::from(level::DEFAULT_SPAWN);
+        let yaw = Rad::from(Deg(90.0));
         let instances = vec![
             EntityInstance {
-                position: [1.0, 70.0, 1.0],
+                position: position.into(),
+                rotation: model_rotation(yaw),
+                head_pitch: 0.0,
+                limb_swing: 0.0,
             };
             1
         ];
@@ -136,10 +213,6 @@ impl Player {
             usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
         });
 
-        let skin_bytes = include_bytes!("../res/texture/skin.png");
-        let skin_texure =
-            texture::Texture::from_bytes(device, queue, skin_bytes, "skin texture").unwrap();
-
         let texture_bind_group_layout =
             device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: Some("Texture bind group layout"),
@@ -163,20 +236,9 @@ impl Player {
                 ],
             });
 
-        let texture_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Texture bind group"),
-            layout: &texture_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&skin_texure.view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&skin_texure.sampler),
-                },
-            ],
-        });
+        let (texture_bind_group, _) =
+            create_skin_bind_group(device, queue, &texture_bind_group_layout, DEFAULT_SKIN)
+                .unwrap();
 
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("Render pipeline layout"),
@@ -234,6 +296,13 @@ impl Player {
         });
 
         Self {
+            position,
+            yaw,
+            pitch: Rad::from(Deg(-45.0)),
+            physics: PlayerPhysics::default(),
+            walk_phase: 0.0,
+            walk_amount: 0.0,
+            last_position: position,
             slected_hotbar: 0,
             hotbar,
             all_item_inventory,
@@ -245,6 +314,7 @@ impl Player {
             index_buffer,
             render_pipeline,
             texture_bind_group,
+            texture_bind_group_layout,
         }
     }
 
@@ -254,100 +324,199 @@ impl Player {
         self.slected_hotbar %= inventory_renderer::SLOTS_PER_ROW as i32;
     }
 
-    //按 头 -> 身体 -> 手臂 -> 腿 的顺序
-    fn create_vertices() -> Vec<EntityVertex> {
-        let mut vertices: Vec<EntityVertex> = Vec::new();
+    pub fn eye_position(&self) -> Point3<f32> {
+        self.position + Vector3::unit_y() * physics::PLAYER_EYE_HEIGHT
+    }
 
-        // 头
-        for i in 0..realm::VERTICES.len() {
-            vertices.push(EntityVertex {
-                position: [
-                    realm::VERTICES[i].position[0] * HALF_HEAD_SIZE * 2.0 - HALF_HEAD_SIZE,
-                    realm::VERTICES[i].position[1] * HEAD_HEIGHT + BODY_HEIGHT + LEG_HEIGHT,
-                    realm::VERTICES[i].position[2] * HALF_HEAD_SIZE * 2.0 - HALF_HEAD_SIZE,
-                ],
-                tex_coord: [
-                    realm::VERTICES[i].tex_coord[0],
-                    realm::VERTICES[i].tex_coord[1],
-                ],
-            });
-        }
+    //视线方向
+    pub fn direction(&self) -> Vector3<f32> {
+        camera::direction_of(self.yaw, self.pitch)
+    }
 
-        // 身体
-        for i in 0..realm::VERTICES.len() {
-            vertices.push(EntityVertex {
-                position: [
-                    realm::VERTICES[i].position[0] * HALF_BODY_LENGTH * 2.0 - HALF_BODY_LENGTH,
-                    realm::VERTICES[i].position[1] * BODY_HEIGHT + LEG_HEIGHT,
-                    realm::VERTICES[i].position[2] * HALF_BODY_WIDTH * 2.0 - HALF_BODY_WIDTH,
-                ],
-                tex_coord: [
-                    realm::VERTICES[i].tex_coord[0],
-                    realm::VERTICES[i].tex_coord[1],
-                ],
-            });
-        }
+    //更新行走动画，并把位置、朝向和肢体角度写入实例缓冲区
+    pub fn update_instance(&mut self, queue: &Queue, dt: f32) {
+        let moved = self.position - self.last_position;
+        self.last_position = self.position;
+        let speed = if dt > 0.0 {
+            (moved.x * moved.x + moved.z * moved.z).sqrt() / dt
+        } else {
+            0.0
+        };
+        self.update_walk_animation(speed, dt);
 
-        // 手臂
-        for i in 0..realm::VERTICES.len() {
-            vertices.push(EntityVertex {
-                position: [
-                    realm::VERTICES[i].position[0] * ARM_SIZE
-                        - ARM_SIZE / 2.0
-                        - HALF_BODY_LENGTH
-                        - ARM_SIZE / 2.0,
-                    realm::VERTICES[i].position[1] * ARM_HEIGHT + BODY_HEIGHT + ARM_OFFSET,
-                    realm::VERTICES[i].position[2] * ARM_SIZE - ARM_SIZE / 2.0,
-                ],
-                tex_coord: [
-                    realm::VERTICES[i].tex_coord[0],
-                    realm::VERTICES[i].tex_coord[1],
-                ],
-            });
-        }
+        self.instances[0] = EntityInstance {
+            position: self.position.into(),
+            rotation: model_rotation(self.yaw),
+            head_pitch: self.pitch.0,
+            limb_swing: self.limb_swing(),
+        };
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+    }
 
-        for i in 0..realm::VERTICES.len() {
-            vertices.push(EntityVertex {
-                position: [
-                    realm::VERTICES[i].position[0] * ARM_SIZE - ARM_SIZE / 2.0
-                        + HALF_BODY_LENGTH
-                        + ARM_SIZE / 2.0,
-                    realm::VERTICES[i].position[1] * ARM_HEIGHT + BODY_HEIGHT + ARM_OFFSET,
-                    realm::VERTICES[i].position[2] * ARM_SIZE - ARM_SIZE / 2.0,
-                ],
-                tex_coord: [
-                    realm::VERTICES[i].tex_coord[0],
-                    realm::VERTICES[i].tex_coord[1],
-                ],
-            });
+    fn update_walk_animation(&mut self, speed: f32, dt: f32) {
+        let target = (speed / WALK_FULL_SPEED).min(1.0);
+        self.walk_amount += (target - self.walk_amount) * (dt * WALK_BLEND_RATE).min(1.0);
+        self.walk_phase =
+            (self.walk_phase + speed * dt * WALK_PHASE_PER_BLOCK) % std::f32::consts::TAU;
+    }
+
+    fn limb_swing(&self) -> f32 {
+        self.walk_phase.sin() * MAX_LIMB_SWING * self.walk_amount
+    }
+
+    //依次使用世界目录、玩家档案目录中的皮肤，都没有时使用默认皮肤
+    pub fn load_skin(&mut self, device: &Device, queue: &Queue, world_dir: &Path) {
+        for dir in [world_dir, Path::new(PROFILE_DIR)] {
+            let path = dir.join(SKIN_FILE_NAME);
+            if !path.exists() {
+                continue;
+            }
+            let result = std::fs::read(&path)
+                .context("读取皮肤文件失败")
+                .and_then(|bytes| self.set_skin(device, queue, &bytes));
+            match result {
+                Ok(()) => return,
+                Err(e) => log::warn!("皮肤{}无法使用:{:#}", path.display(), e),
+            }
         }
+        self.set_skin(device, queue, DEFAULT_SKIN).unwrap();
+    }
 
-        for i in 0..realm::VERTICES.len() {
-            vertices.push(EntityVertex {
-                position: [
-                    realm::VERTICES[i].position[0] * LEG_SIZE - LEG_SIZE / 2.0 - LEG_SIZE / 2.0,
-                    realm::VERTICES[i].position[1] * LEG_HEIGHT,
-                    realm::VERTICES[i].position[2] * LEG_SIZE - LEG_SIZE / 2.0,
-                ],
-                tex_coord: [
-                    realm::VERTICES[i].tex_coord[0],
-                    realm::VERTICES[i].tex_coord[1],
-                ],
-            });
+    //64×32的旧版皮肤也可以使用，UV随皮肤格式变化
+    pub fn set_skin(&mut self, device: &Device, queue: &Queue, bytes: &[u8]) -> anyhow::Result<()> {
+        let (texture_bind_group, layout) =
+            create_skin_bind_group(device, queue, &self.texture_bind_group_layout, bytes)?;
+        self.texture_bind_group = texture_bind_group;
+        self.vertices = Self::create_vertices(layout);
+        self.vertex_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: BufferUsages::VERTEX,
+        });
+        Ok(())
+    }
+
+    //从世界信息中恢复位置、朝向和物品栏
+    pub fn load_state(&mut self, state: &PlayerState, all_block: &[BlockInfo]) {
+        self.position = Point3::from(state.position);
+        self.yaw = Rad(state.yaw);
+        self.pitch = Rad(state.pitch);
+        self.physics = PlayerPhysics::default();
+        self.last_position = self.position;
+        self.load_hotbar(state, all_block);
+    }
+
+    pub fn store_state(&self, state: &mut PlayerState) {
+        state.position = self.position.into();
+        state.yaw = self.yaw.0;
+        state.pitch = self.pitch.0;
+        self.store_hotbar(state);
+    }
+
+    //超出范围的方块当作空格子
+    fn load_hotbar(&mut self, state: &PlayerState, all_block: &[BlockInfo]) {
+        if state.hotbar.is_empty() {
+            return;
         }
-        for i in 0..realm::VERTICES.len() {
-            vertices.push(EntityVertex {
-                position: [
-                    realm::VERTICES[i].position[0] * LEG_SIZE - LEG_SIZE / 2.0 + LEG_SIZE / 2.0,
-                    realm::VERTICES[i].position[1] * LEG_HEIGHT,
-                    realm::VERTICES[i].position[2] * LEG_SIZE - LEG_SIZE / 2.0,
-                ],
-                tex_coord: [
-                    realm::VERTICES[i].tex_coord[0],
-                    realm::VERTICES[i].tex_coord[1],
-                ],
-            });
+        for (item, tp) in self.hotbar.iter_mut().zip(state.hotbar.iter()) {
+            *item = match all_block.get(tp.0 as usize) {
+                Some(info) if *tp != BlockType::Empty => Item::new(ItemType::Block(info.clone())),
+                _ => Item::new(ItemType::Empty),
+            };
         }
+        self.slected_hotbar = state
+            .selected_hotbar
+            .rem_euclid(inventory_renderer::SLOTS_PER_ROW as i32);
+    }
+
+    fn store_hotbar(&self, state: &mut PlayerState) {
+        state.hotbar.clear();
+        state
+            .hotbar
+            .extend(self.hotbar.iter().map(|item| match &item.item_type {
+                ItemType::Block(info) => info.block_type,
+                ItemType::Empty => BlockType::Empty,
+            }));
+        state.selected_hotbar = self.slected_hotbar;
+    }
+
+    //按 头 -> 身体 -> 右臂 -> 左臂 -> 右腿 -> 左腿 的顺序
+    //模型面朝z轴正方向，模型的右侧在x轴负方向
+    fn create_vertices(layout: SkinLayout) -> Vec<EntityVertex> {
+        let mut vertices: Vec<EntityVertex> = Vec::new();
+        let arm_top = BODY_HEIGHT + ARM_OFFSET + ARM_HEIGHT;
+
+        // 头 绕脖子抬头低头
+        push_box(
+            &mut vertices,
+            [-HALF_HEAD_SIZE, BODY_HEIGHT + LEG_HEIGHT, -HALF_HEAD_SIZE],
+            [HALF_HEAD_SIZE * 2.0, HEAD_HEIGHT, HALF_HEAD_SIZE * 2.0],
+            skin_uv(SkinPart::Head, layout),
+            PART_HEAD,
+            [0.0, BODY_HEIGHT + LEG_HEIGHT, 0.0],
+        );
+
+        // 身体
+        push_box(
+            &mut vertices,
+            [-HALF_BODY_LENGTH, LEG_HEIGHT, -HALF_BODY_WIDTH],
+            [HALF_BODY_LENGTH * 2.0, BODY_HEIGHT, HALF_BODY_WIDTH * 2.0],
+            skin_uv(SkinPart::Body, layout),
+            PART_BODY,
+            [0.0, LEG_HEIGHT, 0.0],
+        );
+
+        // 手臂 绕肩膀摆动
+        push_box(
+            &mut vertices,
+            [
+                -HALF_BODY_LENGTH - ARM_SIZE,
+                BODY_HEIGHT + ARM_OFFSET,
+                -ARM_SIZE / 2.0,
+            ],
+            [ARM_SIZE, ARM_HEIGHT, ARM_SIZE],
+            skin_uv(SkinPart::RightArm, layout),
+            PART_RIGHT_ARM,
+            [
+                -HALF_BODY_LENGTH - ARM_SIZE / 2.0,
+                arm_top - ARM_SIZE / 2.0,
+                0.0,
+            ],
+        );
+        push_box(
+            &mut vertices,
+            [HALF_BODY_LENGTH, BODY_HEIGHT + ARM_OFFSET, -ARM_SIZE / 2.0],
+            [ARM_SIZE, ARM_HEIGHT, ARM_SIZE],
+            skin_uv(SkinPart::LeftArm, layout),
+            PART_LEFT_ARM,
+            [
+                HALF_BODY_LENGTH + ARM_SIZE / 2.0,
+                arm_top - ARM_SIZE / 2.0,
+                0.0,
+            ],
+        );
+
+        // 腿 绕胯部摆动
+        push_box(
+            &mut vertices,
+            [-LEG_SIZE, 0.0, -LEG_SIZE / 2.0],
+            [LEG_SIZE, LEG_HEIGHT, LEG_SIZE],
+            skin_uv(SkinPart::RightLeg, layout),
+            PART_RIGHT_LEG,
+            [-LEG_SIZE / 2.0, LEG_HEIGHT, 0.0],
+        );
+        push_box(
+            &mut vertices,
+            [0.0, 0.0, -LEG_SIZE / 2.0],
+            [LEG_SIZE, LEG_HEIGHT, LEG_SIZE],
+            skin_uv(SkinPart::LeftLeg, layout),
+            PART_LEFT_LEG,
+            [LEG_SIZE / 2.0, LEG_HEIGHT, 0.0],
+        );
         vertices
     }
 
@@ -368,6 +537,7 @@ impl Player {
         indcies
     }
 
+    //第三人称时才绘制
     pub fn draw_entities(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup) {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
@@ -382,3 +552,171 @@ impl Player {
         );
     }
 }
+
+//yaw为0时视线朝向x轴正方向，模型正面朝向z轴正方向
+fn model_rotation(yaw: Rad<f32>) -> f32 {
+    std::f32::consts::FRAC_PI_2 - yaw.0
+}
+
+//皮肤格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SkinLayout {
+    //64×64
+    Modern,
+    //64×32的旧版皮肤，左臂和左腿使用右侧的贴图
+    Legacy,
+}
+
+impl SkinLayout {
+    fn from_size(width: u32, height: u32) -> anyhow::Result<Self> {
+        match (width, height) {
+            (64, 64) => Ok(SkinLayout::Modern),
+            (64, 32) => Ok(SkinLayout::Legacy),
+            _ => anyhow::bail!("皮肤尺寸{}×{}不是64×64或64×32", width, height),
+        }
+    }
+
+    fn height(&self) -> f32 {
+        match self {
+            SkinLayout::Modern => 64.0,
+            SkinLayout::Legacy => 32.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SkinPart {
+    Head,
+    Body,
+    RightArm,
+    LeftArm,
+    RightLeg,
+    LeftLeg,
+}
+
+/*
+ * 部位在皮肤中的 [左, 上, 右, 下]，按 正、上、后、下、左(-x)、右(+x) 的顺序，已除以皮肤尺寸
+ * 标准皮肤中每个部位展开为：上方是顶面和底面，下方依次是右侧面、正面、左侧面、背面
+ * 模型的右侧在x轴负方向，所以-x面使用右侧面
+ */
+fn skin_uv(part: SkinPart, layout: SkinLayout) -> [[f32; 4]; 6] {
+    //贴图左上角，以及部位的宽、高、厚，单位为像素
+    let (u, v, w, h, d) = match (part, layout) {
+        (SkinPart::Head, _) => (0.0, 0.0, 8.0, 8.0, 8.0),
+        (SkinPart::Body, _) => (16.0, 16.0, 8.0, 12.0, 4.0),
+        (SkinPart::RightArm, _) | (SkinPart::LeftArm, SkinLayout::Legacy) => {
+            (40.0, 16.0, 4.0, 12.0, 4.0)
+        }
+        (SkinPart::LeftArm, SkinLayout::Modern) => (32.0, 48.0, 4.0, 12.0, 4.0),
+        (SkinPart::RightLeg, _) | (SkinPart::LeftLeg, SkinLayout::Legacy) => {
+            (0.0, 16.0, 4.0, 12.0, 4.0)
+        }
+        (SkinPart::LeftLeg, SkinLayout::Modern) => (16.0, 48.0, 4.0, 12.0, 4.0),
+    };
+    let rect = |left: f32, top: f32, width: f32, height: f32| {
+        [
+            left / 64.0,
+            top / layout.height(),
+            (left + width) / 64.0,
+            (top + height) / layout.height(),
+        ]
+    };
+    [
+        rect(u + d, v + d, w, h),
+        rect(u + d, v, w, d),
+        rect(u + d + w + d, v + d, w, h),
+        rect(u + d + w, v, w, d),
+        rect(u, v + d, d, h),
+        rect(u + d + w, v + d, d, h),
+    ]
+}
+
+//用方块的顶点生成一个长方体，每个面的纹理坐标映射到皮肤中对应的区域
+fn push_box(
+    vertices: &mut Vec<EntityVertex>,
+    min: [f32; 3],
+    size: [f32; 3],
+    uv: [[f32; 4]; 6],
+    part: u32,
+    pivot: [f32; 3],
+) {
+    for (i, vertex) in realm::VERTICES.iter().enumerate() {
+        let [left, top, right, bottom] = uv[i / 4];
+        vertices.push(EntityVertex {
+            position: [
+                vertex.position[0] * size[0] + min[0],
+                vertex.position[1] * size[1] + min[1],
+                vertex.position[2] * size[2] + min[2],
+            ],
+            tex_coord: [
+                left + vertex.tex_coord[0] * (right - left),
+                top + vertex.tex_coord[1] * (bottom - top),
+            ],
+            pivot,
+            part,
+        });
+    }
+}
+
+fn create_skin_bind_group(
+    device: &Device,
+    queue: &Queue,
+    layout: &BindGroupLayout,
+    bytes: &[u8],
+) -> anyhow::Result<(BindGroup, SkinLayout)> {
+    let img = image::load_from_memory(bytes).context("皮肤图片格式错误")?;
+    let skin_layout = SkinLayout::from_size(img.width(), img.height())?;
+    let skin_texture = texture::Texture::from_image(device, queue, &img, Some("skin texture"))?;
+    let bind_group = device.create_bind_group(&BindGroupDescriptor {
+        label: Some("Texture bind group"),
+        layout,
+        entries: &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&skin_texture.view),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&skin_texture.sampler),
+            },
+        ],
+    });
+    Ok((bind_group, skin_layout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skin_uv() {
+        let vertices = Player::create_vertices(SkinLayout::Modern);
+        assert_eq!(vertices.len(), 6 * realm::VERTICES.len());
+        //头的正面左上角在皮肤的(8, 8)
+        assert_eq!(vertices[0].tex_coord, [8.0 / 64.0, 8.0 / 64.0]);
+        assert_eq!(vertices[2].tex_coord, [16.0 / 64.0, 16.0 / 64.0]);
+        assert!(vertices
+            .iter()
+            .all(|v| v.tex_coord[0] <= 1.0 && v.tex_coord[1] <= 1.0));
+
+        //旧版皮肤的左臂使用右臂的贴图
+        assert_eq!(
+            skin_uv(SkinPart::LeftArm, SkinLayout::Legacy),
+            skin_uv(SkinPart::RightArm, SkinLayout::Legacy)
+        );
+        assert_eq!(
+            skin_uv(SkinPart::LeftArm, SkinLayout::Modern)[0][1],
+            52.0 / 64.0
+        );
+        assert!(SkinLayout::from_size(16, 16).is_err());
+    }
+
+    #[test]
+    fn test_default_skin() {
+        let img = image::load_from_memory(DEFAULT_SKIN).unwrap();
+        assert_eq!(
+            SkinLayout::from_size(img.width(), img.height()).unwrap(),
+            SkinLayout::Modern
+        );
+    }
+}
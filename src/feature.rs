@@ -0,0 +1,480 @@
+use crate::realm::{Block, BlockType, Chunk, ChunkCoord, CHUNK_HEIGHT, CHUNK_SIZE};
+use crate::terrain::{HeightMap, TerrainGenerator};
+
+//特征最多超出起点这么多格，生成区块时要考虑起点在区块周围这个范围内的特征
+pub const FEATURE_RADIUS: i32 = 2;
+
+//世界按这个大小划分格子，每个格子最多一个特征，起点在格子内随机
+const FEATURE_CELL: i32 = 5;
+
+//区分不同用途的随机数
+const FEATURE_SALT: u64 = 0x6665_6174;
+
+/*
+ * 由种子和坐标得到的随机数序列(splitmix64)
+ * 同一个位置总是得到相同的序列，和区块的生成顺序无关
+ */
+pub struct FeatureRng(u64);
+
+impl FeatureRng {
+    pub fn new(seed: u32, x: i32, z: i32, salt: u64) -> Self {
+        let mut rng = Self(
+            (seed as u64) << 32 ^ salt ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+        );
+        rng.0 ^= (z as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    //[0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    //[min, max)
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next_u64() % (max - min) as u64) as i32
+    }
+}
+
+/*
+ * 特征写入方块时使用世界坐标，只有落在这个区块内的方块会被写入
+ * 超出的部分在相邻区块生成时由它自己写入，所以特征可以跨越区块边界
+ */
+pub struct ChunkWriter<'a> {
+    chunk: &'a mut Chunk,
+    origin_x: i32,
+    origin_z: i32,
+}
+
+impl<'a> ChunkWriter<'a> {
+    pub fn new(chunk: &'a mut Chunk, coord: &ChunkCoord) -> Self {
+        Self {
+            chunk,
+            origin_x: coord.x * CHUNK_SIZE,
+            origin_z: coord.z * CHUNK_SIZE,
+        }
+    }
+
+    fn local(&self, x: i32, y: i32, z: i32) -> Option<(i32, i32, i32)> {
+        let (x, z) = (x - self.origin_x, z - self.origin_z);
+        let inside = (0..CHUNK_SIZE).contains(&x)
+            && (0..CHUNK_SIZE).contains(&z)
+            && (0..CHUNK_HEIGHT).contains(&y);
+        inside.then_some((x, y, z))
+    }
+
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, tp: BlockType) {
+        if let Some((x, y, z)) = self.local(x, y, z) {
+            self.chunk.set_block(x, y, z, Block::new(tp));
+        }
+    }
+
+    //只替换指定的方块，例如矿石只出现在石头中
+    pub fn replace(&mut self, x: i32, y: i32, z: i32, from: BlockType, to: BlockType) {
+        if let Some((x, y, z)) = self.local(x, y, z) {
+            if self.chunk.get_block(x, y, z).tp == from {
+                self.chunk.set_block(x, y, z, Block::new(to));
+            }
+        }
+    }
+
+    //只替换空方块，例如树叶不会覆盖其他树的树干
+    pub fn set_if_empty(&mut self, x: i32, y: i32, z: i32, tp: BlockType) {
+        if let Some((x, y, z)) = self.local(x, y, z) {
+            if self.chunk.get_block(x, y, z).tp == BlockType::Empty {
+                self.chunk.set_block(x, y, z, Block::new(tp));
+            }
+        }
+    }
+}
+
+/*
+ * 地形生成后放置的树木、石堆等
+ * 形状只能由传入的rng决定，同一个起点在任何区块中放置的结果都相同
+ */
+pub trait Feature: Sync {
+    //放置的方块最多超出起点的水平距离，不能超过FEATURE_RADIUS
+    fn radius(&self) -> i32;
+
+    //(x, y, z)为起点，y是地面上方第一个空方块
+    fn place(&self, writer: &mut ChunkWriter, rng: &mut FeatureRng, x: i32, y: i32, z: i32);
+}
+
+//一层正方形的树叶，skip_corners为真时去掉四角，center为假时留出树干的位置
+fn leaves_layer(
+    writer: &mut ChunkWriter,
+    (x, y, z): (i32, i32, i32),
+    radius: i32,
+    skip_corners: bool,
+    center: bool,
+    leaves: BlockType,
+) {
+    for i in -radius..=radius {
+        for j in -radius..=radius {
+            if skip_corners && i.abs() == radius && j.abs() == radius {
+                continue;
+            }
+            if !center && i == 0 && j == 0 {
+                continue;
+            }
+            writer.set_if_empty(x + i, y, z + j, leaves);
+        }
+    }
+}
+
+//树冠顶部的十字
+fn leaves_cross(writer: &mut ChunkWriter, (x, y, z): (i32, i32, i32), leaves: BlockType) {
+    for (i, j) in [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+        writer.set_if_empty(x + i, y, z + j, leaves);
+    }
+}
+
+fn trunk(writer: &mut ChunkWriter, (x, y, z): (i32, i32, i32), height: i32, log: BlockType) {
+    for dy in 0..height {
+        writer.set_block(x, y + dy, z, log);
+    }
+}
+
+//橡树：较矮，树冠下面两层宽、上面两层窄，宽层的四角随机缺失
+pub struct OakTree;
+
+impl Feature for OakTree {
+    fn radius(&self) -> i32 {
+        2
+    }
+
+    fn place(&self, writer: &mut ChunkWriter, rng: &mut FeatureRng, x: i32, y: i32, z: i32) {
+        let height = rng.range(4, 7);
+        trunk(writer, (x, y, z), height, BlockType::OakLog);
+        for dy in [height - 3, height - 2] {
+            let skip_corners = rng.next_f64() < 0.5;
+            leaves_layer(
+                writer,
+                (x, y + dy, z),
+                2,
+                skip_corners,
+                false,
+                BlockType::OakLeaves,
+            );
+        }
+        leaves_layer(
+            writer,
+            (x, y + height - 1, z),
+            1,
+            false,
+            false,
+            BlockType::OakLeaves,
+        );
+        leaves_cross(writer, (x, y + height, z), BlockType::OakLeaves);
+    }
+}
+
+//白桦：树干细长，树冠集中在顶部
+pub struct BirchTree;
+
+impl Feature for BirchTree {
+    fn radius(&self) -> i32 {
+        2
+    }
+
+    fn place(&self, writer: &mut ChunkWriter, rng: &mut FeatureRng, x: i32, y: i32, z: i32) {
+        let height = rng.range(5, 8);
+        trunk(writer, (x, y, z), height, BlockType::BirchLog);
+        let skip_corners = rng.next_f64() < 0.5;
+        leaves_layer(
+            writer,
+            (x, y + height - 2, z),
+            2,
+            skip_corners,
+            false,
+            BlockType::BirchLeaves,
+        );
+        leaves_layer(
+            writer,
+            (x, y + height - 1, z),
+            2,
+            true,
+            false,
+            BlockType::BirchLeaves,
+        );
+        leaves_cross(writer, (x, y + height, z), BlockType::BirchLeaves);
+    }
+}
+
+//高白桦：树干更高，树冠多出几层窄的
+pub struct TallBirchTree;
+
+impl Feature for TallBirchTree {
+    fn radius(&self) -> i32 {
+        2
+    }
+
+    fn place(&self, writer: &mut ChunkWriter, rng: &mut FeatureRng, x: i32, y: i32, z: i32) {
+        let height = rng.range(9, 13);
+        trunk(writer, (x, y, z), height, BlockType::BirchLog);
+        let narrow_layers = rng.range(2, 4);
+        for dy in height - 2 - narrow_layers..height - 2 {
+            leaves_layer(
+                writer,
+                (x, y + dy, z),
+                1,
+                false,
+                false,
+                BlockType::BirchLeaves,
+            );
+        }
+        leaves_layer(
+            writer,
+            (x, y + height - 2, z),
+            2,
+            true,
+            false,
+            BlockType::BirchLeaves,
+        );
+        leaves_layer(
+            writer,
+            (x, y + height - 1, z),
+            1,
+            false,
+            false,
+            BlockType::BirchLeaves,
+        );
+        leaves_cross(writer, (x, y + height, z), BlockType::BirchLeaves);
+    }
+}
+
+//灌木：一格原木外面包着一两层橡树叶
+pub struct Bush;
+
+impl Feature for Bush {
+    fn radius(&self) -> i32 {
+        2
+    }
+
+    fn place(&self, writer: &mut ChunkWriter, rng: &mut FeatureRng, x: i32, y: i32, z: i32) {
+        writer.set_block(x, y, z, BlockType::OakLog);
+        let radius = rng.range(1, 3);
+        for dy in 0..radius {
+            //越往上越窄，|i|+|j|不超过这一层的半径
+            let layer = radius - dy;
+            for i in -layer..=layer {
+                for j in -layer..=layer {
+                    if i.abs() + j.abs() <= layer {
+                        writer.set_if_empty(x + i, y + dy, z + j, BlockType::OakLeaves);
+                    }
+                }
+            }
+        }
+        writer.set_if_empty(x, y + radius, z, BlockType::OakLeaves);
+    }
+}
+
+//半径一到两格的石头堆，一半埋在地下
+pub struct Boulder;
+
+impl Feature for Boulder {
+    fn radius(&self) -> i32 {
+        2
+    }
+
+    fn place(&self, writer: &mut ChunkWriter, rng: &mut FeatureRng, x: i32, y: i32, z: i32) {
+        let radius = rng.range(1, 3);
+        for i in -radius..=radius {
+            for j in -radius..=radius {
+                for k in -radius..=radius {
+                    if i * i + j * j + k * k <= radius * radius + radius {
+                        writer.set_block(x + i, y - 1 + j, z + k, BlockType::Stone);
+                    }
+                }
+            }
+        }
+    }
+}
+
+//按群系中各个特征的概率决定一个格子里的特征和起点
+fn cell_feature(
+    rng: &mut FeatureRng,
+    cell_x: i32,
+    cell_z: i32,
+    terrain: &TerrainGenerator,
+) -> Option<(&'static dyn Feature, i32, i32)> {
+    let x = cell_x * FEATURE_CELL + rng.range(0, FEATURE_CELL);
+    let z = cell_z * FEATURE_CELL + rng.range(0, FEATURE_CELL);
+    let biome = terrain.biome_map.biome_at(x, z).info();
+    let mut roll = rng.next_f64();
+    for &(chance, feature) in biome.features {
+        if roll < chance {
+            return Some((feature, x, z));
+        }
+        roll -= chance;
+    }
+    None
+}
+
+/*
+ * 放置所有可能碰到这个区块的特征，heights需要包含区块周围FEATURE_RADIUS以内的列
+ * 每个区块都按相同的格子顺序放置，重叠的特征在任何生成顺序下结果都相同
+ */
+pub fn place_features(
+    chunk: &mut Chunk,
+    coord: &ChunkCoord,
+    seed: u32,
+    terrain: &TerrainGenerator,
+    heights: &HeightMap,
+) {
+    let min_x = coord.x * CHUNK_SIZE - FEATURE_RADIUS;
+    let min_z = coord.z * CHUNK_SIZE - FEATURE_RADIUS;
+    let max_x = (coord.x + 1) * CHUNK_SIZE + FEATURE_RADIUS - 1;
+    let max_z = (coord.z + 1) * CHUNK_SIZE + FEATURE_RADIUS - 1;
+
+    let sea_level = terrain.settings().sea_level;
+    let mut writer = ChunkWriter::new(chunk, coord);
+    for cell_x in min_x.div_euclid(FEATURE_CELL)..=max_x.div_euclid(FEATURE_CELL) {
+        for cell_z in min_z.div_euclid(FEATURE_CELL)..=max_z.div_euclid(FEATURE_CELL) {
+            let mut rng = FeatureRng::new(seed, cell_x, cell_z, FEATURE_SALT);
+            let Some((feature, x, z)) = cell_feature(&mut rng, cell_x, cell_z, terrain) else {
+                continue;
+            };
+            debug_assert!(feature.radius() <= FEATURE_RADIUS);
+            //起点太远的特征碰不到这个区块，水下不生成特征
+            if !heights.contains(x, z) || heights.get(x, z) < sea_level {
+                continue;
+            }
+            feature.place(&mut writer, &mut rng, x, heights.get(x, z), z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::biome::Biome;
+    use crate::chunk_generator::ChunkGenerator;
+    use crate::terrain::GeneratorPreset;
+
+    #[test]
+    fn test_feature_rng() {
+        let mut a = FeatureRng::new(1, -3, 4, FEATURE_SALT);
+        let mut b = FeatureRng::new(1, -3, 4, FEATURE_SALT);
+        let mut c = FeatureRng::new(1, 4, -3, FEATURE_SALT);
+        let sequence: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        assert_eq!(sequence, (0..8).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(sequence, (0..8).map(|_| c.next_u64()).collect::<Vec<_>>());
+        for _ in 0..1000 {
+            assert!((2..5).contains(&a.range(2, 5)));
+            assert!((0.0..1.0).contains(&a.next_f64()));
+        }
+    }
+
+    #[test]
+    fn test_feature_shapes() {
+        let features: [(&dyn Feature, BlockType); 5] = [
+            (&OakTree, BlockType::OakLog),
+            (&BirchTree, BlockType::BirchLog),
+            (&TallBirchTree, BlockType::BirchLog),
+            (&Bush, BlockType::OakLog),
+            (&Boulder, BlockType::Stone),
+        ];
+        for (feature, block) in features {
+            assert!(feature.radius() <= FEATURE_RADIUS);
+            let mut shapes = std::collections::HashSet::new();
+            for i in 0..32 {
+                //放在空区块中间，检查所有方块都在半径以内
+                let mut chunk = Chunk::new(crate::realm::ChunkData::new());
+                let mut writer = ChunkWriter::new(&mut chunk, &ChunkCoord::new(0, 0));
+                let mut rng = FeatureRng::new(7, i, 0, FEATURE_SALT);
+                feature.place(&mut writer, &mut rng, 8, 10, 8);
+                assert_eq!(chunk.get_block(8, 10, 8).tp, block);
+
+                let mut shape = Vec::new();
+                for x in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        for y in 0..32 {
+                            if chunk.get_block(x, y, z).tp != BlockType::Empty {
+                                assert!((x - 8).abs() <= feature.radius());
+                                assert!((z - 8).abs() <= feature.radius());
+                                shape.push((x, y, z));
+                            }
+                        }
+                    }
+                }
+                shapes.insert(shape);
+            }
+            //形状由随机数决定，不是每次都相同
+            assert!(shapes.len() > 1);
+        }
+
+        //每个群系的概率总和不超过1
+        for biome in Biome::ALL {
+            let total: f64 = biome.info().features.iter().map(|(chance, _)| chance).sum();
+            assert!(total <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_trees_cross_chunk_borders() {
+        let seed = 42;
+        let preset = GeneratorPreset::Default;
+        let terrain = TerrainGenerator::new(seed, &preset);
+        let forests = (-64..64)
+            .flat_map(|x| (-64..64).map(move |z| ChunkCoord::new(x, z)))
+            .filter(|coord| {
+                let (x, z) = (coord.x * CHUNK_SIZE, coord.z * CHUNK_SIZE);
+                terrain.biome_map.biome_at(x + 8, z + 8) == Biome::Forest
+            });
+
+        let mut border_trees = 0;
+        for center in forests.take(8) {
+            //周围的区块各自独立生成，拼起来后跨边界的树应该是完整的
+            let mut chunks = std::collections::HashMap::new();
+            for dx in -1..=1 {
+                for dz in -1..=1 {
+                    let coord = ChunkCoord::new(center.x + dx, center.z + dz);
+                    let chunk = ChunkGenerator::generate_terrain_internal(coord, seed, &preset);
+                    chunks.insert(coord, chunk);
+                }
+            }
+            let get = |x: i32, y: i32, z: i32| {
+                let coord = ChunkCoord::new(x.div_euclid(CHUNK_SIZE), z.div_euclid(CHUNK_SIZE));
+                chunks[&coord]
+                    .get_block(x.rem_euclid(CHUNK_SIZE), y, z.rem_euclid(CHUNK_SIZE))
+                    .tp
+            };
+
+            for local_x in 0..CHUNK_SIZE {
+                for local_z in 0..CHUNK_SIZE {
+                    let x = center.x * CHUNK_SIZE + local_x;
+                    let z = center.z * CHUNK_SIZE + local_z;
+                    //树干最上面一格
+                    let is_log = |tp| tp == BlockType::OakLog || tp == BlockType::BirchLog;
+                    let Some(top) = (1..CHUNK_HEIGHT - 1)
+                        .find(|&y| is_log(get(x, y, z)) && !is_log(get(x, y + 1, z)))
+                    else {
+                        continue;
+                    };
+                    let above = get(x, top + 1, z);
+                    assert!(above == BlockType::OakLeaves || above == BlockType::BirchLeaves);
+                    for (i, j) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                        assert_ne!(get(x + i, top, z + j), BlockType::Empty);
+                    }
+                    if !(2..CHUNK_SIZE - 2).contains(&local_x)
+                        || !(2..CHUNK_SIZE - 2).contains(&local_z)
+                    {
+                        border_trees += 1;
+                    }
+                }
+            }
+        }
+        //以前区块边缘两格内不会有树
+        assert!(border_trees > 0);
+    }
+}
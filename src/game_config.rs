@@ -1,8 +1,23 @@
+use std::path::Path;
 use std::time::Duration;
 
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::realm::{INIT_CHUNK_RAD, MAX_CHUNK_RAD, MIN_CHUNK_RAD};
+
+//玩家设置保存在这里，启动时读取，修改后写入
+pub const SETTINGS_FILE: &str = "./settings.ron";
+
+//鼠标灵敏度倍数的范围
+pub const SENSITIVITY_MIN: f32 = 0.1;
+pub const SENSITIVITY_MAX: f32 = 3.0;
+
 pub enum GameState {
     StartMenu,
     Running,
+    //暂停时不更新世界和玩家
+    Paused,
 }
 
 /*
@@ -20,6 +35,36 @@ pub struct GameConfig {
     frame_duration: Duration,
     pub player_speed: f32,
     pub is_debug_window_open: bool,
+    //鼠标灵敏度倍数，在暂停菜单的设置中调整
+    pub mouse_sensitivity_scale: f32,
+    //视距(区块半径)，只能通过set_render_distance修改
+    render_distance: i32,
+    //区块工作线程数，为0时根据CPU核心数自动选择，重新打开世界后生效
+    pub worker_threads: usize,
+}
+
+/*
+ * 需要保存的设置，缺少的字段使用默认值
+ * 旧的设置文件可以直接读取
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub mouse_sensitivity_scale: f32,
+    pub max_fps: u32,
+    pub render_distance: i32,
+    pub worker_threads: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity_scale: 1.0,
+            max_fps: 0,
+            render_distance: INIT_CHUNK_RAD,
+            worker_threads: 0,
+        }
+    }
 }
 
 impl GameConfig {
@@ -42,10 +87,62 @@ impl GameConfig {
             player_speed,
             is_debug_window_open,
             game_state,
+            mouse_sensitivity_scale: 1.0,
+            render_distance: INIT_CHUNK_RAD,
+            worker_threads: 0,
         }
     }
 
-    #[allow(unused)]
+    pub fn settings(&self) -> Settings {
+        Settings {
+            mouse_sensitivity_scale: self.mouse_sensitivity_scale,
+            max_fps: self.max_fps,
+            render_distance: self.render_distance,
+            worker_threads: self.worker_threads,
+        }
+    }
+
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        //设置文件可能被手动修改，超出范围的灵敏度被限制，NaN使用默认值
+        self.mouse_sensitivity_scale = if settings.mouse_sensitivity_scale.is_nan() {
+            1.0
+        } else {
+            settings
+                .mouse_sensitivity_scale
+                .clamp(SENSITIVITY_MIN, SENSITIVITY_MAX)
+        };
+        self.set_max_fps(settings.max_fps);
+        self.set_render_distance(settings.render_distance);
+        self.worker_threads = settings.worker_threads;
+    }
+
+    //文件不存在时保持默认设置
+    pub fn load_settings(&mut self, path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("读取设置文件{}失败", path.display()))?;
+        let settings: Settings =
+            ron::from_str(&text).with_context(|| format!("设置文件{}格式错误", path.display()))?;
+        self.apply_settings(&settings);
+        Ok(())
+    }
+
+    pub fn save_settings(&self, path: &Path) -> anyhow::Result<()> {
+        let text = ron::ser::to_string_pretty(&self.settings(), ron::ser::PrettyConfig::default())
+            .context("设置序列化失败")?;
+        std::fs::write(path, text).with_context(|| format!("写入设置文件{}失败", path.display()))
+    }
+
+    pub fn get_render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    pub fn set_render_distance(&mut self, render_distance: i32) {
+        self.render_distance = render_distance.clamp(MIN_CHUNK_RAD, MAX_CHUNK_RAD);
+    }
+
     pub fn set_max_fps(&mut self, new_max_fps: u32) {
         self.max_fps = new_max_fps;
         if self.max_fps != 0 {
@@ -61,3 +158,55 @@ impl GameConfig {
         self.frame_duration
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+
+    #[test]
+    fn test_settings_file() -> anyhow::Result<()> {
+        let dir = test_dir("settings");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("settings.ron");
+
+        //没有设置文件时使用默认值
+        let mut game_config = GameConfig::new();
+        game_config.load_settings(&path)?;
+        assert_eq!(game_config.settings(), Settings::default());
+
+        game_config.mouse_sensitivity_scale = 1.5;
+        game_config.set_max_fps(144);
+        game_config.worker_threads = 3;
+        game_config.set_render_distance(100);
+        assert_eq!(game_config.get_render_distance(), MAX_CHUNK_RAD);
+        game_config.save_settings(&path)?;
+
+        let mut loaded = GameConfig::new();
+        loaded.load_settings(&path)?;
+        assert_eq!(loaded.settings(), game_config.settings());
+
+        //缺少的字段使用默认值，超出范围的视距被限制
+        std::fs::write(&path, "(render_distance: 0)")?;
+        loaded.load_settings(&path)?;
+        assert_eq!(loaded.get_render_distance(), MIN_CHUNK_RAD);
+        assert_eq!(loaded.get_max_fps(), 0);
+        assert_eq!(loaded.mouse_sensitivity_scale, 1.0);
+
+        //超出范围的灵敏度被限制
+        std::fs::write(&path, "(mouse_sensitivity_scale: 100.0)")?;
+        loaded.load_settings(&path)?;
+        assert_eq!(loaded.mouse_sensitivity_scale, SENSITIVITY_MAX);
+        std::fs::write(&path, "(mouse_sensitivity_scale: -2.0)")?;
+        loaded.load_settings(&path)?;
+        assert_eq!(loaded.mouse_sensitivity_scale, SENSITIVITY_MIN);
+        std::fs::write(&path, "(mouse_sensitivity_scale: NaN)")?;
+        loaded.load_settings(&path)?;
+        assert_eq!(loaded.mouse_sensitivity_scale, 1.0);
+
+        std::fs::write(&path, "(render_distance: \"far\")")?;
+        assert!(loaded.load_settings(&path).is_err());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -9,7 +9,7 @@ pub enum ItemType {
 impl ItemType {
     pub fn get_type(&self) -> u32 {
         match self {
-            Self::Block(tp) => tp.block_type as u32,
+            Self::Block(tp) => tp.block_type.0,
             Self::Empty => 0,
         }
     }
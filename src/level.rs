@@ -0,0 +1,414 @@
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::physics::PLAYER_EYE_HEIGHT;
+use crate::realm::BlockType;
+use crate::terrain::{self, GeneratorPreset};
+
+/*
+ * 每个世界目录下的 level.dat 保存世界的元数据
+ * 使用bincode序列化，读取时检查存档格式版本
+ */
+pub const LEVEL_FILE_NAME: &str = "level.dat";
+
+//存档格式版本
+//1: 每个区块一个文件 2: 区域文件 3: 调色板分段 4: 世界生成器 5: 玩家位置改为脚底
+pub const LEVEL_FORMAT_VERSION: u32 = 5;
+
+//新世界的默认出生点
+pub const DEFAULT_SPAWN: [f32; 3] = [1.0, 70.0, 1.0];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    //脚底位置
+    pub position: [f32; 3],
+    //弧度
+    pub yaw: f32,
+    pub pitch: f32,
+    //物品栏中的方块，Empty表示空格子
+    pub hotbar: Vec<BlockType>,
+    pub selected_hotbar: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelData {
+    pub format_version: u32,
+    pub name: String,
+    pub seed: u32,
+    pub spawn: [f32; 3],
+    //创建时间，unix时间戳(秒)
+    pub created_at: u64,
+    //游戏累计运行时间(秒)
+    pub game_time: f64,
+    pub player: PlayerState,
+    pub generator: GeneratorPreset,
+}
+
+//版本4之前的level.dat没有生成器，读取后使用默认生成器，下次保存时升级为当前版本
+#[derive(Deserialize)]
+struct LegacyLevelData {
+    #[allow(unused)]
+    format_version: u32,
+    name: String,
+    seed: u32,
+    spawn: [f32; 3],
+    created_at: u64,
+    game_time: f64,
+    player: PlayerState,
+}
+
+impl From<LegacyLevelData> for LevelData {
+    fn from(legacy: LegacyLevelData) -> Self {
+        Self {
+            format_version: LEVEL_FORMAT_VERSION,
+            name: legacy.name,
+            seed: legacy.seed,
+            spawn: legacy.spawn,
+            created_at: legacy.created_at,
+            game_time: legacy.game_time,
+            player: legacy.player,
+            generator: GeneratorPreset::Default,
+        }
+    }
+}
+
+impl LevelData {
+    //出生点放在地面上方
+    pub fn new(name: &str, seed: u32, generator: GeneratorPreset) -> Self {
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut spawn = DEFAULT_SPAWN;
+        spawn[1] = terrain::surface_height(
+            seed,
+            &generator,
+            spawn[0].floor() as i32,
+            spawn[2].floor() as i32,
+        ) as f32
+            + 1.0;
+
+        Self {
+            format_version: LEVEL_FORMAT_VERSION,
+            name: name.to_string(),
+            seed,
+            spawn,
+            created_at,
+            game_time: 0.0,
+            player: PlayerState {
+                position: spawn,
+                yaw: 90f32.to_radians(),
+                pitch: (-45f32).to_radians(),
+                //空物品栏，由玩家使用默认物品栏
+                hotbar: Vec::new(),
+                selected_hotbar: 0,
+            },
+            generator,
+        }
+    }
+
+    //世界目录中没有 level.dat 时返回None
+    pub fn load(world_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let path = world_dir.join(LEVEL_FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = std::fs::read(&path).context("读取世界信息失败")?;
+        //版本号是第一个字段，先读出来再按对应版本的格式解析
+        let format_version: u32 = bincode::deserialize(&bytes).context("解析世界信息失败")?;
+        if format_version > LEVEL_FORMAT_VERSION {
+            anyhow::bail!(
+                "世界存档版本{}高于当前支持的版本{}",
+                format_version,
+                LEVEL_FORMAT_VERSION
+            );
+        }
+        let mut level: LevelData = if format_version < 4 {
+            bincode::deserialize::<LegacyLevelData>(&bytes)
+                .context("解析世界信息失败")?
+                .into()
+        } else {
+            bincode::deserialize::<LevelData>(&bytes).context("解析世界信息失败")?
+        };
+        //版本5之前保存的是眼睛位置，换算成脚底位置
+        if format_version < 5 {
+            level.player.position[1] -= PLAYER_EYE_HEIGHT;
+            level.format_version = LEVEL_FORMAT_VERSION;
+        }
+        Ok(Some(level))
+    }
+
+    //读取世界信息，不存在时用给定的种子和默认生成器创建
+    pub fn load_or_create(world_dir: &Path, name: &str, seed: u32) -> anyhow::Result<Self> {
+        match Self::load(world_dir)? {
+            Some(level) => Ok(level),
+            None => {
+                let level = Self::new(name, seed, GeneratorPreset::Default);
+                level.save(world_dir)?;
+                Ok(level)
+            }
+        }
+    }
+
+    //先写入临时文件再替换，避免写到一半时退出导致文件损坏
+    pub fn save(&self, world_dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(world_dir).context("创建世界目录失败")?;
+        let bytes = bincode::serialize(self).context("世界信息序列化失败")?;
+        let tmp_path = world_dir.join(format!("{}.tmp", LEVEL_FILE_NAME));
+        std::fs::write(&tmp_path, bytes).context("写入世界信息失败")?;
+        std::fs::rename(&tmp_path, world_dir.join(LEVEL_FILE_NAME)).context("写入世界信息失败")
+    }
+}
+
+/*
+ * 世界列表管理
+ * 每个世界是 worlds_dir 下的一个目录，目录名就是世界名
+ */
+#[derive(Debug, Clone)]
+pub struct WorldSummary {
+    pub dir_name: String,
+    //level.dat 不存在或损坏时为None
+    pub level: Option<LevelData>,
+}
+
+impl WorldSummary {
+    pub fn display_name(&self) -> &str {
+        match &self.level {
+            Some(level) => &level.name,
+            None => &self.dir_name,
+        }
+    }
+}
+
+//按名称排序
+pub fn list_worlds(worlds_dir: &Path) -> Vec<WorldSummary> {
+    let Ok(entries) = std::fs::read_dir(worlds_dir) else {
+        return Vec::new();
+    };
+
+    let mut worlds: Vec<WorldSummary> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let dir_name = entry.file_name().to_str()?.to_string();
+            let level = LevelData::load(&entry.path()).ok().flatten();
+            Some(WorldSummary { dir_name, level })
+        })
+        .collect();
+    worlds.sort_by(|a, b| a.dir_name.cmp(&b.dir_name));
+    worlds
+}
+
+//世界名直接作为目录名，不能包含路径分隔符等字符
+pub fn validate_world_name(name: &str) -> anyhow::Result<()> {
+    if name.trim().is_empty() {
+        anyhow::bail!("世界名不能为空");
+    }
+    if name != name.trim() || name.starts_with('.') {
+        anyhow::bail!("世界名不能以空格或.开头结尾");
+    }
+    if name
+        .chars()
+        .any(|c| c.is_control() || "/\\:*?\"<>|".contains(c))
+    {
+        anyhow::bail!("世界名不能包含 / \\ : * ? \" < > |");
+    }
+    Ok(())
+}
+
+pub fn create_world(
+    worlds_dir: &Path,
+    name: &str,
+    seed: u32,
+    generator: GeneratorPreset,
+) -> anyhow::Result<PathBuf> {
+    validate_world_name(name)?;
+    let world_dir = worlds_dir.join(name);
+    if world_dir.exists() {
+        anyhow::bail!("世界{}已存在", name);
+    }
+    LevelData::new(name, seed, generator).save(&world_dir)?;
+    Ok(world_dir)
+}
+
+pub fn delete_world(worlds_dir: &Path, dir_name: &str) -> anyhow::Result<()> {
+    validate_world_name(dir_name)?;
+    std::fs::remove_dir_all(worlds_dir.join(dir_name)).context("删除世界失败")
+}
+
+//重命名目录，并更新level.dat中的名称
+pub fn rename_world(worlds_dir: &Path, dir_name: &str, new_name: &str) -> anyhow::Result<()> {
+    validate_world_name(dir_name)?;
+    validate_world_name(new_name)?;
+    let new_dir = worlds_dir.join(new_name);
+    if dir_name != new_name && new_dir.exists() {
+        anyhow::bail!("世界{}已存在", new_name);
+    }
+    std::fs::rename(worlds_dir.join(dir_name), &new_dir).context("重命名世界失败")?;
+
+    if let Some(mut level) = LevelData::load(&new_dir)? {
+        level.name = new_name.to_string();
+        level.save(&new_dir)?;
+    }
+    Ok(())
+}
+
+/*
+ * 把输入的种子文本转换为种子
+ * 空文本随机生成，数字直接使用，其他文本取字符串哈希
+ */
+pub fn parse_seed(text: &str) -> u32 {
+    let text = text.trim();
+    if text.is_empty() {
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0),
+        );
+        return hasher.finish() as u32;
+    }
+    if let Ok(seed) = text.parse::<u32>() {
+        return seed;
+    }
+    if let Ok(seed) = text.parse::<i64>() {
+        return seed as u32;
+    }
+    //和Java的String.hashCode相同，结果不随编译器版本变化
+    text.chars()
+        .fold(0i32, |h, c| h.wrapping_mul(31).wrapping_add(c as i32)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+
+    #[test]
+    fn test_level_round_trip() -> anyhow::Result<()> {
+        let world_dir = test_dir("level_round_trip");
+        assert!(LevelData::load(&world_dir)?.is_none());
+
+        let mut level = LevelData::load_or_create(&world_dir, "测试世界", 42)?;
+        assert_eq!(level.seed, 42);
+        assert_eq!(level.format_version, LEVEL_FORMAT_VERSION);
+
+        level.game_time = 123.5;
+        level.player.position = [-10.5, 80.0, 3.25];
+        level.player.yaw = 1.0;
+        level.player.hotbar = vec![BlockType::Stone, BlockType::Empty, BlockType::BirchLog];
+        level.player.selected_hotbar = 2;
+        level.save(&world_dir)?;
+
+        //已存在时不会用新的种子覆盖
+        let loaded = LevelData::load_or_create(&world_dir, "测试世界", 7)?;
+        assert_eq!(loaded, level);
+        Ok(())
+    }
+
+    #[test]
+    fn test_level_legacy_version() -> anyhow::Result<()> {
+        let world_dir = test_dir("level_legacy_version");
+        std::fs::create_dir_all(&world_dir)?;
+        //版本3的格式：没有生成器字段
+        let level = LevelData::new("旧世界", 5, GeneratorPreset::Default);
+        let mut bytes = bincode::serialize(&(
+            3u32,
+            &level.name,
+            level.seed,
+            level.spawn,
+            level.created_at,
+            level.game_time,
+        ))?;
+        bytes.extend(bincode::serialize(&level.player)?);
+        std::fs::write(world_dir.join(LEVEL_FILE_NAME), bytes)?;
+
+        let loaded = LevelData::load(&world_dir)?.unwrap();
+        assert_eq!(loaded.format_version, LEVEL_FORMAT_VERSION);
+        assert_eq!(loaded.seed, 5);
+        assert_eq!(
+            loaded.player.position[1],
+            level.player.position[1] - PLAYER_EYE_HEIGHT
+        );
+        assert_eq!(loaded.generator, GeneratorPreset::Default);
+        Ok(())
+    }
+
+    #[test]
+    fn test_level_eye_position_version() -> anyhow::Result<()> {
+        let world_dir = test_dir("level_eye_position_version");
+        //版本4保存的是眼睛位置
+        let mut level = LevelData::new("旧世界", 6, GeneratorPreset::Flat);
+        level.format_version = 4;
+        level.save(&world_dir)?;
+
+        let loaded = LevelData::load(&world_dir)?.unwrap();
+        assert_eq!(loaded.format_version, LEVEL_FORMAT_VERSION);
+        assert_eq!(
+            loaded.player.position[1],
+            level.player.position[1] - PLAYER_EYE_HEIGHT
+        );
+        assert_eq!(loaded.generator, GeneratorPreset::Flat);
+
+        //升级后再保存读取，位置不再换算
+        loaded.save(&world_dir)?;
+        assert_eq!(LevelData::load(&world_dir)?.unwrap(), loaded);
+        Ok(())
+    }
+
+    #[test]
+    fn test_level_newer_version() -> anyhow::Result<()> {
+        let world_dir = test_dir("level_newer_version");
+        let mut level = LevelData::new("未来的世界", 1, GeneratorPreset::Flat);
+        level.format_version = LEVEL_FORMAT_VERSION + 1;
+        level.save(&world_dir)?;
+        assert!(LevelData::load(&world_dir).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_world_list() -> anyhow::Result<()> {
+        let worlds_dir = test_dir("world_list");
+        assert!(list_worlds(&worlds_dir).is_empty());
+
+        create_world(&worlds_dir, "b世界", 1, GeneratorPreset::Default)?;
+        create_world(&worlds_dir, "a世界", 2, GeneratorPreset::Amplified)?;
+        assert!(create_world(&worlds_dir, "a世界", 3, GeneratorPreset::Default).is_err());
+        assert!(create_world(&worlds_dir, "../外面", 3, GeneratorPreset::Default).is_err());
+        assert!(create_world(&worlds_dir, " ", 3, GeneratorPreset::Default).is_err());
+
+        let worlds = list_worlds(&worlds_dir);
+        let names: Vec<&str> = worlds.iter().map(|w| w.display_name()).collect();
+        assert_eq!(names, ["a世界", "b世界"]);
+        assert_eq!(worlds[0].level.as_ref().unwrap().seed, 2);
+
+        rename_world(&worlds_dir, "a世界", "c世界")?;
+        assert!(rename_world(&worlds_dir, "b世界", "c世界").is_err());
+        let level = LevelData::load(&worlds_dir.join("c世界"))?.unwrap();
+        assert_eq!(level.name, "c世界");
+        assert_eq!(level.seed, 2);
+        assert_eq!(level.generator, GeneratorPreset::Amplified);
+
+        delete_world(&worlds_dir, "b世界")?;
+        let worlds = list_worlds(&worlds_dir);
+        assert_eq!(worlds.len(), 1);
+        assert_eq!(worlds[0].dir_name, "c世界");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(parse_seed("2025318"), 2025318);
+        assert_eq!(parse_seed(" 42 "), 42);
+        assert_eq!(parse_seed("-1"), u32::MAX);
+        assert_eq!(parse_seed("a"), 97);
+        assert_eq!(parse_seed("hello"), parse_seed("hello"));
+        assert_ne!(parse_seed("hello"), parse_seed("world"));
+    }
+}
@@ -1,7 +1,7 @@
 use glyphon::Resolution;
 use instant::Instant;
 use pollster::FutureExt;
-use std::{iter, sync::Arc};
+use std::{iter, path::Path, sync::Arc};
 use util::DeviceExt;
 use wgpu::*;
 use winit::{
@@ -15,12 +15,26 @@ use winit::{
 
 mod basic_config;
 mod benchmark;
+mod biome;
+mod block_registry;
 mod camera;
+mod cave;
+mod chunk_buffer;
 mod chunk_generator;
+mod chunk_mesher;
+mod chunk_section;
 mod entity;
+mod feature;
 mod game_config;
 mod item;
+mod level;
+mod ore;
+mod physics;
 mod realm;
+mod region;
+mod terrain;
+#[cfg(test)]
+mod test_util;
 mod texture;
 mod ui;
 
@@ -56,6 +70,7 @@ struct State {
     camera_uniform: CameraUniform,
     camera_buffer: Buffer,
     camera_bind_group: BindGroup,
+    camera_bind_group_layout: BindGroupLayout,
     camera_controller: camera::CameraController,
 
     dt: f64,
@@ -63,7 +78,9 @@ struct State {
 
     depth_texture: texture::Texture,
 
-    realm: realm::Realm,
+    //在开始菜单选择世界后才创建
+    realm: Option<realm::Realm>,
+    wf_uniform_buffer: Buffer,
     wf_render_pipeline: RenderPipeline,
 
     game_config: game_config::GameConfig,
@@ -72,6 +89,8 @@ struct State {
     //ui_text_renderer: ui::ui_text_renderer::UITextRenderer,
     player: entity::Player,
     ui: ui::UI,
+    start_menu: ui::start_menu::StartMenu,
+    pause_menu: ui::pause_menu::PauseMenu,
 }
 
 impl State {
@@ -89,35 +108,10 @@ impl State {
         //let reload_duration = reload_start.elapsed();
         //println!("reload_duration:{:?}", reload_duration);
 
-        let game_config = game_config::GameConfig::new();
-
-        //创建摄像机
-        let camera = camera::Camera::new((1.0, 70.0, 1.0), cgmath::Deg(90.0), cgmath::Deg(-45.0));
-        let projection = camera::Projection::new(
-            basic_config.config.width,
-            basic_config.config.height,
-            cgmath::Deg(45.0),
-            0.1,
-            100.0,
-        );
-        let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&camera, &projection);
-
-        let dt: f64 = 0.001;
-        let last_render_time = instant::Instant::now();
-
-        let benchmark = benchmark::Benchmark::new();
-
-        window.set_cursor_visible(false);
-
-        let camera_buffer =
-            basic_config
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("First camera buffer"),
-                    contents: bytemuck::cast_slice(&[camera_uniform]),
-                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                });
+        let mut game_config = game_config::GameConfig::new();
+        if let Err(e) = game_config.load_settings(Path::new(game_config::SETTINGS_FILE)) {
+            eprintln!("设置读取失败，使用默认设置:{:?}", e);
+        }
 
         let camera_bind_group_layout =
             basic_config
@@ -148,9 +142,41 @@ impl State {
                     ],
                 });
 
-        let mut realm = realm::Realm::new(&basic_config, &camera_bind_group_layout);
-        let reload_start = std::time::Instant::now();
-        realm.reload_all_chunk(&realm.data.center_chunk_pos.clone(), &basic_config.device);
+        //创建摄像机 进入世界时移动到玩家上次的位置
+        let camera = camera::Camera::new((1.0, 70.0, 1.0), cgmath::Deg(90.0), cgmath::Deg(-45.0));
+
+        let projection = camera::Projection::new(
+            basic_config.config.width,
+            basic_config.config.height,
+            cgmath::Deg(45.0),
+            0.1,
+            camera::render_distance_zfar(game_config.get_render_distance()),
+        );
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera, &projection);
+
+        let dt: f64 = 0.001;
+        let last_render_time = instant::Instant::now();
+
+        let benchmark = benchmark::Benchmark::new();
+
+        let camera_buffer =
+            basic_config
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("First camera buffer"),
+                    contents: bytemuck::cast_slice(&[camera_uniform]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+
+        let wf_uniform_buffer =
+            basic_config
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Wireframe uniform buffer"),
+                    contents: bytemuck::bytes_of(&realm::WireframeUniform::default()),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                });
 
         let camera_bind_group = basic_config
             .device
@@ -164,16 +190,18 @@ impl State {
                     },
                     BindGroupEntry {
                         binding: 1,
-                        resource: realm.render_res.wf_uniform_buffer.as_entire_binding(),
+                        resource: wf_uniform_buffer.as_entire_binding(),
                     },
                 ],
             });
 
-        let camera_controller = camera::CameraController::new(
+        let mut camera_controller = camera::CameraController::new(
             game_config.player_speed,
             basic_config.config.width / 2,
             basic_config.config.height / 2,
         );
+        //开始菜单中使用鼠标
+        camera_controller.is_fov = false;
         //摄像机创建完成
 
         //线框
@@ -184,12 +212,29 @@ impl State {
                 source: ShaderSource::Wgsl(include_str!("wf_shader.wgsl").into()),
             });
 
+        let texture_bind_group_layout =
+            realm::RenderResources::create_texture_bind_group_layout(&basic_config.device);
+        let block_materials_bind_group_layout =
+            realm::RenderResources::create_block_materials_bind_group_layout(&basic_config.device);
+        let wf_render_pipeline_layout =
+            basic_config
+                .device
+                .create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("Wireframe render pipeline layout"),
+                    bind_group_layouts: &[
+                        &camera_bind_group_layout,
+                        &texture_bind_group_layout,
+                        &block_materials_bind_group_layout,
+                    ],
+                    push_constant_ranges: &[],
+                });
+
         let wf_render_pipeline =
             basic_config
                 .device
                 .create_render_pipeline(&RenderPipelineDescriptor {
                     label: Some("Wireframe render pipeline"),
-                    layout: Some(&realm.render_res.render_pipeline_layout),
+                    layout: Some(&wf_render_pipeline_layout),
                     vertex: VertexState {
                         module: &wf_shader,
                         entry_point: Some("vs_main"),
@@ -233,7 +278,7 @@ impl State {
 
         //线框创建完成
         let player = entity::Player::new(
-            &realm.data.all_block,
+            &realm::create_all_block(),
             &basic_config.device,
             &basic_config.queue,
             &camera_bind_group_layout,
@@ -247,9 +292,10 @@ impl State {
             1.0,
             window.inner_size(),
             &player,
-            &realm.render_res.block_materials_bind_group_layout,
-            &realm.render_res.texture_bind_group_layout,
+            &block_materials_bind_group_layout,
+            &texture_bind_group_layout,
         );
+        let start_menu = ui::start_menu::StartMenu::new(realm::WORLD_FILE_DIR.into());
         Self {
             basic_config,
             window,
@@ -259,6 +305,7 @@ impl State {
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            camera_bind_group_layout,
             camera_controller,
 
             dt,
@@ -266,7 +313,8 @@ impl State {
 
             depth_texture,
 
-            realm,
+            realm: None,
+            wf_uniform_buffer,
             wf_render_pipeline,
 
             game_config,
@@ -274,9 +322,42 @@ impl State {
             benchmark,
             player,
             ui,
+            start_menu,
+            pause_menu: ui::pause_menu::PauseMenu::new(),
         }
     }
 
+    //创建选中世界的Realm，从玩家上次的位置开始
+    fn open_world(&mut self, world_name: &str) -> anyhow::Result<()> {
+        let mut realm = realm::Realm::new(
+            &self.basic_config,
+            &self.camera_bind_group_layout,
+            world_name,
+            chunk_generator::worker_count(self.game_config.worker_threads),
+        )?;
+        //区块在第一次更新时按距离排队加载
+        realm.set_render_distance(self.game_config.get_render_distance());
+
+        self.player
+            .load_state(&realm.level.player, &realm.data.all_block);
+        self.player.load_skin(
+            &self.basic_config.device,
+            &self.basic_config.queue,
+            &realm.data.world_dir,
+        );
+        self.ui.reload_hotbar(
+            &self.basic_config.queue,
+            &self.player,
+            self.basic_config.size,
+        );
+        self.ui.close_menu();
+
+        self.realm = Some(realm);
+        self.game_config.game_state = game_config::GameState::Running;
+        self.camera_controller.is_fov = true;
+        Ok(())
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.basic_config.size = new_size;
@@ -305,6 +386,45 @@ impl State {
         }
     }
 
+    //暂停游戏并释放鼠标
+    fn pause(&mut self) {
+        self.game_config.game_state = game_config::GameState::Paused;
+        self.camera_controller.is_fov = false;
+        self.camera_controller.reset_movement();
+        self.ui.close_inventory();
+        self.pause_menu.reset();
+    }
+
+    //设置在暂停菜单中修改，离开菜单时保存
+    fn save_settings(&self) {
+        if let Err(e) = self
+            .game_config
+            .save_settings(Path::new(game_config::SETTINGS_FILE))
+        {
+            eprintln!("设置保存失败:{:?}", e);
+        }
+    }
+
+    fn resume(&mut self) {
+        self.save_settings();
+        self.ui.close_menu();
+        self.game_config.game_state = game_config::GameState::Running;
+        self.camera_controller.is_fov = true;
+    }
+
+    //保存世界后回到开始菜单
+    fn save_and_quit(&mut self) {
+        self.save_settings();
+        if let Some(mut realm) = self.realm.take() {
+            self.player.store_state(&mut realm.level.player);
+            realm.save_world();
+        }
+        self.ui.close_menu();
+        self.start_menu.refresh();
+        self.game_config.game_state = game_config::GameState::StartMenu;
+        self.camera_controller.is_fov = false;
+    }
+
     fn input(&mut self, event: &WindowEvent) -> bool {
         if self.camera_controller.is_fov {
             self.window
@@ -324,14 +444,42 @@ impl State {
                 self.camera_controller.is_cursor_visible = true;
             }
         }
+        match self.game_config.game_state {
+            game_config::GameState::StartMenu => return self.start_menu_input(event),
+            game_config::GameState::Paused => return self.pause_menu_input(event),
+            game_config::GameState::Running => {}
+        }
+
+        //打开物品栏时Esc只关闭物品栏
+        let is_escape_pressed = matches!(
+            event,
+            WindowEvent::KeyboardInput {
+                event: KeyEvent {
+                    state: ElementState::Pressed,
+                    physical_key: PhysicalKey::Code(KeyCode::Escape),
+                    repeat: false,
+                    ..
+                },
+                ..
+            }
+        );
+        if (is_escape_pressed && !self.ui.is_invenory_open)
+            || matches!(event, WindowEvent::Focused(false))
+        {
+            self.pause();
+            return true;
+        }
+
+        let Some(realm) = self.realm.as_mut() else {
+            return false;
+        };
+
         let mut is_consumed = false;
         if self.camera_controller.process_events(
             event,
-            &mut self.camera,
-            &mut self.realm,
-            &self.basic_config.queue,
+            realm,
             &mut self.game_config,
-            &self.player,
+            &mut self.player,
         ) {
             is_consumed = true;
         }
@@ -344,7 +492,7 @@ impl State {
                 &mut self.player,
                 self.basic_config.size,
                 &self.basic_config.device,
-                &self.realm.data.all_block,
+                &realm.data.all_block,
             ) {
                 is_consumed = true;
             }
@@ -353,17 +501,69 @@ impl State {
         is_consumed
     }
 
+    fn start_menu_input(&mut self, event: &WindowEvent) -> bool {
+        let hovered = self.ui.hovered_menu_item(event);
+        match self.start_menu.process_events(event, hovered) {
+            Some(ui::start_menu::StartMenuAction::OpenWorld(name)) => {
+                if let Err(e) = self.open_world(&name) {
+                    self.start_menu.set_message(format!("打开世界失败:{}", e));
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn pause_menu_input(&mut self, event: &WindowEvent) -> bool {
+        let hovered = self.ui.hovered_menu_item(event);
+        match self
+            .pause_menu
+            .process_events(event, hovered, &mut self.game_config)
+        {
+            Some(ui::pause_menu::PauseMenuAction::Resume) => self.resume(),
+            Some(ui::pause_menu::PauseMenuAction::SaveAndQuit) => self.save_and_quit(),
+            None => {}
+        }
+        //暂停时键盘和鼠标只由菜单处理，其他事件照常交给窗口
+        ui::pause_menu::is_menu_input(event)
+    }
+
     fn update(&mut self) {
-        self.camera_controller.update_camera(
-            &mut self.camera,
-            self.dt as f32,
-            &mut self.realm.data,
-        );
+        let Some(realm) = self.realm.as_mut() else {
+            return;
+        };
+        //暂停时世界和玩家都不更新
+        let is_paused = matches!(self.game_config.game_state, game_config::GameState::Paused);
+        if !is_paused {
+            self.camera_controller.update_camera(
+                &mut self.camera,
+                &mut self.player,
+                self.dt as f32,
+                &mut realm.data,
+            );
+            self.player
+                .update_instance(&self.basic_config.queue, self.dt as f32);
+        }
+        //视距可能在设置或按键中修改
+        let render_distance = self.game_config.get_render_distance();
+        if realm.data.chunk_rad() != render_distance {
+            realm.set_render_distance(render_distance);
+        }
+        self.projection
+            .set_zfar(camera::render_distance_zfar(render_distance));
         self.camera_uniform
             .update_view_proj(&self.camera, &self.projection);
 
-        self.realm
-            .update(&self.camera.position, &self.basic_config.device, self.dt);
+        if !is_paused {
+            //自动保存和退出时一起保存
+            self.player.store_state(&mut realm.level.player);
+            realm.update(
+                &self.player.position,
+                &self.basic_config.device,
+                &self.basic_config.queue,
+                self.dt,
+            );
+        }
 
         self.basic_config.queue.write_buffer(
             &self.camera_buffer,
@@ -371,14 +571,18 @@ impl State {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
         self.basic_config.queue.write_buffer(
-            &self.realm.render_res.wf_uniform_buffer,
+            &self.wf_uniform_buffer,
             0,
-            bytemuck::bytes_of(&self.realm.data.wf_uniform),
+            bytemuck::bytes_of(&realm.data.wf_uniform),
         );
 
+        //暂停时摄像机不动，也照常剔除，刚进入世界时同样需要
+        let frustum =
+            camera::Frustum::from_matrix(self.projection.calc_matrix() * self.camera.calc_matrix());
+        realm.cull_chunks(&frustum, self.camera.position);
+
         self.benchmark.update(self.dt);
-        self.ui
-            .update_ui(self.camera.position, self.dt, &self.realm);
+        self.ui.update_ui(self.player.position, self.dt, realm);
     }
 
     fn render(&mut self) -> Result<(), SurfaceError> {
@@ -394,6 +598,25 @@ impl State {
                     label: Some("Render Encoder"),
                 });
 
+        //开始菜单
+        let Some(realm) = self.realm.as_ref() else {
+            self.ui.update_menu(
+                &self.start_menu.page(),
+                self.basic_config.size,
+                &self.basic_config.device,
+            );
+            self.ui.draw_menu(
+                &self.basic_config.device,
+                &self.basic_config.queue,
+                &mut encoder,
+                &view,
+                LoadOp::Clear(self.basic_config.clear_color),
+            );
+            self.basic_config.queue.submit(iter::once(encoder.finish()));
+            output.present();
+            return Ok(());
+        };
+
         // 第一个渲染通道 - 用于游戏场景
         {
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
@@ -418,35 +641,47 @@ impl State {
                 timestamp_writes: None,
             });
 
-            self.realm
-                .draw_realm(&mut render_pass, &self.camera_bind_group);
+            realm.draw_realm(&mut render_pass, &self.camera_bind_group);
 
             //绘制线框
-            if self.realm.data.is_wf_visible {
+            if realm.data.is_wf_visible {
                 render_pass.set_pipeline(&self.wf_render_pipeline);
                 render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-                render_pass.set_bind_group(1, &self.realm.render_res.diffuse_bind_group, &[]);
-                render_pass.set_vertex_buffer(0, self.realm.render_res.wf_vertex_buffer.slice(..));
+                render_pass.set_bind_group(1, &realm.render_res.diffuse_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, realm.render_res.wf_vertex_buffer.slice(..));
                 render_pass.set_index_buffer(
-                    self.realm.render_res.wf_index_buffer.slice(..),
+                    realm.render_res.wf_index_buffer.slice(..),
                     IndexFormat::Uint16,
                 );
                 render_pass.draw_indexed(0..realm::WIREFRAME_INDCIES.len() as u32, 0, 0..1);
             }
 
-            //self.player
-            //    .draw_entities(&mut render_pass, &self.camera_bind_group);
+            //第一人称时看不到自己
+            if self.camera_controller.view_mode != camera::ViewMode::FirstPerson {
+                self.player
+                    .draw_entities(&mut render_pass, &self.camera_bind_group);
+            }
+
+            //半透明的水面最后绘制，和后面已经画好的场景混合
+            realm.draw_translucent(&mut render_pass, &self.camera_bind_group);
         } // 第一个渲染通道结束
 
         //self.ui.ui_text_renderer.set_text("测试文本");
+        if matches!(self.game_config.game_state, game_config::GameState::Paused) {
+            self.ui.update_menu(
+                &self.pause_menu.page(&self.game_config),
+                self.basic_config.size,
+                &self.basic_config.device,
+            );
+        }
 
         self.ui.draw_ui(
             &self.basic_config.device,
             &self.basic_config.queue,
             &mut encoder,
             &view,
-            &self.realm.render_res.diffuse_bind_group,
-            &self.realm.render_res.block_materials_bind_group,
+            &realm.render_res.diffuse_bind_group,
+            &realm.render_res.block_materials_bind_group,
             0.0,
             0.0,
         );
@@ -496,7 +731,16 @@ impl ApplicationHandler for App {
         }
 
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                if let Some(state) = self.state.as_mut() {
+                    state.save_settings();
+                    if let Some(realm) = state.realm.as_mut() {
+                        state.player.store_state(&mut realm.level.player);
+                        realm.save_world();
+                    }
+                }
+                event_loop.exit();
+            }
             WindowEvent::RedrawRequested => {
                 if let Some(state) = self.state.as_mut() {
                     let now = Instant::now();
@@ -0,0 +1,198 @@
+use crate::feature::{ChunkWriter, FeatureRng};
+use crate::realm::{BlockType, Chunk, ChunkCoord, CHUNK_HEIGHT, CHUNK_SIZE};
+
+//矿脉最多偏离起点这么多格，必须小于区块大小，这样只需要考虑相邻的区块
+const VEIN_RADIUS: i32 = 3;
+
+//区分不同用途的随机数
+const ORE_SALT: u64 = 0x6f72_6573;
+
+//一种矿石的生成参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OreSettings {
+    pub block: BlockType,
+    //矿脉起点的高度范围[min_y, max_y)，矿脉本身最多再超出VEIN_RADIUS格
+    pub min_y: i32,
+    pub max_y: i32,
+    //每个区块中的矿脉数量
+    pub veins_per_chunk: u32,
+    //一条矿脉最多的方块数，实际数量在一半到这个值之间
+    pub vein_size: u32,
+}
+
+pub const DEFAULT_ORES: &[OreSettings] = &[
+    OreSettings {
+        block: BlockType::CoalOre,
+        min_y: 5,
+        max_y: 96,
+        veins_per_chunk: 16,
+        vein_size: 12,
+    },
+    OreSettings {
+        block: BlockType::IronOre,
+        min_y: 5,
+        max_y: 56,
+        veins_per_chunk: 10,
+        vein_size: 8,
+    },
+    OreSettings {
+        block: BlockType::GoldOre,
+        min_y: 3,
+        max_y: 28,
+        veins_per_chunk: 3,
+        vein_size: 7,
+    },
+    OreSettings {
+        block: BlockType::DiamondOre,
+        min_y: 2,
+        max_y: 14,
+        veins_per_chunk: 1,
+        vein_size: 6,
+    },
+];
+
+//放大化的山更高，煤和铁可以生成到更高的地方
+pub const AMPLIFIED_ORES: &[OreSettings] = &[
+    OreSettings {
+        max_y: 160,
+        veins_per_chunk: 24,
+        ..DEFAULT_ORES[0]
+    },
+    OreSettings {
+        max_y: 96,
+        veins_per_chunk: 14,
+        ..DEFAULT_ORES[1]
+    },
+    DEFAULT_ORES[2],
+    DEFAULT_ORES[3],
+];
+
+/*
+ * 在地形中放置矿脉，只替换石头，不会出现在泥土、洞穴和基岩中
+ * 每个区块的矿脉由种子和区块坐标决定，相邻区块的矿脉超出边界的部分也会写入这个区块
+ */
+pub fn place_ores(chunk: &mut Chunk, coord: &ChunkCoord, seed: u32, ores: &[OreSettings]) {
+    let mut writer = ChunkWriter::new(chunk, coord);
+    for chunk_x in coord.x - 1..=coord.x + 1 {
+        for chunk_z in coord.z - 1..=coord.z + 1 {
+            for (i, ore) in ores.iter().enumerate() {
+                let mut rng = FeatureRng::new(seed, chunk_x, chunk_z, ORE_SALT + i as u64);
+                for _ in 0..ore.veins_per_chunk {
+                    place_vein(
+                        &mut writer,
+                        &mut rng,
+                        &ChunkCoord::new(chunk_x, chunk_z),
+                        ore,
+                    );
+                }
+            }
+        }
+    }
+}
+
+//从区块内随机的起点随机游走，经过的石头换成矿石
+fn place_vein(
+    writer: &mut ChunkWriter,
+    rng: &mut FeatureRng,
+    coord: &ChunkCoord,
+    ore: &OreSettings,
+) {
+    let max_y = ore.max_y.min(CHUNK_HEIGHT);
+    let start_x = coord.x * CHUNK_SIZE + rng.range(0, CHUNK_SIZE);
+    let start_z = coord.z * CHUNK_SIZE + rng.range(0, CHUNK_SIZE);
+    let start_y = rng.range(ore.min_y, max_y.max(ore.min_y + 1));
+    let size = rng.range(ore.vein_size as i32 / 2, ore.vein_size as i32 + 1);
+
+    let (mut x, mut y, mut z) = (start_x, start_y, start_z);
+    for _ in 0..size {
+        writer.replace(x, y, z, BlockType::Stone, ore.block);
+        //每次沿一个方向走一格，不超出VEIN_RADIUS
+        let step = rng.range(0, 2) * 2 - 1;
+        match rng.range(0, 3) {
+            0 => x = (x + step).clamp(start_x - VEIN_RADIUS, start_x + VEIN_RADIUS),
+            1 => y = (y + step).clamp(start_y - VEIN_RADIUS, start_y + VEIN_RADIUS),
+            _ => z = (z + step).clamp(start_z - VEIN_RADIUS, start_z + VEIN_RADIUS),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_generator::ChunkGenerator;
+    use crate::realm::{Block, ChunkData};
+    use crate::terrain::GeneratorPreset;
+
+    #[test]
+    fn test_ores_in_height_ranges() {
+        let seed = 42;
+        let mut counts = [0; 4];
+        for chunk_x in 0..3 {
+            for chunk_z in 0..3 {
+                let coord = ChunkCoord::new(chunk_x, chunk_z);
+                let chunk = ChunkGenerator::generate_terrain_internal(
+                    coord,
+                    seed,
+                    &GeneratorPreset::Default,
+                );
+                for x in 0..CHUNK_SIZE {
+                    for z in 0..CHUNK_SIZE {
+                        for y in 0..CHUNK_HEIGHT {
+                            let tp = chunk.get_block(x, y, z).tp;
+                            let Some(i) = DEFAULT_ORES.iter().position(|ore| ore.block == tp)
+                            else {
+                                continue;
+                            };
+                            let ore = &DEFAULT_ORES[i];
+                            assert!(y >= ore.min_y - VEIN_RADIUS && y < ore.max_y + VEIN_RADIUS);
+                            counts[i] += 1;
+                        }
+                    }
+                }
+            }
+        }
+        //每种矿石都会出现，越稀有的越少
+        assert!(counts.iter().all(|&count| count > 0), "{:?}", counts);
+        assert!(
+            counts.windows(2).all(|pair| pair[0] > pair[1]),
+            "{:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn test_ores_replace_stone_only() {
+        //一半是石头一半是泥土，矿石只出现在石头中
+        let coord = ChunkCoord::new(-1, 2);
+        let mut chunk = Chunk::new(ChunkData::new());
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..32 {
+                    let tp = if x < CHUNK_SIZE / 2 {
+                        BlockType::Stone
+                    } else {
+                        BlockType::Dirt
+                    };
+                    chunk.set_block(x, y, z, Block::new(tp));
+                }
+            }
+        }
+        place_ores(&mut chunk, &coord, 7, DEFAULT_ORES);
+        let mut found = false;
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_HEIGHT {
+                    let tp = chunk.get_block(x, y, z).tp;
+                    if y >= 32 {
+                        assert_eq!(tp, BlockType::Empty);
+                    } else if x >= CHUNK_SIZE / 2 {
+                        assert_eq!(tp, BlockType::Dirt);
+                    } else {
+                        found |= tp != BlockType::Stone;
+                    }
+                }
+            }
+        }
+        assert!(found);
+    }
+}
@@ -0,0 +1,446 @@
+use cgmath::*;
+
+use crate::realm::{self, Block, BlockType, RealmData};
+
+//玩家碰撞箱 宽0.6格 高1.8格
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+//眼睛到脚底的高度，摄像机位置 = 脚底位置 + 眼睛高度
+pub const PLAYER_EYE_HEIGHT: f32 = 1.62;
+
+//重力加速度和起跳速度，跳跃高度约为 JUMP_SPEED^2 / (2 * GRAVITY) ≈ 1.45格
+pub const GRAVITY: f32 = 28.0;
+pub const JUMP_SPEED: f32 = 9.0;
+pub const MAX_FALL_SPEED: f32 = 60.0;
+//在地面上可以直接走上一格高的台阶
+pub const STEP_HEIGHT: f32 = 1.0;
+//水中重力较小，下沉速度有上限，按住跳跃键以固定速度上浮
+pub const WATER_GRAVITY: f32 = 8.0;
+pub const MAX_SINK_SPEED: f32 = 3.0;
+pub const SWIM_SPEED: f32 = 4.0;
+//水中水平移动速度的倍数
+pub const WATER_SPEED_FACTOR: f32 = 0.6;
+//一帧时间过长时拆成多步模拟
+const MAX_STEP_TIME: f32 = 0.05;
+//浮点误差容忍度，刚好贴着方块时不会因为误差陷进去
+const COLLISION_EPSILON: f32 = 1e-4;
+
+//轴对齐包围盒
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    //以脚底中心为原点的玩家碰撞箱
+    pub fn player(feet: Point3<f32>) -> Self {
+        let half = PLAYER_WIDTH / 2.0;
+        Self::new(
+            Point3::new(feet.x - half, feet.y, feet.z - half),
+            Point3::new(feet.x + half, feet.y + PLAYER_HEIGHT, feet.z + half),
+        )
+    }
+
+    pub fn block(coord: Point3<i32>) -> Self {
+        let min = coord.map(|v| v as f32);
+        Self::new(min, min + Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    //底面中心
+    pub fn feet(&self) -> Point3<f32> {
+        Point3::new(
+            (self.min.x + self.max.x) / 2.0,
+            self.min.y,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    pub fn offset(&self, delta: Vector3<f32>) -> Self {
+        Self::new(self.min + delta, self.max + delta)
+    }
+
+    //只接触不算相交
+    pub fn intersects(&self, other: &Self) -> bool {
+        (0..3).all(|i| self.min[i] < other.max[i] && self.max[i] > other.min[i])
+    }
+
+    //向delta方向扩展，包含移动经过的所有位置
+    pub fn expand(&self, delta: Vector3<f32>) -> Self {
+        let mut aabb = *self;
+        for i in 0..3 {
+            if delta[i] < 0.0 {
+                aabb.min[i] += delta[i];
+            } else {
+                aabb.max[i] += delta[i];
+            }
+        }
+        aabb
+    }
+
+    /*
+     * 沿axis轴移动delta时，被other挡住后实际能移动的距离
+     * 另外两个轴上不重叠时不会碰撞
+     * 已经和other重叠时忽略它，卡在方块里时仍然可以走出来
+     */
+    fn clip(&self, other: &Self, axis: usize, delta: f32) -> f32 {
+        let overlaps_other_axes = (0..3).filter(|&i| i != axis).all(|i| {
+            self.min[i] < other.max[i] - COLLISION_EPSILON
+                && self.max[i] > other.min[i] + COLLISION_EPSILON
+        });
+        if !overlaps_other_axes {
+            return delta;
+        }
+
+        if delta > 0.0 && other.min[axis] >= self.max[axis] - COLLISION_EPSILON {
+            delta.min(other.min[axis] - self.max[axis])
+        } else if delta < 0.0 && other.max[axis] <= self.min[axis] + COLLISION_EPSILON {
+            delta.max(other.max[axis] - self.min[axis])
+        } else {
+            delta
+        }
+    }
+}
+
+//可以碰撞的方块
+pub fn is_solid(block: Block) -> bool {
+    block.tp.is_solid()
+}
+
+//area范围内所有实心方块的碰撞箱
+fn solid_blocks(data: &RealmData, area: &Aabb) -> Vec<Aabb> {
+    let min = area.min.map(|v| v.floor() as i32);
+    let max = area.max.map(|v| v.ceil() as i32 - 1);
+    let mut blocks = Vec::new();
+    for x in min.x..=max.x {
+        for y in min.y.max(0)..=max.y.min(realm::CHUNK_HEIGHT - 1) {
+            for z in min.z..=max.z {
+                let coord = Point3::new(x, y, z);
+                if is_solid(data.get_block(coord)) {
+                    blocks.push(Aabb::block(coord));
+                }
+            }
+        }
+    }
+    blocks
+}
+
+//碰撞箱是否和水方块重叠
+pub fn is_in_water(data: &RealmData, aabb: &Aabb) -> bool {
+    let min = aabb.min.map(|v| v.floor() as i32);
+    let max = aabb.max.map(|v| v.ceil() as i32 - 1);
+    for x in min.x..=max.x {
+        for y in min.y.max(0)..=max.y.min(realm::CHUNK_HEIGHT - 1) {
+            for z in min.z..=max.z {
+                if data.get_block(Point3::new(x, y, z)).tp == BlockType::Water {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/*
+ * 扫掠碰撞：依次沿 y、x、z 轴移动并被方块挡住
+ * 先收集整段移动经过的方块，移动再快也不会穿过方块
+ * 返回移动后的碰撞箱和实际移动的距离
+ */
+pub fn sweep(data: &RealmData, aabb: Aabb, delta: Vector3<f32>) -> (Aabb, Vector3<f32>) {
+    let blocks = solid_blocks(data, &aabb.expand(delta));
+    let mut aabb = aabb;
+    let mut moved = Vector3::zero();
+    for axis in [1, 0, 2] {
+        let mut d = delta[axis];
+        for block in &blocks {
+            d = aabb.clip(block, axis, d);
+        }
+        moved[axis] = d;
+        let mut offset = Vector3::zero();
+        offset[axis] = d;
+        aabb = aabb.offset(offset);
+    }
+    (aabb, moved)
+}
+
+//生存模式下玩家的速度、是否站在地面上和是否在水中
+#[derive(Debug)]
+pub struct PlayerPhysics {
+    pub velocity: Vector3<f32>,
+    pub on_ground: bool,
+    pub in_water: bool,
+}
+
+impl Default for PlayerPhysics {
+    fn default() -> Self {
+        Self {
+            velocity: Vector3::zero(),
+            on_ground: false,
+            in_water: false,
+        }
+    }
+}
+
+impl PlayerPhysics {
+    //wish_velocity为按键期望的水平速度，返回新的脚底位置
+    pub fn update(
+        &mut self,
+        data: &RealmData,
+        feet: Point3<f32>,
+        wish_velocity: Vector3<f32>,
+        jump: bool,
+        dt: f32,
+    ) -> Point3<f32> {
+        //所在区块还没有加载时不移动，避免掉出世界
+        let coord = realm::get_chunk_coord(feet.x.floor() as i32, feet.z.floor() as i32);
+        if !data.chunk_map.contains_key(&coord) {
+            return feet;
+        }
+
+        let mut feet = feet;
+        let mut remaining = dt;
+        while remaining > 0.0 {
+            let step = remaining.min(MAX_STEP_TIME);
+            feet = self.step(data, feet, wish_velocity, jump, step);
+            remaining -= step;
+        }
+        feet
+    }
+
+    fn step(
+        &mut self,
+        data: &RealmData,
+        feet: Point3<f32>,
+        wish_velocity: Vector3<f32>,
+        jump: bool,
+        dt: f32,
+    ) -> Point3<f32> {
+        self.in_water = is_in_water(data, &Aabb::player(feet));
+        if self.in_water {
+            //游泳：水平方向变慢，按住跳跃键上浮，否则慢慢下沉
+            self.velocity.x = wish_velocity.x * WATER_SPEED_FACTOR;
+            self.velocity.z = wish_velocity.z * WATER_SPEED_FACTOR;
+            self.velocity.y = if jump {
+                SWIM_SPEED
+            } else {
+                (self.velocity.y - WATER_GRAVITY * dt).max(-MAX_SINK_SPEED)
+            };
+        } else {
+            self.velocity.x = wish_velocity.x;
+            self.velocity.z = wish_velocity.z;
+            if jump && self.on_ground {
+                self.velocity.y = JUMP_SPEED;
+            }
+            self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-MAX_FALL_SPEED);
+        }
+
+        let delta = self.velocity * dt;
+        let aabb = Aabb::player(feet);
+        let (mut new_aabb, mut moved) = sweep(data, aabb, delta);
+        let mut on_ground = delta.y < 0.0 && moved.y > delta.y;
+
+        //在地面上或水中水平方向被挡住时，尝试抬高后再移动，即走上台阶或爬上岸
+        let is_blocked = moved.x != delta.x || moved.z != delta.z;
+        if (self.on_ground || self.in_water) && is_blocked {
+            let (up_aabb, up) = sweep(data, aabb, Vector3::new(0.0, STEP_HEIGHT, 0.0));
+            let (side_aabb, side) = sweep(data, up_aabb, Vector3::new(delta.x, 0.0, delta.z));
+            let (down_aabb, down) = sweep(data, side_aabb, Vector3::new(0.0, -up.y, 0.0));
+            if side.x * side.x + side.z * side.z > moved.x * moved.x + moved.z * moved.z {
+                new_aabb = down_aabb;
+                moved = up + side + down;
+                on_ground = down.y > -up.y;
+            }
+        }
+
+        if moved.y != delta.y {
+            //落地或者撞到头
+            self.velocity.y = 0.0;
+        }
+        self.on_ground = on_ground;
+        new_aabb.feet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realm::{BlockType, ChunkCoord, Realm};
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    //平坦的测试地形，地面高度为4
+    fn test_data() -> RealmData {
+        let mut data = RealmData::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                Realm::generate_terrian_test(&mut data.chunk_map, &ChunkCoord::new(x, z), 0);
+            }
+        }
+        data
+    }
+
+    fn simulate(
+        physics: &mut PlayerPhysics,
+        data: &RealmData,
+        mut feet: Point3<f32>,
+        wish_velocity: Vector3<f32>,
+        seconds: f32,
+    ) -> Point3<f32> {
+        for _ in 0..(seconds * 60.0) as usize {
+            feet = physics.update(data, feet, wish_velocity, false, 1.0 / 60.0);
+        }
+        feet
+    }
+
+    #[test]
+    fn test_fall_and_land() {
+        let data = test_data();
+        let mut physics = PlayerPhysics::default();
+        let feet = simulate(
+            &mut physics,
+            &data,
+            Point3::new(0.5, 20.0, 0.5),
+            Vector3::zero(),
+            3.0,
+        );
+        assert_near(feet.y, 4.0);
+        assert!(physics.on_ground);
+        assert_eq!(physics.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn test_fast_fall_does_not_tunnel() {
+        let data = test_data();
+        let mut physics = PlayerPhysics {
+            velocity: Vector3::new(0.0, -MAX_FALL_SPEED, 0.0),
+            ..Default::default()
+        };
+        //一步移动超过地面厚度也不会穿过去
+        let feet = physics.update(
+            &data,
+            Point3::new(0.5, 6.0, 0.5),
+            Vector3::zero(),
+            false,
+            0.5,
+        );
+        assert_near(feet.y, 4.0);
+    }
+
+    #[test]
+    fn test_jump() {
+        let data = test_data();
+        let mut physics = PlayerPhysics::default();
+        let mut feet = simulate(
+            &mut physics,
+            &data,
+            Point3::new(0.5, 4.0, 0.5),
+            Vector3::zero(),
+            0.1,
+        );
+        feet = physics.update(&data, feet, Vector3::zero(), true, 1.0 / 60.0);
+        assert!(!physics.on_ground);
+
+        let mut max_y = feet.y;
+        for _ in 0..120 {
+            feet = physics.update(&data, feet, Vector3::zero(), false, 1.0 / 60.0);
+            max_y = max_y.max(feet.y);
+        }
+        assert!(max_y > 5.2 && max_y < 5.6, "max_y:{}", max_y);
+        assert_near(feet.y, 4.0);
+    }
+
+    #[test]
+    fn test_wall_and_step_up() {
+        let mut data = test_data();
+        //x=3处一格高的台阶，x=8处两格高的墙
+        for z in -8..8 {
+            for x in 3..8 {
+                data.set_block(Point3::new(x, 4, z), Block::new(BlockType::Stone));
+            }
+            data.set_block(Point3::new(8, 5, z), Block::new(BlockType::Stone));
+            data.set_block(Point3::new(8, 6, z), Block::new(BlockType::Stone));
+        }
+
+        let mut physics = PlayerPhysics::default();
+        let feet = simulate(
+            &mut physics,
+            &data,
+            Point3::new(0.5, 4.0, 0.5),
+            Vector3::new(5.0, 0.0, 0.0),
+            3.0,
+        );
+        //走上台阶后被墙挡住
+        assert_near(feet.y, 5.0);
+        assert_near(feet.x, 8.0 - PLAYER_WIDTH / 2.0);
+        assert!(physics.on_ground);
+    }
+
+    #[test]
+    fn test_swim() {
+        let mut data = test_data();
+        //地面上方4格深的水池
+        for x in -8..8 {
+            for z in -8..8 {
+                for y in 4..8 {
+                    data.set_block(Point3::new(x, y, z), Block::new(BlockType::Water));
+                }
+            }
+        }
+
+        //掉进水里后减速，慢慢沉到底
+        let mut physics = PlayerPhysics::default();
+        let mut feet = Point3::new(0.5, 20.0, 0.5);
+        let mut time = 0.0;
+        while feet.y > 4.0 + 1e-3 && time < 10.0 {
+            feet = physics.update(&data, feet, Vector3::zero(), false, 1.0 / 60.0);
+            if physics.in_water {
+                assert!(physics.velocity.y >= -MAX_SINK_SPEED);
+            }
+            time += 1.0 / 60.0;
+        }
+        assert!(physics.in_water);
+        assert!(physics.on_ground);
+        assert_near(feet.y, 4.0);
+
+        //按住跳跃键浮到水面，身体的一部分露出水面
+        let mut max_y = feet.y;
+        for _ in 0..240 {
+            feet = physics.update(&data, feet, Vector3::zero(), true, 1.0 / 60.0);
+            max_y = max_y.max(feet.y);
+        }
+        assert!(max_y > 8.0 - PLAYER_HEIGHT, "max_y:{}", max_y);
+        assert!(max_y < 9.0, "max_y:{}", max_y);
+
+        //水中水平方向变慢，水不会挡住玩家
+        let mut physics = PlayerPhysics::default();
+        let start = Point3::new(0.5, 4.0, 0.5);
+        let feet = simulate(&mut physics, &data, start, Vector3::new(5.0, 0.0, 0.0), 0.5);
+        assert_near(feet.x - start.x, 5.0 * WATER_SPEED_FACTOR * 0.5);
+    }
+
+    #[test]
+    fn test_unloaded_chunk_freezes() {
+        let data = RealmData::new();
+        let mut physics = PlayerPhysics::default();
+        let feet = Point3::new(0.5, 20.0, 0.5);
+        assert_eq!(
+            physics.update(&data, feet, Vector3::zero(), false, 1.0),
+            feet
+        );
+    }
+
+    #[test]
+    fn test_aabb() {
+        let player = Aabb::player(Point3::new(0.5, 4.0, 0.5));
+        assert!(player.intersects(&Aabb::block(Point3::new(0, 5, 0))));
+        assert!(!player.intersects(&Aabb::block(Point3::new(0, 3, 0))));
+        assert!(!player.intersects(&Aabb::block(Point3::new(1, 4, 0))));
+        assert_eq!(player.feet(), Point3::new(0.5, 4.0, 0.5));
+    }
+}
@@ -1,10 +1,18 @@
 struct InstanceInput {
-    @location(2) position: vec3<f32>,
+    @location(5) position: vec3<f32>,
+    //绕y轴旋转的弧度
+    @location(6) rotation: f32,
+    @location(7) head_pitch: f32,
+    @location(8) limb_swing: f32,
 }
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
+    //部位旋转时围绕的关节
+    @location(2) pivot: vec3<f32>,
+    //0头 1身体 2右臂 3左臂 4右腿 5左腿
+    @location(3) part: u32,
 }
 
 struct VertexOutput {
@@ -19,6 +27,31 @@ struct CameraUniform {
 @group(1) @binding(0)
 var<uniform> camera: CameraUniform;
 
+//绕x轴旋转，角度为正时z轴正方向的点向上转
+fn rotate_x(p: vec3<f32>, angle: f32) -> vec3<f32> {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec3<f32>(p.x, p.y * c + p.z * s, -p.y * s + p.z * c);
+}
+
+fn part_angle(part: u32, instacne: InstanceInput) -> f32 {
+    switch part {
+        case 0u: {
+            return instacne.head_pitch;
+        }
+        //右臂和左腿同向摆动
+        case 2u, 5u: {
+            return instacne.limb_swing;
+        }
+        case 3u, 4u: {
+            return -instacne.limb_swing;
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
+
 //标记为vertex shader
 @vertex
 fn vs_main(
@@ -29,7 +62,16 @@ fn vs_main(
     //let：不可变 可以推断类型
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    let world_position = model.position + instacne.position;
+    //先让部位绕关节旋转，再让整个模型绕y轴旋转
+    let posed = rotate_x(model.position - model.pivot, part_angle(model.part, instacne)) + model.pivot;
+    let c = cos(instacne.rotation);
+    let s = sin(instacne.rotation);
+    let rotated = vec3<f32>(
+        posed.x * c + posed.z * s,
+        posed.y,
+        -posed.x * s + posed.z * c,
+    );
+    let world_position = rotated + instacne.position;
     out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
 
     return out;
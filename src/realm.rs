@@ -1,17 +1,21 @@
+use std::collections::{HashMap, HashSet};
 use std::panic;
-use std::path::Path;
-use std::{collections::HashMap, sync::atomic::AtomicBool};
+use std::path::{Path, PathBuf};
 
-use anyhow::Context;
-use num_derive::FromPrimitive;
 use serde::{Deserialize, Serialize};
 use wgpu::{util::DeviceExt, *};
 
 use cgmath::*;
 
 use crate::basic_config::{self, BasicConfig};
-use crate::chunk_generator::{self, ChunkGenerator};
-use crate::{camera, texture};
+use crate::block_registry;
+use crate::camera::Frustum;
+use crate::chunk_buffer::{ChunkMeshBuffer, MeshSlot};
+use crate::chunk_generator::{ChunkGenerator, WorkerStats};
+use crate::chunk_mesher::{self, ChunkMesh, ChunkMeshParts, ChunkVertex, BORDERS};
+use crate::chunk_section::{ChunkSection, SECTION_HEIGHT, SECTION_NUM};
+use crate::level::LevelData;
+use crate::{region, texture};
 
 pub const TEXT_FRAC: f32 = 16.0 / 512.0;
 pub const WF_SIZE: f32 = 0.01;
@@ -178,100 +182,142 @@ pub const WIREFRAME_INDCIES: &[u16] = &[
 ];
 
 pub const INIT_CHUNK_RAD: i32 = 5;
+//每帧最多加入世界的区块数，避免一帧内处理太多造成卡顿
+pub const CHUNK_LOAD_BUDGET: usize = 4;
+//设置中可选的视距范围
+pub const MIN_CHUNK_RAD: i32 = 2;
+pub const MAX_CHUNK_RAD: i32 = 16;
 pub const CHUNK_SIZE: i32 = 16;
 pub const CHUNK_HEIGHT: i32 = 512;
 pub const BLOCK_NUM_PER_CHUNK: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize;
 
-const WORLD_FILE_DIR: &str = "./worlds";
+pub const WORLD_FILE_DIR: &str = "./worlds";
+//没有指定种子时使用
+pub const DEFAULT_SEED: u32 = 2025318;
+
+//自动保存间隔(秒)
+pub const AUTOSAVE_INTERVAL: f64 = 60.0;
 
 pub const BLOCK_EMPTY: Block = Block {
     tp: BlockType::Empty,
 };
 
-//同时作为usize和u32
-//注意范围
-#[repr(usize)]
-#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
-pub enum BlockType {
+/*
+ * 方块编号，即方块在定义文件中的顺序，0为空方块
+ * 编号保存在区块中，代码里直接用到的方块在这里定义常量
+ * 加载定义文件时检查这些方块的位置和名称
+ */
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlockType(pub u32);
+
+#[allow(non_upper_case_globals)]
+impl BlockType {
     //没有方块 默认值
-    #[default]
-    Empty = 0,
+    pub const Empty: BlockType = BlockType(0);
 
     //基岩 世界基础
-    UnderStone = 1,
+    pub const UnderStone: BlockType = BlockType(1);
 
     //石头
-    Stone = 2,
+    pub const Stone: BlockType = BlockType(2);
 
     //草方块
-    Grass = 3,
+    pub const Grass: BlockType = BlockType(3);
 
     //泥土
-    Dirt = 4,
+    pub const Dirt: BlockType = BlockType(4);
 
     //白桦原木
-    BirchLog = 5,
+    pub const BirchLog: BlockType = BlockType(5);
 
     //白桦树叶
-    BirchLeaves = 6,
+    pub const BirchLeaves: BlockType = BlockType(6);
 
     //白桦木板
-    BirchPlank = 7,
+    pub const BirchPlank: BlockType = BlockType(7);
+
+    pub const TestBlock: BlockType = BlockType(8);
+
+    //沙子 沙漠和海底的地表
+    pub const Sand: BlockType = BlockType(9);
+
+    //雪块 雪原的地表
+    pub const Snow: BlockType = BlockType(10);
+
+    //橡木原木
+    pub const OakLog: BlockType = BlockType(11);
+
+    //橡树树叶
+    pub const OakLeaves: BlockType = BlockType(12);
+
+    //煤矿石 以下几种矿石在地下的石头中成簇生成，见ore
+    pub const CoalOre: BlockType = BlockType(13);
+
+    //铁矿石
+    pub const IronOre: BlockType = BlockType(14);
+
+    //金矿石
+    pub const GoldOre: BlockType = BlockType(15);
+
+    //钻石矿石
+    pub const DiamondOre: BlockType = BlockType(16);
+
+    //水 填充海平面以下的空处，可以在其中游泳
+    pub const Water: BlockType = BlockType(17);
+
+    //定义文件中必须有的方块，以及它们的名称
+    pub const BUILTIN: [(BlockType, &'static str); 17] = [
+        (BlockType::UnderStone, "bedrock"),
+        (BlockType::Stone, "stone"),
+        (BlockType::Grass, "grass_block"),
+        (BlockType::Dirt, "dirt"),
+        (BlockType::BirchLog, "birch_log"),
+        (BlockType::BirchLeaves, "birch_leaves"),
+        (BlockType::BirchPlank, "birch_planks"),
+        (BlockType::TestBlock, "test_block"),
+        (BlockType::Sand, "sand"),
+        (BlockType::Snow, "snow_block"),
+        (BlockType::OakLog, "oak_log"),
+        (BlockType::OakLeaves, "oak_leaves"),
+        (BlockType::CoalOre, "coal_ore"),
+        (BlockType::IronOre, "iron_ore"),
+        (BlockType::GoldOre, "gold_ore"),
+        (BlockType::DiamondOre, "diamond_ore"),
+        (BlockType::Water, "water"),
+    ];
 
-    TestBlock = 8,
-}
-//添加方块之后记得方块数量
-pub const BLOCK_NUM: usize = 9;
+    pub fn info(self) -> &'static BlockInfo {
+        block_registry::registry().get(self)
+    }
 
-impl BlockType {
+    //相邻方块透明时才绘制面
     pub fn is_transparent(&self) -> bool {
-        match self {
-            //BlockType::BirchLeaves => true,
-            BlockType::Empty => true,
-            _ => false,
-        }
+        self.info().transparent
     }
-}
 
-#[repr(u32)]
-#[derive(FromPrimitive)]
-pub enum BlockMaterials {
-    Empty = 999,
-    UnderStone = 0,
-    Stone = 1,
-    GrassBlockSide = 2,
-    GrassBlockTop = 3,
-    Dirt = 4,
-    BirchLogTop = 5,
-    BirchLog = 6,
-    BirchLeaves = 7,
-    BirchPlank = 8,
-    TestBlock = 9,
+    //半透明的面单独绘制，见chunk_mesher::ChunkMesh
+    #[allow(unused)]
+    pub fn is_translucent(&self) -> bool {
+        self.info().translucent
+    }
+
+    //可以碰撞
+    pub fn is_solid(&self) -> bool {
+        self.info().solid
+    }
 }
-// 添加材质后记得修改材质数量
-pub const BLOCK_MATERIALS_NUM: u32 = 10;
 
-#[allow(unused)]
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone)]
 pub struct BlockInfo {
-    pub name: &'static str,
+    pub name: String,
     pub block_type: BlockType,
+    //每个面在纹理数组中的层，顺序为：正、上、后、下、左、右
     pub tex_offset: [u32; 6],
-}
-
-impl BlockInfo {
-    fn new(
-        name: &'static str,
-        //顺序为：正、上、后、下、左、右
-        tex_offset: [u32; 6],
-        block_type: BlockType,
-    ) -> Self {
-        Self {
-            name,
-            block_type,
-            tex_offset, //vertices,
-        }
-    }
+    pub transparent: bool,
+    pub translucent: bool,
+    pub solid: bool,
+    //负数表示无法破坏
+    pub hardness: f32,
 }
 
 #[repr(C)]
@@ -309,7 +355,7 @@ pub struct WireframeVertex {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct WireframeUniform {
     pub position: [f32; 3],
     _padding: f32,
@@ -358,7 +404,7 @@ impl Instance {
     }
 }
 
-#[derive(Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[derive(Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
 pub struct ChunkCoord {
     pub x: i32,
     pub z: i32,
@@ -368,6 +414,28 @@ impl ChunkCoord {
     pub fn new(x: i32, z: i32) -> Self {
         Self { x, z }
     }
+
+    //以区块为单位的距离的平方，加载范围是圆形的
+    pub fn distance_squared(&self, other: &ChunkCoord) -> i32 {
+        let dx = self.x - other.x;
+        let dz = self.z - other.z;
+        dx * dx + dz * dz
+    }
+}
+
+//center为圆心、radius为半径的圆内的区块，由近到远排列
+pub fn chunks_in_radius(center: ChunkCoord, radius: i32) -> Vec<ChunkCoord> {
+    let mut coords = Vec::new();
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            let coord = ChunkCoord::new(center.x + x, center.z + z);
+            if coord.distance_squared(&center) <= radius * radius {
+                coords.push(coord);
+            }
+        }
+    }
+    coords.sort_by_key(|coord| (coord.distance_squared(&center), coord.x, coord.z));
+    coords
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
@@ -383,31 +451,72 @@ impl Block {
 
 /*
  * 这是存储到文件中的数据
+ * 竖直方向按 SECTION_HEIGHT 分成 SECTION_NUM 个分段，每个分段单独用调色板压缩
  */
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct ChunkData {
-    pub blocks: Vec<Block>,
+    sections: Vec<ChunkSection>,
+}
+
+impl Default for ChunkData {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ChunkData {
+    //全空区块
+    pub fn new() -> Self {
+        Self {
+            sections: vec![ChunkSection::new(); SECTION_NUM],
+        }
+    }
+
+    //旧版平铺存储的方块，下标与 RealmData::relative_to_index 相同
+    pub fn from_flat_blocks(blocks: &[Block]) -> anyhow::Result<Self> {
+        if blocks.len() != BLOCK_NUM_PER_CHUNK {
+            anyhow::bail!("区块数据损坏：方块数量不匹配");
+        }
+        let mut data = Self::new();
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_HEIGHT {
+                for z in 0..CHUNK_SIZE {
+                    let block = blocks[RealmData::relative_to_index(x, y, z)];
+                    if block.tp != BlockType::Empty {
+                        data.set_block(x, y, z, block);
+                    }
+                }
+            }
+        }
+        Ok(data)
+    }
+
     pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
-        self.blocks[(x * CHUNK_SIZE * CHUNK_HEIGHT + y * CHUNK_SIZE + z) as usize]
+        self.sections[(y / SECTION_HEIGHT) as usize].get(ChunkSection::index(
+            x,
+            y % SECTION_HEIGHT,
+            z,
+        ))
     }
-}
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Material {
-    index: u32,
-    _padding: [u32; 3],
-}
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) {
+        self.sections[(y / SECTION_HEIGHT) as usize]
+            .set(ChunkSection::index(x, y % SECTION_HEIGHT, z), block);
+    }
 
-impl Material {
-    fn new(index: u32) -> Self {
-        Self {
-            index,
-            _padding: [0, 0, 0],
+    //分段内全是空气，遍历方块时可以直接跳过
+    pub fn is_section_empty(&self, section: usize) -> bool {
+        self.sections[section].is_empty()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.sections.len() != SECTION_NUM {
+            anyhow::bail!("区块数据损坏：分段数量不匹配");
         }
+        for section in &self.sections {
+            section.validate()?;
+        }
+        Ok(())
     }
 }
 
@@ -417,26 +526,18 @@ impl Material {
 #[derive(Debug, Clone)]
 pub struct Chunk {
     data: ChunkData,
-    pub coord_to_offset: Vec<u32>,
-    pub instance: Vec<Instance>,
-    //is_dirty: AtomicBool,
-
-    //为第一个空位置
-    pub offset_top: u32,
+    //区块被修改过，卸载或保存时需要写入文件
+    pub is_dirty: bool,
+    //CPU上保留一份网格，相邻区块加载后只需要更新侧面
+    pub mesh: ChunkMeshParts,
 }
 
 impl Chunk {
     pub fn new(data: ChunkData) -> Self {
-        //let is_dirty = AtomicBool::new(false);
-        let coord_to_offset = vec![u32::MAX; BLOCK_NUM_PER_CHUNK];
-        let instance: Vec<Instance> = vec![Instance::default(); BLOCK_NUM_PER_CHUNK];
-        let offset_top = 0;
         Self {
             data,
-            //is_dirty,
-            instance,
-            coord_to_offset,
-            offset_top,
+            is_dirty: false,
+            mesh: ChunkMeshParts::default(),
         }
     }
 
@@ -444,67 +545,25 @@ impl Chunk {
         if x < 0 || y < 0 || z < 0 || x >= CHUNK_SIZE || y >= CHUNK_HEIGHT || z >= CHUNK_SIZE {
             return BLOCK_EMPTY;
         }
-        return self.data.blocks[(x * CHUNK_SIZE * CHUNK_HEIGHT + y * CHUNK_SIZE + z) as usize];
+        self.data.get_block(x, y, z)
     }
 
     pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) {
-        self.data.blocks[(x * CHUNK_SIZE * CHUNK_HEIGHT + y * CHUNK_SIZE + z) as usize] = block;
+        self.data.set_block(x, y, z, block);
     }
 
-    fn save(&self, world_dir: &str, coord: &ChunkCoord) -> anyhow::Result<()> {
-        let path = Path::new(world_dir)
-            .join("chunks")
-            .join(format!("x{}", coord.x))
-            .join(format!("y{}.chunk", coord.z));
-
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).context("创建区块目录失败")?;
-        }
-
-        let encoded = bincode::serialize(&self.data).context("区块序列化失败")?;
-
-        std::fs::write(&path, encoded).context("写入区块失败")?;
-
-        Ok(())
+    //y所在的分段是否全空
+    pub fn is_section_empty(&self, y: i32) -> bool {
+        self.data.is_section_empty((y / SECTION_HEIGHT) as usize)
     }
 
-    fn load(world_dir: &str, coord: &ChunkCoord) -> anyhow::Result<Option<ChunkData>> {
-        let path = Path::new(world_dir)
-            .join("chunks")
-            .join(format!("x{}", coord.x))
-            .join(format!("y{}.chunk", coord.z));
-
-        //区块不存在
-        if !path.exists() {
-            return Ok(None);
-        }
-
-        let data = std::fs::read(&path).context("读取区块文件失败")?;
-
-        let chunk: ChunkData = bincode::deserialize(&data).context("解析区块数据失败")?;
-
-        if chunk.blocks.len() != BLOCK_NUM_PER_CHUNK {
-            anyhow::bail!("区块数据损坏：方块数量不匹配");
-        }
+    //区块保存在世界目录下的区域文件中
+    fn save(&self, world_dir: &Path, coord: &ChunkCoord) -> anyhow::Result<()> {
+        region::save_chunk(world_dir, coord, &self.data)
+    }
 
-        Ok(Some(chunk))
-    }
-    pub fn has_any_visible_face(&self, x: i32, y: i32, z: i32) -> bool {
-        // 检查六个相邻位置
-        [
-            (x + 1, y, z),
-            (x - 1, y, z),
-            (x, y + 1, z),
-            (x, y - 1, z),
-            (x, y, z + 1),
-            (x, y, z - 1),
-        ]
-        .iter()
-        //any()遍历检查是否有true，否则返回false
-        .any(|(nx, ny, nz)| {
-            // 相邻方块为透明，则面可见
-            self.get_block(*nx, *ny, *nz).tp.is_transparent()
-        })
+    pub(crate) fn load(world_dir: &Path, coord: &ChunkCoord) -> anyhow::Result<Option<ChunkData>> {
+        region::load_chunk(world_dir, coord)
     }
 }
 
@@ -518,7 +577,9 @@ pub struct RealmData {
     pub center_chunk_pos: ChunkCoord,
     chunk_rad: i32,
 
-    pub name: &'static str,
+    pub name: String,
+    //世界存档目录 区块保存在其下的region目录中
+    pub world_dir: PathBuf,
 
     pub seed: u32,
 }
@@ -538,7 +599,8 @@ impl RealmData {
 
         let center_chunk_pos = ChunkCoord { x: 0, z: 0 };
 
-        let name = "./data/worlds/default_name_1";
+        let name = "default_name_1".to_string();
+        let world_dir = world_dir_of(&name);
 
         let chunk_rad: i32 = INIT_CHUNK_RAD;
         if chunk_rad < 0 {
@@ -547,7 +609,7 @@ impl RealmData {
 
         let chunk_map: HashMap<ChunkCoord, Chunk> = HashMap::new();
 
-        let seed = 2025318;
+        let seed = DEFAULT_SEED;
         //init chunk
         //Self::load_all_chunk(
         //    chunk_rad as i32,
@@ -568,11 +630,21 @@ impl RealmData {
             is_wf_visible,
             center_chunk_pos,
             name,
+            world_dir,
             chunk_rad,
             seed,
         }
     }
 
+    pub fn chunk_rad(&self) -> i32 {
+        self.chunk_rad
+    }
+
+    //只修改半径，加载和卸载由Realm::set_render_distance完成
+    pub fn set_chunk_rad(&mut self, chunk_rad: i32) {
+        self.chunk_rad = chunk_rad.clamp(MIN_CHUNK_RAD, MAX_CHUNK_RAD);
+    }
+
     pub fn get_block(&self, absolute_coord: Point3<i32>) -> Block {
         let mut x = absolute_coord.x;
         let y = absolute_coord.y;
@@ -628,66 +700,97 @@ impl RealmData {
     //    }
     //}
 
-    fn save_chunk(&self, coord: &ChunkCoord) {
-        match self.chunk_map.get(coord).unwrap().save(&self.name, coord) {
-            Ok(()) => {}
-            Err(e) => {
-                eprintln!("区块保存错误:{}", e);
-                panic!("区块保存错误");
+    //返回方块坐标是否合法
+    pub fn set_block(&mut self, abs_coord: Point3<i32>, block: Block) -> bool {
+        let chunk_coord = get_chunk_coord(abs_coord.x, abs_coord.z);
+
+        // 检查区块是否存在
+        if let Some(chunk) = self.chunk_map.get_mut(&chunk_coord) {
+            let local_coord = get_local_coord(abs_coord);
+
+            // 检查 y 坐标是否在有效范围内
+            if abs_coord.y >= 0 && abs_coord.y < CHUNK_HEIGHT {
+                chunk.set_block(local_coord.x, local_coord.y, local_coord.z, block);
+
+                // 标记区块为需要保存状态
+                chunk.is_dirty = true;
+                return true;
             }
         }
+        false
     }
 
-    //#[allow(unused)]
-    pub fn debug_print(chunk: &Chunk) {
-        for x in 0..chunk.offset_top {
-            println!("instance:{:?}", chunk.instance[x as usize]);
+    //仅保存被修改过的区块，保存失败时保留脏标记以便之后重试
+    pub fn save_chunk(&mut self, coord: &ChunkCoord) {
+        let Some(chunk) = self.chunk_map.get_mut(coord) else {
+            return;
+        };
+        if !chunk.is_dirty {
+            return;
         }
-    }
-
-    pub fn load_all_instance(&mut self) {
-        let coords = self.chunk_map.keys().cloned().collect::<Vec<ChunkCoord>>();
-        for coord in coords {
-            self.create_instance(&coord);
+        match chunk.save(&self.world_dir, coord) {
+            Ok(()) => chunk.is_dirty = false,
+            Err(e) => eprintln!("区块保存错误:{:?}", e),
         }
     }
 
-    //仅为创建可见方块创建实例
-    pub fn create_instance(&mut self, chunk_coord: &ChunkCoord) {
-        let mut index = 0u32;
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_HEIGHT {
-                for z in 0..CHUNK_SIZE {
-                    let block = self.chunk_map.get(chunk_coord).unwrap().get_block(x, y, z);
-                    if block.tp != BlockType::Empty {
-                        let abs_coord = Self::relative_to_absolute(chunk_coord, x, y, z);
-                        if self.has_any_visible_face(abs_coord.x, abs_coord.y, abs_coord.z) {
-                            let chunk = self.chunk_map.get_mut(chunk_coord).unwrap();
-                            chunk.instance[index as usize] = Instance {
-                                position: Self::relative_to_absolute_array(chunk_coord, x, y, z),
-                                block_type: block.tp as u32,
-                            };
-                            chunk.coord_to_offset[Self::relative_to_index(x, y, z)] = index;
-                            chunk.offset_top += 1;
-                            index += 1;
-                        }
-                    }
-                }
+    //保存所有已加载的脏区块，返回成功保存的数量
+    pub fn save_all_chunks(&mut self) -> usize {
+        let dirty_coords: Vec<ChunkCoord> = self
+            .chunk_map
+            .iter()
+            .filter(|(_, chunk)| chunk.is_dirty)
+            .map(|(coord, _)| *coord)
+            .collect();
+
+        let mut saved = 0;
+        for coord in dirty_coords {
+            self.save_chunk(&coord);
+            if !self.chunk_map[&coord].is_dirty {
+                saved += 1;
             }
         }
+        saved
     }
 
-    pub fn relative_to_absolute_array(
-        chunk_coord: &ChunkCoord,
-        x: i32,
-        y: i32,
-        z: i32,
-    ) -> [f32; 3] {
-        [
-            (chunk_coord.x * CHUNK_SIZE + x) as f32,
-            y as f32,
-            (chunk_coord.z * CHUNK_SIZE + z) as f32,
-        ]
+    //区块边界外的方块从相邻区块读取，相邻区块没有加载时视为空
+    pub fn remesh_chunk(&mut self, chunk_coord: &ChunkCoord) {
+        let Some(chunk) = self.chunk_map.get(chunk_coord) else {
+            return;
+        };
+        let mesh = chunk_mesher::mesh_chunk(chunk, chunk_coord, |x, y, z| {
+            self.get_block(Self::relative_to_absolute(chunk_coord, x, y, z))
+        });
+        self.chunk_map.get_mut(chunk_coord).unwrap().mesh = mesh;
+    }
+
+    //只重新生成一个侧面，border为BORDERS中的下标
+    pub fn update_border(&mut self, chunk_coord: &ChunkCoord, border: usize) {
+        let Some(chunk) = self.chunk_map.get(chunk_coord) else {
+            return;
+        };
+        let mesh = chunk_mesher::mesh_border(chunk, chunk_coord, border, |x, y, z| {
+            self.get_block(Self::relative_to_absolute(chunk_coord, x, y, z))
+        });
+        self.chunk_map.get_mut(chunk_coord).unwrap().mesh.borders[border] = mesh;
+    }
+
+    /*
+     * 区块加入后，它和已加载的相邻区块之间的侧面要重新判断是否露出
+     * 两边都要更新，返回网格有变化的区块
+     */
+    pub fn connect_chunk(&mut self, chunk_coord: &ChunkCoord) -> Vec<ChunkCoord> {
+        let mut changed = vec![*chunk_coord];
+        for (border, (_, dx, dz)) in BORDERS.iter().enumerate() {
+            let adjacent = ChunkCoord::new(chunk_coord.x + dx, chunk_coord.z + dz);
+            if !self.chunk_map.contains_key(&adjacent) {
+                continue;
+            }
+            self.update_border(chunk_coord, border);
+            self.update_border(&adjacent, chunk_mesher::opposite_border(border));
+            changed.push(adjacent);
+        }
+        changed
     }
 
     pub fn relative_to_absolute(chunk_coord: &ChunkCoord, x: i32, y: i32, z: i32) -> Point3<i32> {
@@ -701,44 +804,21 @@ impl RealmData {
     pub fn relative_to_index(x: i32, y: i32, z: i32) -> usize {
         (x * CHUNK_SIZE * CHUNK_HEIGHT + y * CHUNK_SIZE + z) as usize
     }
-
-    // 检查方块是否有至少一个可见面
-    fn has_any_visible_face(&self, x: i32, y: i32, z: i32) -> bool {
-        // 检查六个相邻位置
-        [
-            (x + 1, y, z),
-            (x - 1, y, z),
-            (x, y + 1, z),
-            (x, y - 1, z),
-            (x, y, z + 1),
-            (x, y, z - 1),
-        ]
-        .iter()
-        //any()遍历检查是否有true，否则返回false
-        .any(|(nx, ny, nz)| {
-            // 相邻方块为透明，则面可见
-            self.get_block(Point3::new(*nx, *ny, *nz))
-                .tp
-                .is_transparent()
-        })
-    }
 }
 
 pub struct RenderResources {
     //pub wf_vertices: Vec<WireframeVertex>,
     pub wf_vertex_buffer: Buffer,
     pub wf_index_buffer: Buffer,
-    pub instance_buffers: HashMap<ChunkCoord, Buffer>,
-    pub block_vertex_buffer: Buffer,
-    pub block_index_buffer: Buffer,
-    pub wf_uniform_buffer: Buffer,
+    //所有区块的网格
+    pub chunk_meshes: ChunkMeshBuffer,
+    //方块图标使用，世界中的方块把纹理层写在顶点里
     pub block_materials_buffer: Buffer,
     pub block_materials_bind_group: BindGroup,
-    pub block_materials_bind_group_layout: BindGroupLayout,
     render_pipeline: RenderPipeline,
+    //水等半透明的面，在不透明的面之后绘制
+    translucent_pipeline: RenderPipeline,
     pub diffuse_bind_group: BindGroup,
-    pub texture_bind_group_layout: BindGroupLayout,
-    pub render_pipeline_layout: PipelineLayout,
 }
 impl RenderResources {
     fn new(
@@ -747,11 +827,6 @@ impl RenderResources {
         camera_bind_group_layout: &BindGroupLayout,
     ) -> Self {
         let device = &basic_config.device;
-        let block_vertex_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
-            label: Some("block vertex buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: BufferUsages::VERTEX,
-        });
 
         let wf_vertices = generate_wf_vertices();
 
@@ -767,47 +842,24 @@ impl RenderResources {
             usage: BufferUsages::INDEX,
         });
 
-        let wf_uniform_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
-            label: Some("Wireframe uniform buffer"),
-            contents: bytemuck::bytes_of(&data.wf_uniform),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
-        });
-
-        let instance_buffers = Self::init_instance_buffers(device, data);
-
-        let block_index_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
-            label: Some("block index buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: BufferUsages::INDEX,
-        });
+        let mut chunk_meshes = ChunkMeshBuffer::new();
+        Self::init_chunk_meshes(&mut chunk_meshes, data);
 
-        let mut block_materials: Vec<Material> = vec![Material::new(0); BLOCK_NUM * 6];
-        for (i, block) in data.all_block.iter().enumerate() {
-            for face in 0..6 {
-                block_materials[i * 6 + face] = Material::new(block.tex_offset[face]);
-            }
-        }
+        //着色器按 方块编号*6+面 读取纹理层，数组长度随方块数量变化
+        let block_materials: Vec<u32> = data
+            .all_block
+            .iter()
+            .flat_map(|block| block.tex_offset)
+            .collect();
 
         let block_materials_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
             label: Some("block materials buffer"),
             contents: bytemuck::cast_slice(&block_materials),
-            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
         });
 
         let block_materials_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("Block materials bind group layout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
+            Self::create_block_materials_bind_group_layout(device);
 
         let block_materials_bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("block materials bind group"),
@@ -826,34 +878,15 @@ impl RenderResources {
 
         //let num_indices = realm::INDICES.len() as u32;
 
-        let diffuse_texture =
-            texture::Texture::load_blocks("res/texture", &basic_config.device, &basic_config.queue)
-                .unwrap();
+        let diffuse_texture = texture::Texture::load_blocks(
+            block_registry::TEXTURE_DIR,
+            block_registry::registry().textures(),
+            &basic_config.device,
+            &basic_config.queue,
+        )
+        .unwrap();
 
-        let texture_bind_group_layout =
-            basic_config
-                .device
-                .create_bind_group_layout(&BindGroupLayoutDescriptor {
-                    label: Some("Texture bind group layout"),
-                    entries: &[
-                        BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: ShaderStages::FRAGMENT,
-                            ty: BindingType::Texture {
-                                sample_type: TextureSampleType::Float { filterable: true },
-                                view_dimension: TextureViewDimension::D2Array,
-                                multisampled: false,
-                            },
-                            count: None,
-                        },
-                        BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: ShaderStages::FRAGMENT,
-                            ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                            count: None,
-                        },
-                    ],
-                });
+        let texture_bind_group_layout = Self::create_texture_bind_group_layout(device);
 
         let diffuse_bind_group =
             basic_config
@@ -875,35 +908,80 @@ impl RenderResources {
 
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("First render pipeline layout"),
-            bind_group_layouts: &[
-                &camera_bind_group_layout,
-                &texture_bind_group_layout,
-                &block_materials_bind_group_layout,
-            ],
+            bind_group_layouts: &[camera_bind_group_layout, &texture_bind_group_layout],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-            label: Some("First render pipeline"),
-            layout: Some(&render_pipeline_layout),
+        let render_pipeline = Self::create_chunk_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            basic_config.config.format,
+            false,
+        );
+        let translucent_pipeline = Self::create_chunk_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            basic_config.config.format,
+            true,
+        );
+        //render_pipeline和buffer创建完成
+
+        Self {
+            wf_index_buffer,
+            wf_vertex_buffer,
+            chunk_meshes,
+            block_materials_buffer,
+            block_materials_bind_group,
+            diffuse_bind_group,
+            render_pipeline,
+            translucent_pipeline,
+        }
+    }
+
+    /*
+     * 不透明的面写入深度，不混合
+     * 半透明的面和已有的颜色混合，不写入深度，后画的水面不会挡住先画的水面
+     * 从水下也能看到水面，所以不剔除背面
+     */
+    fn create_chunk_pipeline(
+        device: &Device,
+        layout: &PipelineLayout,
+        shader: &ShaderModule,
+        format: TextureFormat,
+        translucent: bool,
+    ) -> RenderPipeline {
+        let (label, entry_point, blend) = if translucent {
+            (
+                "Translucent render pipeline",
+                "fs_translucent",
+                BlendState::ALPHA_BLENDING,
+            )
+        } else {
+            ("First render pipeline", "fs_main", BlendState::REPLACE)
+        };
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
             vertex: VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc(), Instance::desc()],
+                buffers: &[ChunkVertex::desc()],
                 compilation_options: PipelineCompilationOptions::default(),
             },
             primitive: PrimitiveState {
                 topology: PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 front_face: FrontFace::Cw,
-                cull_mode: Some(Face::Back),
+                cull_mode: (!translucent).then_some(Face::Back),
                 unclipped_depth: false,
                 polygon_mode: PolygonMode::Fill,
                 conservative: false,
             },
             depth_stencil: Some(wgpu::DepthStencilState {
                 format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
+                depth_write_enabled: !translucent,
                 depth_compare: wgpu::CompareFunction::Less,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
@@ -914,66 +992,74 @@ impl RenderResources {
                 alpha_to_coverage_enabled: false,
             },
             fragment: Some(FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
+                module: shader,
+                entry_point: Some(entry_point),
                 targets: &[Some(ColorTargetState {
-                    format: basic_config.config.format,
-                    blend: Some(BlendState::REPLACE),
+                    format,
+                    blend: Some(blend),
                     write_mask: ColorWrites::ALL,
                 })],
                 compilation_options: PipelineCompilationOptions::default(),
             }),
             multiview: None,
             cache: None,
-        });
-        //render_pipeline和buffer创建完成
-
-        Self {
-            block_vertex_buffer,
-            block_index_buffer,
-            wf_index_buffer,
-            wf_vertex_buffer,
-            wf_uniform_buffer,
-            instance_buffers,
-            block_materials_buffer,
-            block_materials_bind_group,
-            block_materials_bind_group_layout,
-            diffuse_bind_group,
-            render_pipeline,
-            texture_bind_group_layout,
-            render_pipeline_layout,
-        }
+        })
     }
 
-    pub fn init_instance_buffers(device: &Device, data: &RealmData) -> HashMap<ChunkCoord, Buffer> {
-        let mut instance_buffers = HashMap::new();
+    pub fn init_chunk_meshes(chunk_meshes: &mut ChunkMeshBuffer, data: &RealmData) {
+        chunk_meshes.clear();
         for (coord, chunk) in &data.chunk_map {
-            instance_buffers.insert(
-                *coord,
-                device.create_buffer_init(&util::BufferInitDescriptor {
-                    label: Some("Block buffer"),
-                    contents: bytemuck::cast_slice(&chunk.instance),
-                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-                }),
-            );
+            chunk_meshes.insert(*coord, &chunk.mesh.combined());
         }
-        instance_buffers
     }
 
-    pub fn insert_instance_buffer(
-        &mut self,
-        device: &Device,
-        coord: &ChunkCoord,
-        instance: &[Instance],
-    ) {
-        self.instance_buffers.insert(
-            *coord,
-            device.create_buffer_init(&util::BufferInitDescriptor {
-                label: Some("Block buffer"),
-                contents: bytemuck::cast_slice(&instance),
-                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-            }),
-        );
+    /*
+     * 布局在进入世界之前就需要(UI和线框管线)
+     * wgpu会合并内容相同的布局，所以分别创建的布局可以通用
+     */
+    pub fn create_block_materials_bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Block materials bind group layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    pub fn create_texture_bind_group_layout(device: &Device) -> BindGroupLayout {
+        device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Texture bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    //写入在下一次flush时才提交到GPU
+    pub fn insert_chunk_mesh(&mut self, coord: &ChunkCoord, mesh: &ChunkMesh) {
+        self.chunk_meshes.insert(*coord, mesh);
     }
 }
 
@@ -981,50 +1067,72 @@ pub struct Realm {
     pub data: RealmData,
     pub render_res: RenderResources,
     chunk_generator: ChunkGenerator,
-    pub is_loading: bool,
-    is_init: bool,
-    chunk_update_timer: f64,
-    chunk_check_interval: f64,
-    pub pre_dx: i32,
-    pub pre_dz: i32,
-    pre_center_chunk_pos: ChunkCoord,
+    autosave_timer: f64,
+    //世界元数据，保存时写入level.dat
+    pub level: LevelData,
+    //中心或视距变化后需要重新排队
+    is_queue_dirty: bool,
+    //网格有变化、需要重新上传到GPU的区块
+    upload_chunks: HashSet<ChunkCoord>,
+    //这一帧视锥体内的区块，每帧更新摄像机后重新计算
+    visible_chunks: Vec<MeshSlot>,
+    //其中有半透明面的区块，从远到近排列
+    translucent_chunks: Vec<MeshSlot>,
+    pub culled_chunks: usize,
 }
 impl Realm {
-    pub fn new(basic_config: &BasicConfig, camera_bind_group_layout: &BindGroupLayout) -> Self {
+    //打开世界目录，没有level.dat时用默认种子创建新世界
+    pub fn new(
+        basic_config: &BasicConfig,
+        camera_bind_group_layout: &BindGroupLayout,
+        world_name: &str,
+        worker_threads: usize,
+    ) -> anyhow::Result<Self> {
         let mut data = RealmData::new();
-        data.load_all_instance();
-
-        let render_res = RenderResources::new(basic_config, &data, camera_bind_group_layout);
-        let chunk_generator = ChunkGenerator::new(1);
-
-        let is_loading = true;
+        data.world_dir = world_dir_of(world_name);
+        let level = LevelData::load_or_create(&data.world_dir, world_name, DEFAULT_SEED)?;
+        data.name = level.name.clone();
+        data.seed = level.seed;
+        //从玩家上次所在的区块开始加载
+        data.center_chunk_pos = get_chunk_coord(
+            level.player.position[0].floor() as i32,
+            level.player.position[2].floor() as i32,
+        );
 
-        let chunk_check_interval = 0.05; // 0.5秒检查一次
+        match region::migrate_legacy_chunks(&data.world_dir) {
+            Ok(0) => {}
+            Ok(migrated) => println!("迁移旧版区块文件:{}", migrated),
+            Err(e) => eprintln!("旧版区块迁移失败:{:?}", e),
+        }
 
-        let pre_center_chunk_pos = data.center_chunk_pos;
+        let render_res = RenderResources::new(basic_config, &data, camera_bind_group_layout);
+        let chunk_generator = ChunkGenerator::new(
+            worker_threads,
+            data.world_dir.clone(),
+            level.generator.clone(),
+        );
 
-        Self {
+        Ok(Self {
             data,
             render_res,
             chunk_generator,
-            is_loading,
-            chunk_update_timer: 0.0,
-            chunk_check_interval,
-            pre_dx: 0,
-            pre_dz: 0,
-            pre_center_chunk_pos,
-            is_init: true,
-        }
+            autosave_timer: 0.0,
+            level,
+            is_queue_dirty: true,
+            upload_chunks: HashSet::new(),
+            visible_chunks: Vec::new(),
+            translucent_chunks: Vec::new(),
+            culled_chunks: 0,
+        })
     }
 
     #[allow(unused)]
-    fn generate_terrian_test(
+    pub(crate) fn generate_terrian_test(
         chunk_map: &mut HashMap<ChunkCoord, Chunk>,
         chunk_coord: &ChunkCoord,
         seed: u32,
     ) {
-        let blocks = vec![BLOCK_EMPTY; BLOCK_NUM_PER_CHUNK];
-        let mut chunk = Chunk::new(ChunkData { blocks });
+        let mut chunk = Chunk::new(ChunkData::new());
 
         for x in 0..CHUNK_SIZE {
             for y in 0..CHUNK_HEIGHT {
@@ -1057,613 +1165,239 @@ impl Realm {
         return 90;
     }
 
-    //区块初始化
-    fn init_chunk(
-        chunk_pos: &ChunkCoord,
-        chunk_map: &mut HashMap<ChunkCoord, Chunk>,
-        world_dir: &str,
-        seed: u32,
-        chunk_generator: &ChunkGenerator,
-    ) -> bool {
-        match chunk_map.get(chunk_pos) {
-            //存在就不要重复添加
-            Some(_) => {
-                return true;
-            }
-
-            None => {
-                if chunk_generator.is_chunk_pending(chunk_pos) {
-                    return false;
-                }
-                match Chunk::load(world_dir, chunk_pos) {
-                    //不存在则尝试读取
-                    Ok(Some(data)) => {
-                        let chunk = Chunk::new(data);
-                        chunk_map.insert(*chunk_pos, chunk);
-                        return true;
-                    }
-
-                    //读取失败则生成
-                    Ok(None) => {
-                        chunk_generator.request_chunk(*chunk_pos, seed);
-                        return false;
-                    }
-                    //读取错误
-                    Err(e) => {
-                        eprintln!("区块加载错误：{}", e);
-                        //区块有错先崩溃
-                        panic!("区块加载错误");
-                    }
-                };
-            }
-        }
-    }
-
-    //加载区块同时加载进GPU资源中
-    fn load_chunk(&mut self, device: &Device, new_chunk_pos: &ChunkCoord) -> bool {
-        let loaded = Self::init_chunk(
-            new_chunk_pos,
-            &mut self.data.chunk_map,
-            self.data.name,
-            self.data.seed,
-            &self.chunk_generator,
-        );
-
-        if loaded {
-            self.data.create_instance(new_chunk_pos);
-            self.render_res.insert_instance_buffer(
-                device,
-                new_chunk_pos,
-                &self.data.chunk_map.get(new_chunk_pos).unwrap().instance,
-            );
-        }
-        loaded
-    }
-
-    fn process_generated_chunks(&mut self, device: &Device) {
-        let generated_chunks = self.chunk_generator.get_generated_chunks();
+    /*
+     * 离开范围后才加载完成的区块直接丢弃
+     * 已经加载的区块可能被修改过，不能被重复请求的结果覆盖
+     */
+    fn process_generated_chunks(&mut self) {
+        let generated_chunks = self.chunk_generator.get_generated_chunks(CHUNK_LOAD_BUDGET);
         for respose in generated_chunks {
+            if !self.is_in_keep_range(&respose.coord)
+                || self.data.chunk_map.contains_key(&respose.coord)
+            {
+                continue;
+            }
             self.data.chunk_map.insert(respose.coord, respose.chunk);
-            //self.data.create_instance(&respose.coord);
-            self.render_res.insert_instance_buffer(
-                device,
-                &respose.coord,
-                &self.data.chunk_map.get(&respose.coord).unwrap().instance,
-            );
+            let changed = self.data.connect_chunk(&respose.coord);
+            self.upload_chunks.extend(changed);
             //println!("并行加载区块:{:?}", respose.coord);
         }
     }
 
+    /*
+     * 卸载前先保存被修改过的区块
+     * 相邻区块朝向它的侧面保持隐藏，它们在加载范围的边缘，看不到这一面
+     */
     fn unload_chunk(&mut self, chunk_pos: &ChunkCoord) {
-        self.render_res.instance_buffers.remove(chunk_pos);
+        self.data.save_chunk(chunk_pos);
+        self.render_res.chunk_meshes.remove(chunk_pos);
+        self.upload_chunks.remove(chunk_pos);
         self.data.chunk_map.remove(chunk_pos);
         //println!("卸载区块:{:?}", chunk_pos);
     }
 
-    pub fn update(&mut self, player_pos: &Point3<f32>, device: &Device, dt: f64) {
-        self.process_generated_chunks(device);
-
-        //控制轮询频率
-        if self.is_loading {
-            if self.chunk_update_timer > self.chunk_check_interval {
-                self.chunk_update_timer = 0.0;
-
-                //不只更新边界
-                if self.pre_dx.abs() > 1 || self.pre_dz.abs() > 1 || self.is_init {
-                    //这里要检查的是新的中心区块
-                    if self.check_all_chunk_loaded(&ChunkCoord {
-                        x: self.data.center_chunk_pos.x + self.pre_dx,
-                        z: self.data.center_chunk_pos.z + self.pre_dz,
-                    }) {
-                        self.is_loading = false;
-                        self.data.center_chunk_pos = ChunkCoord::new(
-                            self.data.center_chunk_pos.x + self.pre_dx,
-                            self.data.center_chunk_pos.z + self.pre_dz,
-                        );
-                        self.unload_all_redundant_chunk();
-
-                        if self.is_init {
-                            self.is_init = false;
-                        }
-
-                        println!(
-                            "更新所有区块:offset:{},{}, 现在center_chunk_pos=({},{})",
-                            self.pre_dx,
-                            self.pre_dz,
-                            self.data.center_chunk_pos.x,
-                            self.data.center_chunk_pos.z
-                        );
-                    }
-
-                //仅更新边界
-                } else {
-                    if self.check_border_loaded(self.pre_dx, self.pre_dz) {
-                        println!(
-                            "更新边界前中心:({},{})",
-                            self.data.center_chunk_pos.x, self.data.center_chunk_pos.z
-                        );
-                        self.is_loading = false;
-                        self.data.center_chunk_pos = ChunkCoord::new(
-                            self.data.center_chunk_pos.x + self.pre_dx,
-                            self.data.center_chunk_pos.z + self.pre_dz,
-                        );
-                        self.unload_border_chunk();
-                        println!(
-                            "更新边界:offset:{},{} 现在cenetr_chunk_pos=({},{})",
-                            self.pre_dx,
-                            self.pre_dz,
-                            self.data.center_chunk_pos.x,
-                            self.data.center_chunk_pos.z
-                        );
-                    }
-                }
-
-                //已经在加载了，如果加载时间比较短先不更新
-            } else {
-                self.chunk_update_timer += dt;
-                return;
-            }
-
-        //如果没有区块正在加载
-        } else {
-            let new_coord = get_chunk_coord(player_pos.x as i32, player_pos.z as i32);
-            let dx = new_coord.x - self.data.center_chunk_pos.x;
-            let dz = new_coord.z - self.data.center_chunk_pos.z;
-            //则检查是否需要加载
-            if dx == 0 && dz == 0 {
-                return;
-            }
-
-            //走出中心区块，而且相应的区块没有加载，则加载
-            if dx.abs() > 1 || dz.abs() > 1 {
-                if !self.check_all_chunk_loaded(&new_coord) {
-                    self.is_loading = true;
-                    self.load_all_chunk(device, &new_coord);
-
-                    println!("加载所有区块:offset:{},{}", dx, dz);
-                } else {
-                    self.data.center_chunk_pos = new_coord;
-
-                    //一切正常的话，以下应该不会执行
-                    //self.unload_all_redundant_chunk();
-                }
-            } else {
-                if !self.check_border_loaded(dx, dz) {
-                    println!("加载边界:offset:{},{}", dx, dz);
-                    self.is_loading = true;
-                    self.update_helper(dx, dz, device);
-                } else {
-                    self.data.center_chunk_pos = new_coord;
-                    self.unload_border_chunk();
-                }
-            }
-
-            //self.pre_center_chunk_pos = self.data.center_chunk_pos;
-            self.pre_dx = dx;
-            self.pre_dz = dz;
-        }
+    pub fn update(&mut self, player_pos: &Point3<f32>, device: &Device, queue: &Queue, dt: f64) {
+        self.process_generated_chunks();
+        self.update_load_queue(player_pos);
+        self.upload_modified_meshes();
+        self.render_res.chunk_meshes.flush(device, queue);
+        self.level.game_time += dt;
+        self.autosave(dt);
     }
 
-    //两个offset都只有-1, 0, 1三个值
-    //此时的区块中心还没更新
-    fn update_helper(&mut self, x_offset: i32, z_offset: i32, device: &Device) {
-        if z_offset != 0 {
-            for x in -self.data.chunk_rad..=self.data.chunk_rad {
-                //let old_chunk_pos = ChunkCoord::new(
-                //    self.data.center_chunk_pos.x + x,
-                //    self.data.center_chunk_pos.z - self.data.chunk_rad * z_offset,
-                //);
-                let new_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + x + x_offset,
-                    self.data.center_chunk_pos.z + (self.data.chunk_rad + 1) * z_offset,
-                );
-                //卸载和加载顺序不能反
-                self.load_chunk(device, &new_chunk_pos);
-                //self.unload_chunk(&old_chunk_pos);
-            }
-        }
-        if x_offset != 0 {
-            for z in -self.data.chunk_rad..=self.data.chunk_rad {
-                //let old_chunk_pos = ChunkCoord::new(
-                //    self.data.center_chunk_pos.x - self.data.chunk_rad * x_offset,
-                //    self.data.center_chunk_pos.z + z,
-                //);
-                let new_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + (self.data.chunk_rad + 1) * x_offset,
-                    self.data.center_chunk_pos.z + z + z_offset,
-                );
-                self.load_chunk(device, &new_chunk_pos);
-                //self.unload_chunk(&old_chunk_pos);
-            }
-        }
+    //已加载的区块在半径+1以内保留，避免在边界来回走动时反复加载
+    fn is_in_keep_range(&self, coord: &ChunkCoord) -> bool {
+        let keep = self.data.chunk_rad + 1;
+        coord.distance_squared(&self.data.center_chunk_pos) <= keep * keep
     }
 
-    fn check_all_chunk_loaded(&self, new_coord: &ChunkCoord) -> bool {
-        for x in -self.data.chunk_rad..=self.data.chunk_rad {
-            for z in -self.data.chunk_rad..=self.data.chunk_rad {
-                let chunk_pos = ChunkCoord::new(new_coord.x + x, new_coord.z + z);
-                if !self.data.chunk_map.contains_key(&chunk_pos) {
-                    return false;
-                }
-            }
+    /*
+     * 玩家进入新的区块或视距变化时重新排队
+     * 卸载范围外的区块，取消还没开始的请求，缺少的区块交给工作线程读取或生成
+     * 工作线程按距离由近到远处理
+     */
+    fn update_load_queue(&mut self, player_pos: &Point3<f32>) {
+        let center = get_chunk_coord(player_pos.x.floor() as i32, player_pos.z.floor() as i32);
+        if center == self.data.center_chunk_pos && !self.is_queue_dirty {
+            return;
         }
-        true
-    }
-
-    fn unload_border_chunk(&mut self) {
-        if self.data.chunk_map.contains_key(&ChunkCoord::new(
-            self.data.center_chunk_pos.x,
-            self.data.center_chunk_pos.z - self.data.chunk_rad - 2,
-        )) {
-            for x in -self.data.chunk_rad - 1..=self.data.chunk_rad + 1 {
-                let old_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + x,
-                    self.data.center_chunk_pos.z - (self.data.chunk_rad + 2),
-                );
-                self.unload_chunk(&old_chunk_pos);
-            }
+        self.is_queue_dirty = false;
+        self.data.center_chunk_pos = center;
+
+        let redundant: Vec<ChunkCoord> = self
+            .data
+            .chunk_map
+            .keys()
+            .filter(|coord| !self.is_in_keep_range(coord))
+            .cloned()
+            .collect();
+        for coord in redundant {
+            self.unload_chunk(&coord);
         }
 
-        if self.data.chunk_map.contains_key(&ChunkCoord::new(
-            self.data.center_chunk_pos.x,
-            self.data.center_chunk_pos.z + self.data.chunk_rad + 2,
-        )) {
-            for x in -self.data.chunk_rad - 1..=self.data.chunk_rad + 1 {
-                let old_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + x,
-                    self.data.center_chunk_pos.z + (self.data.chunk_rad + 2),
-                );
-                self.unload_chunk(&old_chunk_pos);
-            }
-        }
-
-        if self.data.chunk_map.contains_key(&ChunkCoord::new(
-            self.data.center_chunk_pos.x + self.data.chunk_rad + 2,
-            self.data.center_chunk_pos.z,
-        )) {
-            for z in -self.data.chunk_rad - 1..=self.data.chunk_rad + 1 {
-                let old_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + (self.data.chunk_rad + 2),
-                    self.data.center_chunk_pos.z + z,
-                );
-                self.unload_chunk(&old_chunk_pos);
+        self.chunk_generator
+            .retarget(center, self.data.chunk_rad + 1);
+        for coord in chunks_in_radius(center, self.data.chunk_rad) {
+            if !self.data.chunk_map.contains_key(&coord) {
+                self.chunk_generator.request_chunk(coord, self.data.seed);
             }
         }
+    }
 
-        if self.data.chunk_map.contains_key(&ChunkCoord::new(
-            self.data.center_chunk_pos.x - self.data.chunk_rad - 2,
-            self.data.center_chunk_pos.z,
-        )) {
-            for z in -self.data.chunk_rad - 1..=self.data.chunk_rad + 1 {
-                let old_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x - (self.data.chunk_rad + 2),
-                    self.data.center_chunk_pos.z + z,
-                );
-                self.unload_chunk(&old_chunk_pos);
-            }
-        }
+    //排队中的和正在加载的区块数
+    pub fn worker_stats(&self) -> Vec<WorkerStats> {
+        self.chunk_generator.stats()
     }
 
-    fn unload_all_redundant_chunk(&mut self) {
-        //卸载多余区块
-        self.data.chunk_map.retain(|coord, _| {
-            (coord.x - self.data.center_chunk_pos.x).abs() <= self.data.chunk_rad + 1
-                && (coord.z - self.data.center_chunk_pos.z).abs() <= self.data.chunk_rad + 1
-        });
+    pub fn pending_chunks(&self) -> usize {
+        self.chunk_generator.pending_count()
     }
 
-    fn load_all_chunk(&mut self, device: &Device, new_coord: &ChunkCoord) {
-        for x in -self.data.chunk_rad..=self.data.chunk_rad {
-            for z in -self.data.chunk_rad..=self.data.chunk_rad {
-                let chunk_pos = ChunkCoord::new(new_coord.x + x, new_coord.z + z);
-                self.load_chunk(device, &chunk_pos);
-            }
-        }
+    //运行时修改视距，下一次更新时加载或卸载
+    pub fn set_render_distance(&mut self, chunk_rad: i32) {
+        self.data.set_chunk_rad(chunk_rad);
+        self.is_queue_dirty = true;
     }
 
-    fn check_border_loaded(&self, x_offset: i32, z_offset: i32) -> bool {
-        if z_offset != 0 {
-            for x in -self.data.chunk_rad..=self.data.chunk_rad {
-                let new_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + x + x_offset,
-                    self.data.center_chunk_pos.z + (self.data.chunk_rad + 1) * z_offset,
-                );
-                if !self.data.chunk_map.contains_key(&new_chunk_pos) {
-                    return false;
-                }
-            }
+    //定期保存脏区块，防止程序异常退出时丢失修改
+    fn autosave(&mut self, dt: f64) {
+        self.autosave_timer += dt;
+        if self.autosave_timer < AUTOSAVE_INTERVAL {
+            return;
         }
-        if x_offset != 0 {
-            for z in -self.data.chunk_rad..=self.data.chunk_rad {
-                let new_chunk_pos = ChunkCoord::new(
-                    self.data.center_chunk_pos.x + (self.data.chunk_rad + 1) * x_offset,
-                    self.data.center_chunk_pos.z + z + z_offset,
-                );
-                if !self.data.chunk_map.contains_key(&new_chunk_pos) {
-                    return false;
-                }
-            }
+        self.autosave_timer = 0.0;
+        let saved = self.data.save_all_chunks();
+        if saved > 0 {
+            println!("自动保存区块:{}", saved);
         }
-        true
+        self.save_level();
     }
 
-    pub fn reload_all_chunk(&mut self, new_coord: &ChunkCoord, device: &Device) {
-        // 卸载所有区块
-        //self.data.chunk_map.clear();
-        //self.render_res.instance_buffers.clear();
-        println!("重新加载所有区块");
-
-        // 卸载多余区块
-        self.data.chunk_map.retain(|coord, _| {
-            (coord.x - new_coord.x).abs() > self.data.chunk_rad + 1
-                || (coord.z - new_coord.z).abs() > self.data.chunk_rad + 1
-        });
+    //保存所有修改过的区块和世界信息
+    pub fn save_world(&mut self) {
+        let saved = self.data.save_all_chunks();
+        println!("保存世界{}的区块:{}", self.data.name, saved);
+        self.save_level();
+    }
 
-        // 请求所有需要的区块
-        for relative_x in -self.data.chunk_rad..=self.data.chunk_rad {
-            for relative_z in -self.data.chunk_rad..=self.data.chunk_rad {
-                let x = new_coord.x + relative_x;
-                let z = new_coord.z + relative_z;
-                let coord = ChunkCoord::new(x, z);
-
-                Self::init_chunk(
-                    &coord,
-                    &mut self.data.chunk_map,
-                    self.data.name,
-                    self.data.seed,
-                    &self.chunk_generator,
-                );
-            }
+    fn save_level(&self) {
+        if let Err(e) = self.level.save(&self.data.world_dir) {
+            eprintln!("世界信息保存错误:{:?}", e);
         }
-        self.render_res.instance_buffers =
-            RenderResources::init_instance_buffers(device, &self.data);
     }
 
     //返回位置是否合法
     fn set_block_data(&mut self, abs_coord: Point3<i32>, block: Block) -> bool {
-        let chunk_coord = get_chunk_coord(abs_coord.x, abs_coord.z);
-
-        // 检查区块是否存在
-        if let Some(chunk) = self.data.chunk_map.get_mut(&chunk_coord) {
-            let local_coord = get_local_coord(abs_coord);
-
-            // 检查 y 坐标是否在有效范围内
-            if abs_coord.y >= 0 && abs_coord.y < CHUNK_HEIGHT as i32 {
-                chunk.set_block(local_coord.x, local_coord.y, local_coord.z, block);
+        self.data.set_block(abs_coord, block)
+    }
 
-                // 标记区块为需要更新状态
-                //chunk
-                //    .is_dirty
-                //    .store(true, std::sync::atomic::Ordering::Relaxed);
-                return true;
+    /*
+     * 修改方块后重新生成所在区块的网格
+     * 方块在区块边界上时，相邻区块朝向它的侧面也会变化
+     */
+    pub fn place_block(&mut self, block_coord: Point3<i32>, block: Block) {
+        if !self.set_block_data(block_coord, block) {
+            return;
+        }
+        let chunk_coord = get_chunk_coord(block_coord.x, block_coord.z);
+        self.data.remesh_chunk(&chunk_coord);
+        self.upload_chunks.insert(chunk_coord);
+        for (border, (_, dx, dz)) in BORDERS.iter().enumerate() {
+            let adjacent = get_chunk_coord(block_coord.x + dx, block_coord.z + dz);
+            if adjacent != chunk_coord && self.data.chunk_map.contains_key(&adjacent) {
+                self.data
+                    .update_border(&adjacent, chunk_mesher::opposite_border(border));
+                self.upload_chunks.insert(adjacent);
             }
         }
-        false
     }
 
-    fn get_offset(&self, abs_coord: Point3<i32>) -> Option<u32> {
-        let chunk_coord = get_chunk_coord(abs_coord.x, abs_coord.z);
-        let local_coord = get_local_coord(abs_coord);
-        if let Some(chunk) = self.data.chunk_map.get(&chunk_coord) {
-            return Some(
-                chunk.coord_to_offset
-                    [RealmData::relative_to_index(local_coord.x, local_coord.y, local_coord.z)],
-            );
-        }
-        None
-    }
-
-    fn update_adjacent_block(&mut self, abs_coord: Point3<i32>, queue: &Queue) {
-        //更新相邻方块
-        let x = abs_coord.x;
-        let y = abs_coord.y;
-        let z = abs_coord.z;
-        [
-            (x + 1, y, z),
-            (x - 1, y, z),
-            (x, y + 1, z),
-            (x, y - 1, z),
-            (x, y, z + 1),
-            (x, y, z - 1),
-        ]
-        .iter()
-        .for_each(|(x, y, z)| {
-            if let Some(offset) = self.get_offset(Point3::new(*x, *y, *z)) {
-                //如果offset为MAX，则说明该方块不在缓冲区内，即不可见
-                //又如果方块为非空，则讲此方块插入缓冲区
-                if offset == u32::MAX {
-                    if !self
-                        .data
-                        .get_block(Point3::new(*x, *y, *z))
-                        .tp
-                        .is_transparent()
-                    {
-                        self.update_single_block_buffer(Point3::new(*x, *y, *z), queue);
-                    }
-                }
+    fn upload_modified_meshes(&mut self) {
+        for coord in std::mem::take(&mut self.upload_chunks) {
+            if let Some(chunk) = self.data.chunk_map.get(&coord) {
+                let mesh = chunk.mesh.combined();
+                self.render_res.insert_chunk_mesh(&coord, &mesh);
             }
-        });
+        }
     }
 
-    pub fn place_block(&mut self, block_coord: Point3<i32>, block: Block, queue: &Queue) {
-        if self.set_block_data(block_coord, block) {
-            let chunk_coord = get_chunk_coord(block_coord.x, block_coord.z);
-            let block_offset = get_local_coord(block_coord);
-
-            //offset为选中方块的偏移量
-            let mut offset = self.data.chunk_map[&chunk_coord].coord_to_offset
-                [RealmData::relative_to_index(block_offset.x, block_offset.y, block_offset.z)];
-
-            let mut instance = Instance {
-                position: [
-                    block_coord.x as f32,
-                    block_coord.y as f32,
-                    block_coord.z as f32,
-                ],
-                block_type: block.tp as u32,
-            };
-
-            /*
-             * 如果是空方块则为摧毁方块操作
-             * 则需要更新相邻方块
-             * 且偏移量一定有效，这是在camera摧毁方块操作保证了的！
-             * 同时用缓冲区顶部的数据覆盖当前数据
-             * 同时还需要更新offset_top和coord_to_offset
-             * coord_to_offset需要更新两个位置
-             */
-            if block.tp == BlockType::Empty {
-                //这是最后一个有效实例
-                instance = self.data.chunk_map[&chunk_coord].instance
-                    [(self.data.chunk_map[&chunk_coord].offset_top - 1) as usize];
-
-                let chunk = self.data.chunk_map.get_mut(&chunk_coord).unwrap();
-
-                chunk.offset_top -= 1;
-
-                //用数组末尾的值覆盖要删除的值
-                chunk.instance[(offset) as usize] = instance;
-
-                let move_instance = chunk.instance[chunk.offset_top as usize];
-                let move_coor = get_local_coord(Point3::new(
-                    move_instance.position[0] as i32,
-                    move_instance.position[1] as i32,
-                    move_instance.position[2] as i32,
-                ));
-
-                chunk.coord_to_offset
-                    [RealmData::relative_to_index(move_coor.x, move_coor.y, move_coor.z)] = offset;
-
-                chunk.coord_to_offset[RealmData::relative_to_index(
-                    block_offset.x,
-                    block_offset.y,
-                    block_offset.z,
-                )] = u32::MAX;
-
-                /*
-                 * 以下两个函数调用不能反！！！
-                 * 否则update_adjacent_block原本写入的缓冲
-                 * 可能会被write_buffer覆写！
-                 *
-                 * bug发生在缓冲区最后一个实例是最新实例的情况下
-                 */
-                queue.write_buffer(
-                    &self.render_res.instance_buffers[&chunk_coord],
-                    (offset * std::mem::size_of::<Instance>() as u32) as u64,
-                    bytemuck::bytes_of(&instance),
-                );
-
-                self.update_adjacent_block(block_coord, queue);
-
-                /*
-                 * 方块非空，此时为放置操作
-                 * 偏移量设置为offset_top
-                 *
-                 * 更新:
-                 * offset_top
-                 * coord_to_offset
-                 * instance
-                 */
+    /*
+     * 只绘制包围盒和视锥体相交的区块
+     * 半透明的面按区块包围盒中心到摄像机eye的距离从远到近绘制，近处的水面混合在远处的上面
+     */
+    pub fn cull_chunks(&mut self, frustum: &Frustum, eye: Point3<f32>) {
+        self.visible_chunks.clear();
+        self.culled_chunks = 0;
+        for slot in self.render_res.chunk_meshes.slots().values() {
+            if frustum.intersects_aabb(Point3::from(slot.min), Point3::from(slot.max)) {
+                self.visible_chunks.push(slot.clone());
             } else {
-                let chunk = self.data.chunk_map.get_mut(&chunk_coord).unwrap();
-                offset = chunk.offset_top;
-                chunk.offset_top += 1;
-                chunk.coord_to_offset[RealmData::relative_to_index(
-                    block_offset.x,
-                    block_offset.y,
-                    block_offset.z,
-                )] = offset;
-                chunk.instance[offset as usize] = instance;
-                queue.write_buffer(
-                    &self.render_res.instance_buffers[&chunk_coord],
-                    offset as u64 * std::mem::size_of::<Instance>() as u64,
-                    bytemuck::bytes_of(&instance),
-                );
+                self.culled_chunks += 1;
             }
-
-            /*
-             * 如果offset为MAX，则说明该方块不在缓冲区内
-             * 则为新添加的方块， 其位置位于top_offset， 然后需要递增offset_top
-             */
         }
-    }
-
-    //把单个方块插入缓冲区中 令单个方块可见
-    //假定方块为实体
-    pub fn update_single_block_buffer(&mut self, abs_coord: Point3<i32>, queue: &Queue) {
-        let chunk_coord = get_chunk_coord(abs_coord.x, abs_coord.z);
-        let block_offset = get_local_coord(abs_coord);
-
-        let offset = self.data.chunk_map[&chunk_coord].offset_top;
-
-        let chunk = self.data.chunk_map.get_mut(&chunk_coord).unwrap();
-        let block = chunk.get_block(block_offset.x, block_offset.y, block_offset.z);
-        //更新offset_top和coord_to_offset，不需要更新instance
-        let instance = Instance {
-            position: [abs_coord.x as f32, abs_coord.y as f32, abs_coord.z as f32],
-            block_type: block.tp as u32,
-        };
-
-        chunk.offset_top += 1;
-        chunk.coord_to_offset
-            [RealmData::relative_to_index(block_offset.x, block_offset.y, block_offset.z)] = offset;
-        chunk.instance[offset as usize] = instance;
 
-        //println!(
-        //    "insert instance{:?} to {:?},{:?}",
-        //    instance, abs_coord, offset
-        //);
-        queue.write_buffer(
-            &self.render_res.instance_buffers[&chunk_coord],
-            offset as u64 * std::mem::size_of::<Instance>() as u64,
-            bytemuck::bytes_of(&instance),
+        self.translucent_chunks.clear();
+        self.translucent_chunks.extend(
+            self.visible_chunks
+                .iter()
+                .filter(|slot| !slot.translucent_indices.is_empty())
+                .cloned(),
         );
+        sort_back_to_front(&mut self.translucent_chunks, eye);
     }
 
-    #[allow(unused)]
-    pub fn debug_print(&self) {
-        println!("chunk data:");
-        for x in 0..self.data.chunk_map[&ChunkCoord { x: 0, z: 0 }].offset_top {
-            println!(
-                "{}: {:?}",
-                x,
-                self.data.chunk_map[&ChunkCoord { x: 0, z: 0 }].instance[x as usize]
-            );
-        }
-        println!("chunk coord_to_offset:");
-        let chunk = self.data.chunk_map.get(&ChunkCoord { x: 0, z: 0 }).unwrap();
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_HEIGHT {
-                for z in 0..CHUNK_SIZE {
-                    println!(
-                        "{},{},{}: {}",
-                        x,
-                        y,
-                        z,
-                        chunk.coord_to_offset[RealmData::relative_to_index(x, y, z)]
-                    );
-                }
-            }
-        }
+    pub fn drawn_chunks(&self) -> usize {
+        self.visible_chunks.len()
     }
 
     pub fn draw_realm(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup) {
         render_pass.set_pipeline(&self.render_res.render_pipeline);
         render_pass.set_bind_group(0, camera_bind_group, &[]);
         render_pass.set_bind_group(1, &self.render_res.diffuse_bind_group, &[]);
-        render_pass.set_bind_group(2, &self.render_res.block_materials_bind_group, &[]);
-        render_pass.set_vertex_buffer(0, self.render_res.block_vertex_buffer.slice(..));
-        render_pass.set_index_buffer(
-            self.render_res.block_index_buffer.slice(..),
-            IndexFormat::Uint16,
-        );
 
-        for (coord, chunk) in self.data.chunk_map.iter() {
-            render_pass.set_vertex_buffer(1, self.render_res.instance_buffers[&coord].slice(..));
-            render_pass.draw_indexed(0..INDICES.len() as _, 0, 0..chunk.offset_top as u32);
+        let Some((vertex_buffer, index_buffer)) = self.render_res.chunk_meshes.buffers() else {
+            return;
+        };
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint32);
+        //索引是区块内的，用base_vertex偏移到区块的顶点
+        for slot in &self.visible_chunks {
+            render_pass.draw_indexed(slot.indices.clone(), slot.vertices.start as i32, 0..1);
+        }
+    }
+
+    //在所有不透明的物体之后绘制
+    pub fn draw_translucent(&self, render_pass: &mut RenderPass, camera_bind_group: &BindGroup) {
+        let Some((vertex_buffer, index_buffer)) = self.render_res.chunk_meshes.buffers() else {
+            return;
+        };
+        if self.translucent_chunks.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_res.translucent_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.render_res.diffuse_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint32);
+        for slot in &self.translucent_chunks {
+            render_pass.draw_indexed(
+                slot.translucent_indices.clone(),
+                slot.vertices.start as i32,
+                0..1,
+            );
         }
     }
 }
 
+//按包围盒中心到eye的距离从远到近排序
+fn sort_back_to_front(slots: &mut [MeshSlot], eye: Point3<f32>) {
+    let distance = |slot: &MeshSlot| {
+        let center = Point3::from(slot.min).midpoint(Point3::from(slot.max));
+        center.distance2(eye)
+    };
+    slots.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+}
+
 fn generate_wf_vertices() -> Vec<WireframeVertex> {
     //方块8顶点
     let mut v8 = vec![
@@ -1760,6 +1494,11 @@ fn generate_wf_vertices() -> Vec<WireframeVertex> {
     v32
 }
 
+//世界存档目录
+pub fn world_dir_of(name: &str) -> PathBuf {
+    Path::new(WORLD_FILE_DIR).join(name)
+}
+
 //x，z是实际坐标
 pub fn get_chunk_coord(x: i32, z: i32) -> ChunkCoord {
     //floor 向下取整
@@ -1775,138 +1514,9 @@ pub fn get_local_coord(coord: Point3<i32>) -> Point3<i32> {
     Point3::new(local_x, coord.y, local_z)
 }
 
-fn create_all_block() -> Vec<BlockInfo> {
-    use BlockMaterials::*;
-    let mut all_block: Vec<BlockInfo> = vec![BlockInfo::default(); BLOCK_NUM];
-
-    let empty = BlockInfo::new(
-        "Empty",
-        [
-            Empty as u32,
-            Empty as u32,
-            Empty as u32,
-            Empty as u32,
-            Empty as u32,
-            Empty as u32,
-        ],
-        BlockType::Empty,
-    );
-    all_block[empty.block_type as usize] = empty;
-
-    let under_stone = BlockInfo::new(
-        "bedrock",
-        [
-            UnderStone as u32,
-            UnderStone as u32,
-            UnderStone as u32,
-            UnderStone as u32,
-            UnderStone as u32,
-            UnderStone as u32,
-        ],
-        BlockType::UnderStone,
-    );
-    all_block[under_stone.block_type as usize] = under_stone;
-
-    let stone = BlockInfo::new(
-        "stone",
-        [
-            Stone as u32,
-            Stone as u32,
-            Stone as u32,
-            Stone as u32,
-            Stone as u32,
-            Stone as u32,
-        ],
-        BlockType::Stone,
-    );
-    all_block[stone.block_type as usize] = stone;
-
-    let dirt = BlockInfo::new(
-        "dirt",
-        [
-            Dirt as u32,
-            Dirt as u32,
-            Dirt as u32,
-            Dirt as u32,
-            Dirt as u32,
-            Dirt as u32,
-        ],
-        BlockType::Dirt,
-    );
-    all_block[dirt.block_type as usize] = dirt;
-
-    //创建草方块
-    let grass = BlockInfo::new(
-        "grass_block_side",
-        [
-            GrassBlockSide as u32,
-            GrassBlockTop as u32,
-            GrassBlockSide as u32,
-            Dirt as u32,
-            GrassBlockSide as u32,
-            GrassBlockSide as u32,
-        ],
-        BlockType::Grass,
-    );
-    all_block[grass.block_type as usize] = grass;
-
-    let brich_log = BlockInfo::new(
-        "birch_log",
-        [
-            BirchLog as u32,
-            BirchLogTop as u32,
-            BirchLog as u32,
-            BirchLogTop as u32,
-            BirchLog as u32,
-            BirchLog as u32,
-        ],
-        BlockType::BirchLog,
-    );
-    all_block[brich_log.block_type as usize] = brich_log;
-
-    let brich_leaves = BlockInfo::new(
-        "birch_leaves",
-        [
-            BirchLeaves as u32,
-            BirchLeaves as u32,
-            BirchLeaves as u32,
-            BirchLeaves as u32,
-            BirchLeaves as u32,
-            BirchLeaves as u32,
-        ],
-        BlockType::BirchLeaves,
-    );
-    all_block[brich_leaves.block_type as usize] = brich_leaves;
-
-    let birch_plank = BlockInfo::new(
-        "birch_plank",
-        [
-            BirchPlank as u32,
-            BirchPlank as u32,
-            BirchPlank as u32,
-            BirchPlank as u32,
-            BirchPlank as u32,
-            BirchPlank as u32,
-        ],
-        BlockType::BirchPlank,
-    );
-    all_block[birch_plank.block_type as usize] = birch_plank;
-
-    let test_block = BlockInfo::new(
-        "test_block",
-        [
-            TestBlock as u32,
-            TestBlock as u32,
-            TestBlock as u32,
-            TestBlock as u32,
-            TestBlock as u32,
-            TestBlock as u32,
-        ],
-        BlockType::TestBlock,
-    );
-    all_block[test_block.block_type as usize] = test_block;
-
-    all_block
+//方块由定义文件决定，见block_registry
+pub fn create_all_block() -> Vec<BlockInfo> {
+    block_registry::registry().blocks().to_vec()
 }
 
 #[cfg(test)]
@@ -1915,6 +1525,8 @@ mod tests {
 
     use super::RealmData;
     use super::*;
+    use crate::terrain::GeneratorPreset;
+    use crate::test_util::test_dir;
 
     #[test]
     fn test_get_set_block() {
@@ -1929,25 +1541,142 @@ mod tests {
 
     #[test]
     fn test_chunk_file() -> anyhow::Result<()> {
-        let data = RealmData::new();
+        let mut data = RealmData::new();
+        data.world_dir = test_dir("chunk_file");
         let coord = ChunkCoord::new(0, 0);
+        Realm::generate_terrian_test(&mut data.chunk_map, &coord, data.seed);
         data.chunk_map
             .get(&coord)
             .unwrap()
-            .save(data.name, &coord)?;
+            .save(&data.world_dir, &coord)?;
 
-        let chunk = Chunk::load(data.name, &coord).unwrap().unwrap();
+        let chunk = Chunk::load(&data.world_dir, &coord).unwrap().unwrap();
 
         assert_eq!(chunk, data.chunk_map.get(&coord).unwrap().data);
         Ok(())
     }
 
+    #[test]
+    fn test_chunk_sections() -> anyhow::Result<()> {
+        let mut data = RealmData::new();
+        let coord = ChunkCoord::new(0, 0);
+        Realm::generate_terrian_test(&mut data.chunk_map, &coord, data.seed);
+        let chunk = &data.chunk_map[&coord];
+
+        //地面在最下面的分段中，上面的分段全空
+        assert!(!chunk.is_section_empty(0));
+        assert!((SECTION_HEIGHT..CHUNK_HEIGHT)
+            .step_by(SECTION_HEIGHT as usize)
+            .all(|y| chunk.is_section_empty(y)));
+        assert_eq!(chunk.get_block(3, 3, 7).tp, BlockType::Grass);
+        assert_eq!(chunk.get_block(3, 4, 7).tp, BlockType::Empty);
+
+        //序列化后远小于平铺存储的131072个方块
+        assert!(bincode::serialize(&chunk.data)?.len() < 4096);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dirty_chunk_saved() -> anyhow::Result<()> {
+        let mut data = RealmData::new();
+        data.world_dir = test_dir("dirty_chunk");
+        let coord = ChunkCoord::new(-1, 0);
+        Realm::generate_terrian_test(&mut data.chunk_map, &coord, data.seed);
+        assert!(!data.chunk_map[&coord].is_dirty);
+
+        //未修改的区块不需要保存
+        assert_eq!(data.save_all_chunks(), 0);
+        assert!(Chunk::load(&data.world_dir, &coord)?.is_none());
+
+        let block_coord = Point3::new(-5, 10, 3);
+        assert!(data.set_block(block_coord, Block::new(BlockType::Stone)));
+        assert!(data.chunk_map[&coord].is_dirty);
+
+        assert_eq!(data.save_all_chunks(), 1);
+        assert!(!data.chunk_map[&coord].is_dirty);
+
+        let saved = Chunk::load(&data.world_dir, &coord)?.unwrap();
+        let local = get_local_coord(block_coord);
+        assert_eq!(
+            saved.get_block(local.x, local.y, local.z).tp,
+            BlockType::Stone
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_get_block() {
-        let data = RealmData::new();
+        let mut data = RealmData::new();
+        Realm::generate_terrian_test(&mut data.chunk_map, &ChunkCoord::new(-1, -1), data.seed);
         assert_eq!(
             data.get_block(Point3::new(-3, 0, -3)).tp,
             BlockType::UnderStone
         );
     }
+
+    #[test]
+    fn test_chunks_in_radius() {
+        let center = ChunkCoord::new(3, -2);
+        let coords = chunks_in_radius(center, 5);
+        //半径5的圆内有81个区块，比正方形少
+        assert_eq!(coords.len(), 81);
+        assert_eq!(coords[0], center);
+        assert!(coords
+            .windows(2)
+            .all(|w| w[0].distance_squared(&center) <= w[1].distance_squared(&center)));
+        assert!(!coords.contains(&ChunkCoord::new(center.x + 5, center.z + 5)));
+        assert!(coords.contains(&ChunkCoord::new(center.x + 5, center.z)));
+    }
+
+    //模拟工作线程：只用区块自己的数据生成网格，再按顺序加入
+    fn connect_generated(coords: &[ChunkCoord]) -> RealmData {
+        let mut data = RealmData::new();
+        for coord in coords {
+            let mut chunk = ChunkGenerator::generate_terrain_internal(
+                *coord,
+                2025318,
+                &GeneratorPreset::Default,
+            );
+            chunk.mesh = chunk_mesher::mesh_chunk(&chunk, coord, |_, _, _| BLOCK_EMPTY);
+            data.chunk_map.insert(*coord, chunk);
+            data.connect_chunk(coord);
+        }
+        data
+    }
+
+    #[test]
+    fn test_connect_chunk_hides_seams() {
+        let coords = [
+            ChunkCoord::new(0, 0),
+            ChunkCoord::new(1, 0),
+            ChunkCoord::new(0, 1),
+            ChunkCoord::new(1, 1),
+        ];
+        let face_count =
+            |data: &RealmData, coord: &ChunkCoord| data.chunk_map[coord].mesh.face_count();
+
+        let mut alone = 0;
+        for coord in &coords {
+            alone += connect_generated(&[*coord]).chunk_map[coord]
+                .mesh
+                .face_count();
+        }
+        let connected = connect_generated(&coords);
+        let total: usize = coords.iter().map(|c| face_count(&connected, c)).sum();
+        assert!(total < alone);
+
+        //加入顺序不影响结果，并且和主线程直接生成的网格相同
+        let mut reversed = coords;
+        reversed.reverse();
+        let mut remeshed = connect_generated(&reversed);
+        for coord in &coords {
+            assert_eq!(face_count(&connected, coord), face_count(&remeshed, coord));
+            let before = connected.chunk_map[coord].mesh.combined();
+            remeshed.remesh_chunk(coord);
+            assert_eq!(
+                before.vertices,
+                remeshed.chunk_map[coord].mesh.combined().vertices
+            );
+        }
+    }
 }
@@ -0,0 +1,623 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use serde::{Deserialize, Serialize};
+
+use crate::chunk_section::{MAX_ENCODED_SECTION_BYTES, SECTION_NUM};
+use crate::realm::{Block, ChunkCoord, ChunkData, BLOCK_NUM_PER_CHUNK};
+
+/*
+ * 区域文件格式
+ * 每个区域文件保存 REGION_SIZE * REGION_SIZE 个区块
+ *
+ * 文件按 SECTOR_SIZE 字节分扇区
+ * 前 HEADER_SECTORS 个扇区为头部表，每个区块占一项：
+ *   起始扇区(u32, 小端) + 数据长度(u32, 小端)，起始扇区为0表示区块不存在
+ * 区块数据：1字节编码方式 + zlib压缩后的bincode数据
+ * 编码方式 CHUNK_ENCODING_FLAT 为旧版平铺的方块数组，只读取不再写入
+ */
+pub const REGION_SIZE: i32 = 32;
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+const SECTOR_SIZE: u64 = 4096;
+const HEADER_ENTRY_SIZE: usize = 8;
+const HEADER_SECTORS: u64 = (CHUNKS_PER_REGION * HEADER_ENTRY_SIZE) as u64 / SECTOR_SIZE;
+//压缩后的区块远小于这个值，头部表中更大的长度一定是损坏的
+const MAX_CHUNK_BYTES: u32 = 16 << 20;
+//解压后最大的区块数据：每个分段都是最大的调色板分段，或者旧版平铺的方块数组
+//解压到这个长度时一定是损坏的数据，不再继续解压
+const MAX_RAW_CHUNK_BYTES: usize = {
+    let palette = 8 + SECTION_NUM * MAX_ENCODED_SECTION_BYTES;
+    let flat = 8 + BLOCK_NUM_PER_CHUNK * size_of::<Block>();
+    if palette > flat {
+        palette
+    } else {
+        flat
+    }
+};
+
+const REGION_DIR: &str = "region";
+//旧版每个区块一个文件的目录
+const LEGACY_CHUNK_DIR: &str = "chunks";
+const MIGRATED_CHUNK_DIR: &str = "chunks.migrated";
+//读取失败的区块数据移到这里，方便排查
+pub const CORRUPTED_DIR: &str = "corrupted";
+
+//工作线程读取和主线程保存可能同时访问同一个区域文件，所有读写都要先加锁
+static REGION_LOCK: Mutex<()> = Mutex::new(());
+
+//区块数据的编码方式
+const CHUNK_ENCODING_FLAT: u8 = 1;
+const CHUNK_ENCODING_PALETTE: u8 = 2;
+
+//旧版平铺存储的区块数据
+#[derive(Serialize, Deserialize)]
+struct FlatChunkData {
+    blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct HeaderEntry {
+    sector_offset: u32,
+    length: u32,
+}
+
+impl HeaderEntry {
+    fn sector_count(&self) -> u64 {
+        (self.length as u64).div_ceil(SECTOR_SIZE)
+    }
+
+    //数据必须在头部表之后、文件末尾之前，否则按损坏处理，不会按损坏的长度分配内存
+    fn check(&self, file_len: u64) -> anyhow::Result<()> {
+        if (self.sector_offset as u64) < HEADER_SECTORS {
+            anyhow::bail!("区域文件损坏：区块起始扇区{}在文件头中", self.sector_offset);
+        }
+        if self.length > MAX_CHUNK_BYTES
+            || self.sector_offset as u64 * SECTOR_SIZE + self.length as u64 > file_len
+        {
+            anyhow::bail!("区域文件损坏：区块长度{}超出文件范围", self.length);
+        }
+        Ok(())
+    }
+}
+
+pub struct RegionFile {
+    file: File,
+    header: Vec<HeaderEntry>,
+}
+
+impl RegionFile {
+    //打开区域文件，不存在则创建
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("创建区域目录失败")?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .context("打开区域文件失败")?;
+
+        let header_len = (HEADER_SECTORS * SECTOR_SIZE) as usize;
+        let file_len = file.metadata().context("读取区域文件信息失败")?.len();
+
+        let mut header = vec![HeaderEntry::default(); CHUNKS_PER_REGION];
+        if file_len == 0 {
+            file.write_all(&vec![0u8; header_len])
+                .context("写入区域文件头失败")?;
+        } else {
+            if file_len < header_len as u64 {
+                anyhow::bail!("区域文件损坏：文件头不完整");
+            }
+            let mut bytes = vec![0u8; header_len];
+            file.seek(SeekFrom::Start(0))?;
+            file.read_exact(&mut bytes).context("读取区域文件头失败")?;
+            for (i, entry) in header.iter_mut().enumerate() {
+                let raw = &bytes[i * HEADER_ENTRY_SIZE..(i + 1) * HEADER_ENTRY_SIZE];
+                entry.sector_offset = u32::from_le_bytes(raw[0..4].try_into().unwrap());
+                entry.length = u32::from_le_bytes(raw[4..8].try_into().unwrap());
+            }
+        }
+
+        Ok(Self { file, header })
+    }
+
+    pub fn read_chunk(&mut self, coord: &ChunkCoord) -> anyhow::Result<Option<Vec<u8>>> {
+        let entry = self.header[entry_index(coord)];
+        if entry.sector_offset == 0 {
+            return Ok(None);
+        }
+        entry.check(self.file.metadata().context("读取区域文件信息失败")?.len())?;
+
+        let mut bytes = vec![0u8; entry.length as usize];
+        self.file
+            .seek(SeekFrom::Start(entry.sector_offset as u64 * SECTOR_SIZE))?;
+        self.file
+            .read_exact(&mut bytes)
+            .context("读取区块数据失败")?;
+        Ok(Some(bytes))
+    }
+
+    //原有位置放得下则原地覆盖，否则追加到文件末尾
+    pub fn write_chunk(&mut self, coord: &ChunkCoord, bytes: &[u8]) -> anyhow::Result<()> {
+        let index = entry_index(coord);
+        let old_entry = self.header[index];
+        let new_entry = HeaderEntry {
+            sector_offset: 0,
+            length: bytes.len() as u32,
+        };
+
+        //损坏的旧位置可能指向文件头，不能原地覆盖
+        let sector_offset = if old_entry.sector_offset as u64 >= HEADER_SECTORS
+            && new_entry.sector_count() <= old_entry.sector_count()
+        {
+            old_entry.sector_offset as u64
+        } else {
+            let file_len = self.file.metadata()?.len();
+            file_len.div_ceil(SECTOR_SIZE).max(HEADER_SECTORS)
+        };
+
+        //补齐到整扇区，保证下一次追加从扇区边界开始
+        let mut padded = bytes.to_vec();
+        padded.resize((new_entry.sector_count() * SECTOR_SIZE) as usize, 0);
+
+        self.file
+            .seek(SeekFrom::Start(sector_offset * SECTOR_SIZE))?;
+        self.file.write_all(&padded).context("写入区块数据失败")?;
+
+        let entry = HeaderEntry {
+            sector_offset: sector_offset as u32,
+            ..new_entry
+        };
+        self.write_header_entry(index, entry)?;
+        self.header[index] = entry;
+        Ok(())
+    }
+
+    //只清除头部表中的一项，数据所在的扇区不再使用
+    pub fn remove_chunk(&mut self, coord: &ChunkCoord) -> anyhow::Result<()> {
+        let index = entry_index(coord);
+        self.write_header_entry(index, HeaderEntry::default())?;
+        self.header[index] = HeaderEntry::default();
+        Ok(())
+    }
+
+    fn write_header_entry(&mut self, index: usize, entry: HeaderEntry) -> anyhow::Result<()> {
+        let mut raw = [0u8; HEADER_ENTRY_SIZE];
+        raw[0..4].copy_from_slice(&entry.sector_offset.to_le_bytes());
+        raw[4..8].copy_from_slice(&entry.length.to_le_bytes());
+        self.file
+            .seek(SeekFrom::Start((index * HEADER_ENTRY_SIZE) as u64))?;
+        self.file.write_all(&raw).context("写入区域文件头失败")?;
+        Ok(())
+    }
+}
+
+//区块所在的区域坐标
+pub fn region_coord(coord: &ChunkCoord) -> (i32, i32) {
+    (
+        coord.x.div_euclid(REGION_SIZE),
+        coord.z.div_euclid(REGION_SIZE),
+    )
+}
+
+fn entry_index(coord: &ChunkCoord) -> usize {
+    (coord.x.rem_euclid(REGION_SIZE) + coord.z.rem_euclid(REGION_SIZE) * REGION_SIZE) as usize
+}
+
+pub fn region_path(world_dir: &Path, coord: &ChunkCoord) -> PathBuf {
+    let (region_x, region_z) = region_coord(coord);
+    world_dir
+        .join(REGION_DIR)
+        .join(format!("r.{}.{}.region", region_x, region_z))
+}
+
+pub fn encode_chunk(data: &ChunkData) -> anyhow::Result<Vec<u8>> {
+    let raw = bincode::serialize(data).context("区块序列化失败")?;
+    let mut encoder = ZlibEncoder::new(vec![CHUNK_ENCODING_PALETTE], Compression::fast());
+    encoder.write_all(&raw).context("区块压缩失败")?;
+    encoder.finish().context("区块压缩失败")
+}
+
+pub fn decode_chunk(bytes: &[u8]) -> anyhow::Result<ChunkData> {
+    let Some((&encoding, compressed)) = bytes.split_first() else {
+        anyhow::bail!("区块数据损坏：数据为空");
+    };
+    if encoding != CHUNK_ENCODING_FLAT && encoding != CHUNK_ENCODING_PALETTE {
+        anyhow::bail!("区块数据损坏：未知的编码方式{}", encoding);
+    }
+
+    let mut raw = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(MAX_RAW_CHUNK_BYTES as u64 + 1)
+        .read_to_end(&mut raw)
+        .context("区块解压失败")?;
+    if raw.len() > MAX_RAW_CHUNK_BYTES {
+        anyhow::bail!("区块数据损坏：解压后超过{}字节", MAX_RAW_CHUNK_BYTES);
+    }
+
+    if encoding == CHUNK_ENCODING_FLAT {
+        return decode_flat_chunk(&raw);
+    }
+    let chunk: ChunkData = bincode::deserialize(&raw).context("解析区块数据失败")?;
+    chunk.validate()?;
+    Ok(chunk)
+}
+
+fn decode_flat_chunk(raw: &[u8]) -> anyhow::Result<ChunkData> {
+    let flat: FlatChunkData = bincode::deserialize(raw).context("解析区块数据失败")?;
+    ChunkData::from_flat_blocks(&flat.blocks)
+}
+
+/*
+ * 区域文件打不开(例如文件头不完整)时，把它移到corrupted目录后重新创建
+ * 否则这个区域中的区块永远无法保存
+ */
+pub fn save_chunk(world_dir: &Path, coord: &ChunkCoord, data: &ChunkData) -> anyhow::Result<()> {
+    let bytes = encode_chunk(data)?;
+    let _lock = REGION_LOCK.lock().unwrap();
+    let path = region_path(world_dir, coord);
+    let mut region = match RegionFile::open(&path) {
+        Ok(region) => region,
+        Err(e) if path.exists() => {
+            let moved = quarantine_region(world_dir, coord)?;
+            eprintln!("区域文件损坏，已移到{}:{:#}", moved.display(), e);
+            RegionFile::open(&path)?
+        }
+        Err(e) => return Err(e),
+    };
+    region.write_chunk(coord, &bytes)
+}
+
+pub fn load_chunk(world_dir: &Path, coord: &ChunkCoord) -> anyhow::Result<Option<ChunkData>> {
+    let bytes = {
+        let _lock = REGION_LOCK.lock().unwrap();
+        let path = region_path(world_dir, coord);
+        //区域不存在
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut region = RegionFile::open(&path)?;
+        region.read_chunk(coord)?
+    };
+    //解压不需要持有锁
+    bytes.map(|bytes| decode_chunk(&bytes)).transpose()
+}
+
+/*
+ * 把读取失败的区块移到世界目录下的corrupted目录，之后这个区块会重新生成
+ * 区域文件头损坏时整个区域文件都移过去
+ * 返回移动后的文件路径
+ */
+pub fn quarantine_chunk(world_dir: &Path, coord: &ChunkCoord) -> anyhow::Result<PathBuf> {
+    let _lock = REGION_LOCK.lock().unwrap();
+    let mut region = match RegionFile::open(&region_path(world_dir, coord)) {
+        Ok(region) => region,
+        Err(_) => return quarantine_region(world_dir, coord),
+    };
+
+    //数据本身读不出来时只留下空文件作为记录
+    let bytes = region.read_chunk(coord).ok().flatten().unwrap_or_default();
+    let target = corrupted_dir(world_dir)?.join(format!(
+        "c.{}.{}.{}.chunk",
+        coord.x,
+        coord.z,
+        quarantine_time()
+    ));
+    std::fs::write(&target, bytes).context("写入损坏的区块数据失败")?;
+    region.remove_chunk(coord)?;
+    Ok(target)
+}
+
+//把coord所在的整个区域文件移走，调用时需要持有REGION_LOCK
+fn quarantine_region(world_dir: &Path, coord: &ChunkCoord) -> anyhow::Result<PathBuf> {
+    let (region_x, region_z) = region_coord(coord);
+    let target = corrupted_dir(world_dir)?.join(format!(
+        "r.{}.{}.{}.region",
+        region_x,
+        region_z,
+        quarantine_time()
+    ));
+    std::fs::rename(region_path(world_dir, coord), &target).context("移动损坏的区域文件失败")?;
+    Ok(target)
+}
+
+fn corrupted_dir(world_dir: &Path) -> anyhow::Result<PathBuf> {
+    let dir = world_dir.join(CORRUPTED_DIR);
+    std::fs::create_dir_all(&dir).context("创建corrupted目录失败")?;
+    Ok(dir)
+}
+
+//同一个区块可能多次损坏，用时间区分
+fn quarantine_time() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+/*
+ * 把旧版 chunks/xN/yM.chunk 目录中的区块全部写入区域文件
+ * 全部成功后把旧目录重命名为 chunks.migrated，因此只会执行一次
+ * 返回迁移的区块数量
+ */
+pub fn migrate_legacy_chunks(world_dir: &Path) -> anyhow::Result<usize> {
+    let legacy_dir = world_dir.join(LEGACY_CHUNK_DIR);
+    if !legacy_dir.is_dir() {
+        return Ok(0);
+    }
+
+    let mut migrated = 0;
+    for x_entry in std::fs::read_dir(&legacy_dir).context("读取旧区块目录失败")? {
+        let x_entry = x_entry?;
+        let Some(x) = parse_legacy_name(&x_entry.file_name(), "x", "") else {
+            continue;
+        };
+
+        for z_entry in std::fs::read_dir(x_entry.path()).context("读取旧区块目录失败")? {
+            let z_entry = z_entry?;
+            let Some(z) = parse_legacy_name(&z_entry.file_name(), "y", ".chunk") else {
+                continue;
+            };
+
+            let coord = ChunkCoord::new(x, z);
+            let bytes = std::fs::read(z_entry.path()).context("读取旧区块文件失败")?;
+            let data =
+                decode_flat_chunk(&bytes).with_context(|| format!("解析旧区块失败:{:?}", coord))?;
+
+            save_chunk(world_dir, &coord, &data)?;
+            migrated += 1;
+        }
+    }
+
+    std::fs::rename(&legacy_dir, world_dir.join(MIGRATED_CHUNK_DIR))
+        .context("重命名旧区块目录失败")?;
+    Ok(migrated)
+}
+
+//解析形如 x12 / y-3.chunk 的文件名
+fn parse_legacy_name(name: &std::ffi::OsStr, prefix: &str, suffix: &str) -> Option<i32> {
+    name.to_str()?
+        .strip_prefix(prefix)?
+        .strip_suffix(suffix)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realm::{BlockType, BLOCK_EMPTY};
+    use crate::test_util::test_dir;
+
+    fn test_flat_blocks(tp: BlockType, filled: usize) -> Vec<Block> {
+        let mut blocks = vec![BLOCK_EMPTY; BLOCK_NUM_PER_CHUNK];
+        for block in blocks.iter_mut().take(filled) {
+            *block = Block::new(tp);
+        }
+        blocks
+    }
+
+    fn test_chunk_data(tp: BlockType, filled: usize) -> ChunkData {
+        ChunkData::from_flat_blocks(&test_flat_blocks(tp, filled)).unwrap()
+    }
+
+    #[test]
+    fn test_region_round_trip() -> anyhow::Result<()> {
+        let world_dir = test_dir("region_round_trip");
+        let coords = [
+            ChunkCoord::new(0, 0),
+            ChunkCoord::new(31, 31),
+            ChunkCoord::new(-1, -1),
+            ChunkCoord::new(-33, 40),
+        ];
+        for (i, coord) in coords.iter().enumerate() {
+            save_chunk(
+                &world_dir,
+                coord,
+                &test_chunk_data(BlockType::Stone, i * 100),
+            )?;
+        }
+        for (i, coord) in coords.iter().enumerate() {
+            let data = load_chunk(&world_dir, coord)?.unwrap();
+            assert_eq!(data, test_chunk_data(BlockType::Stone, i * 100));
+        }
+
+        //同一区域中未保存的区块
+        assert!(load_chunk(&world_dir, &ChunkCoord::new(1, 0))?.is_none());
+        //不存在的区域
+        assert!(load_chunk(&world_dir, &ChunkCoord::new(100, 100))?.is_none());
+
+        //(0,0)和(31,31)在同一个区域文件中
+        assert_eq!(
+            region_path(&world_dir, &coords[0]),
+            region_path(&world_dir, &coords[1])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_rewrite_in_place() -> anyhow::Result<()> {
+        let world_dir = test_dir("region_rewrite");
+        let coord = ChunkCoord::new(3, 5);
+        let path = region_path(&world_dir, &coord);
+
+        save_chunk(&world_dir, &coord, &test_chunk_data(BlockType::Dirt, 5000))?;
+        let len_before = std::fs::metadata(&path)?.len();
+
+        //数据变小，原地覆盖，文件大小不变
+        save_chunk(&world_dir, &coord, &test_chunk_data(BlockType::Grass, 10))?;
+        assert_eq!(std::fs::metadata(&path)?.len(), len_before);
+        assert_eq!(
+            load_chunk(&world_dir, &coord)?.unwrap(),
+            test_chunk_data(BlockType::Grass, 10)
+        );
+
+        //其他区块追加到末尾，不影响已有区块
+        let other = ChunkCoord::new(4, 5);
+        save_chunk(&world_dir, &other, &test_chunk_data(BlockType::Stone, 1))?;
+        assert!(std::fs::metadata(&path)?.len() > len_before);
+        assert_eq!(
+            load_chunk(&world_dir, &coord)?.unwrap(),
+            test_chunk_data(BlockType::Grass, 10)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_legacy_chunks() -> anyhow::Result<()> {
+        let world_dir = test_dir("region_migrate");
+        let coords = [ChunkCoord::new(0, 0), ChunkCoord::new(-2, 7)];
+        for coord in &coords {
+            let path = world_dir
+                .join(LEGACY_CHUNK_DIR)
+                .join(format!("x{}", coord.x))
+                .join(format!("y{}.chunk", coord.z));
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            let data = FlatChunkData {
+                blocks: test_flat_blocks(BlockType::BirchLog, (coord.z + 10) as usize),
+            };
+            std::fs::write(path, bincode::serialize(&data)?)?;
+        }
+
+        assert_eq!(migrate_legacy_chunks(&world_dir)?, 2);
+        for coord in &coords {
+            assert_eq!(
+                load_chunk(&world_dir, coord)?.unwrap(),
+                test_chunk_data(BlockType::BirchLog, (coord.z + 10) as usize)
+            );
+        }
+
+        //只迁移一次
+        assert!(!world_dir.join(LEGACY_CHUNK_DIR).exists());
+        assert_eq!(migrate_legacy_chunks(&world_dir)?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_flat_chunk() -> anyhow::Result<()> {
+        //旧版区域文件中平铺编码的区块仍然可以读取
+        let flat = FlatChunkData {
+            blocks: test_flat_blocks(BlockType::Grass, 3000),
+        };
+        let mut encoder = ZlibEncoder::new(vec![CHUNK_ENCODING_FLAT], Compression::fast());
+        encoder.write_all(&bincode::serialize(&flat)?)?;
+        let bytes = encoder.finish()?;
+        assert_eq!(
+            decode_chunk(&bytes)?,
+            test_chunk_data(BlockType::Grass, 3000)
+        );
+
+        //重新保存后使用调色板编码，数据更小
+        let encoded = encode_chunk(&decode_chunk(&bytes)?)?;
+        assert_eq!(encoded[0], CHUNK_ENCODING_PALETTE);
+        assert!(bincode::serialize(&decode_chunk(&encoded)?)?.len() < BLOCK_NUM_PER_CHUNK / 16);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quarantine_chunk() -> anyhow::Result<()> {
+        let world_dir = test_dir("region_quarantine");
+        let coord = ChunkCoord::new(2, 3);
+        let other = ChunkCoord::new(3, 3);
+        save_chunk(&world_dir, &other, &test_chunk_data(BlockType::Stone, 10))?;
+        let mut region = RegionFile::open(&region_path(&world_dir, &coord))?;
+        region.write_chunk(&coord, &[CHUNK_ENCODING_PALETTE, 1, 2, 3])?;
+        drop(region);
+
+        assert!(load_chunk(&world_dir, &coord).is_err());
+        let moved = quarantine_chunk(&world_dir, &coord)?;
+        assert!(moved.starts_with(world_dir.join(CORRUPTED_DIR)));
+        assert_eq!(
+            std::fs::read(&moved)?,
+            vec![CHUNK_ENCODING_PALETTE, 1, 2, 3]
+        );
+        //移走后当作不存在，同一区域的其他区块不受影响
+        assert!(load_chunk(&world_dir, &coord)?.is_none());
+        assert!(load_chunk(&world_dir, &other)?.is_some());
+
+        //文件头损坏时移走整个区域文件
+        std::fs::write(region_path(&world_dir, &coord), [0u8; 10])?;
+        assert!(load_chunk(&world_dir, &other).is_err());
+        quarantine_chunk(&world_dir, &other)?;
+        assert!(load_chunk(&world_dir, &other)?.is_none());
+        assert_eq!(std::fs::read_dir(world_dir.join(CORRUPTED_DIR))?.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompression_limit() -> anyhow::Result<()> {
+        //有效的区块解压后不会超过上限
+        let mut data = ChunkData::new();
+        data.set_block(0, 0, 0, Block::new(BlockType::Stone));
+        let raw = bincode::serialize(&data)?;
+        assert!(raw.len() <= MAX_RAW_CHUNK_BYTES);
+
+        //压缩率很高的数据解压到上限时停止，当作损坏的区块
+        let mut encoder = ZlibEncoder::new(vec![CHUNK_ENCODING_PALETTE], Compression::fast());
+        encoder.write_all(&vec![0; MAX_RAW_CHUNK_BYTES + 1])?;
+        let bytes = encoder.finish()?;
+        assert!(bytes.len() < MAX_CHUNK_BYTES as usize);
+        let err = decode_chunk(&bytes).unwrap_err();
+        assert!(format!("{:#}", err).contains("解压后超过"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_forged_header_entry() -> anyhow::Result<()> {
+        let world_dir = test_dir("region_forged_header");
+        let coord = ChunkCoord::new(5, 6);
+        let other = ChunkCoord::new(6, 6);
+        save_chunk(&world_dir, &other, &test_chunk_data(BlockType::Stone, 10))?;
+
+        //长度接近u32::MAX、指向文件头、超出文件末尾的项都返回错误，不会分配巨大的内存
+        let forged = [
+            HeaderEntry {
+                sector_offset: HEADER_SECTORS as u32,
+                length: u32::MAX,
+            },
+            HeaderEntry {
+                sector_offset: 1,
+                length: 100,
+            },
+            HeaderEntry {
+                sector_offset: 1000,
+                length: 100,
+            },
+        ];
+        for entry in forged {
+            let mut region = RegionFile::open(&region_path(&world_dir, &coord))?;
+            region.write_header_entry(entry_index(&coord), entry)?;
+            drop(region);
+            assert!(load_chunk(&world_dir, &coord).is_err());
+            assert!(load_chunk(&world_dir, &other)?.is_some());
+        }
+
+        //移走后可以重新保存，文件头没有被覆盖
+        quarantine_chunk(&world_dir, &coord)?;
+        assert!(load_chunk(&world_dir, &coord)?.is_none());
+        save_chunk(&world_dir, &coord, &test_chunk_data(BlockType::Dirt, 20))?;
+        assert_eq!(
+            load_chunk(&world_dir, &coord)?.unwrap(),
+            test_chunk_data(BlockType::Dirt, 20)
+        );
+        assert!(load_chunk(&world_dir, &other)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_corrupted_chunk() {
+        assert!(decode_chunk(&[]).is_err());
+        assert!(decode_chunk(&[CHUNK_ENCODING_FLAT, 1, 2, 3]).is_err());
+        assert!(decode_chunk(&[CHUNK_ENCODING_PALETTE, 1, 2, 3]).is_err());
+        assert!(decode_chunk(&[42]).is_err());
+    }
+}
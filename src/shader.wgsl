@@ -1,11 +1,8 @@
-struct InstanceInput {
-    @location(5) position: vec3<f32>,
-    @location(6) block_type: u32,
-}
-
 struct VertexInput {
     @location(0) position: vec3<f32>,
+    //合并后的面纹理坐标可能大于1，采样器重复平铺
     @location(1) tex_coords: vec2<f32>,
+    @location(2) layer: u32,
 }
 
 struct VertexOutput {
@@ -21,33 +18,18 @@ struct CameraUniform {
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
-struct MaterialUniform {
-    index: vec4<u32>,
-}
-
 //标记为vertex shader
 @vertex
 fn vs_main(
     model: VertexInput,
-    instance: InstanceInput,
-    @builtin(vertex_index) vertex_index: u32,
 ) -> VertexOutput {
     //var：可变   需要声明类型
     //let：不可变 可以推断类型
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    let world_position = model.position + instance.position;
-    if instance.block_type == 0 {
-        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
-        return out;
-    } else {
-        out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
-    }
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.layer = model.layer;
 
-    let face_index = vertex_index / 4u;
-    let material_index = instance.block_type * 6u + face_index;
-    out.layer = block_materials[material_index].index[0];
-    
     return out;
 }
 
@@ -57,12 +39,16 @@ var t_diffuse: texture_2d_array<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 
-// 为了符合步长要求 使用结构体
-// 数组大小应该等于BLOCK_NUM * 6
-@group(2) @binding(0)
-var<uniform> block_materials: array<MaterialUniform, 54>;
-
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(t_diffuse, s_diffuse, in.tex_coords, in.layer);
-}
\ No newline at end of file
+}
+
+//半透明的面，贴图本身不透明时也按这个透明度混合
+const TRANSLUCENT_ALPHA: f32 = 0.7;
+
+@fragment
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords, in.layer);
+    return vec4<f32>(color.rgb, color.a * TRANSLUCENT_ALPHA);
+}
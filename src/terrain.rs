@@ -0,0 +1,628 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+use crate::biome::{BiomeMap, HeightProfile};
+use crate::block_registry;
+use crate::ore::{OreSettings, AMPLIFIED_ORES, DEFAULT_ORES};
+use crate::realm::{BlockType, ChunkCoord, CHUNK_HEIGHT, CHUNK_SIZE};
+
+//地面最高的高度，上面留出生成树木的空间
+const MAX_TERRAIN_HEIGHT: i32 = CHUNK_HEIGHT - 32;
+
+//分形噪声的参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseSettings {
+    //叠加的层数
+    pub octaves: u32,
+    //第一层的周期，单位为方块
+    pub scale: f64,
+    //每一层的频率是上一层的多少倍
+    pub lacunarity: f64,
+    //每一层的振幅是上一层的多少倍
+    pub persistence: f64,
+}
+
+//地形生成器的参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainSettings {
+    //平缓的丘陵使用fBm噪声
+    pub hills: NoiseSettings,
+    //山地使用山脊噪声
+    pub ridges: NoiseSettings,
+    //群系高度参数的倍数，为0时地面全部在海平面
+    pub amplitude: f64,
+    pub sea_level: i32,
+    //地下生成的矿石，见ore::place_ores
+    pub ores: &'static [OreSettings],
+}
+
+const DEFAULT_TERRAIN: TerrainSettings = TerrainSettings {
+    hills: NoiseSettings {
+        octaves: 4,
+        scale: 64.0,
+        lacunarity: 2.0,
+        persistence: 0.5,
+    },
+    ridges: NoiseSettings {
+        octaves: 5,
+        scale: 128.0,
+        lacunarity: 2.0,
+        persistence: 0.5,
+    },
+    amplitude: 1.0,
+    sea_level: 32,
+    ores: DEFAULT_ORES,
+};
+
+const AMPLIFIED_TERRAIN: TerrainSettings = TerrainSettings {
+    hills: NoiseSettings {
+        octaves: 6,
+        ..DEFAULT_TERRAIN.hills
+    },
+    ridges: NoiseSettings {
+        octaves: 6,
+        scale: 192.0,
+        ..DEFAULT_TERRAIN.ridges
+    },
+    amplitude: 3.0,
+    ores: AMPLIFIED_ORES,
+    ..DEFAULT_TERRAIN
+};
+
+const FLAT_TERRAIN: TerrainSettings = TerrainSettings {
+    amplitude: 0.0,
+    ..DEFAULT_TERRAIN
+};
+
+/*
+ * 自定义生成器的参数，保存在level.dat中，其他参数和默认生成器相同
+ * 丘陵和山地使用相同的频率倍数，山地比丘陵多叠加一层
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CustomTerrain {
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub amplitude: f64,
+    pub sea_level: i32,
+}
+
+//自定义参数的范围，超出范围的值在创建世界时拒绝，读取存档时限制
+const CUSTOM_OCTAVES: RangeInclusive<u32> = 1..=8;
+const CUSTOM_LACUNARITY: RangeInclusive<f64> = 1.0..=4.0;
+const CUSTOM_AMPLITUDE: RangeInclusive<f64> = 0.0..=4.0;
+const CUSTOM_SEA_LEVEL: RangeInclusive<i32> = 1..=MAX_TERRAIN_HEIGHT;
+
+impl Default for CustomTerrain {
+    fn default() -> Self {
+        Self {
+            octaves: DEFAULT_TERRAIN.hills.octaves,
+            lacunarity: DEFAULT_TERRAIN.hills.lacunarity,
+            amplitude: DEFAULT_TERRAIN.amplitude,
+            sea_level: DEFAULT_TERRAIN.sea_level,
+        }
+    }
+}
+
+impl CustomTerrain {
+    //level.dat可能被手动修改过，生成前把参数限制在范围内，NaN使用默认值
+    fn clamped(&self) -> Self {
+        let default = Self::default();
+        let clamp_f64 = |value: f64, range: &RangeInclusive<f64>, default: f64| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(*range.start(), *range.end())
+            }
+        };
+        Self {
+            octaves: self
+                .octaves
+                .clamp(*CUSTOM_OCTAVES.start(), *CUSTOM_OCTAVES.end()),
+            lacunarity: clamp_f64(self.lacunarity, &CUSTOM_LACUNARITY, default.lacunarity),
+            amplitude: clamp_f64(self.amplitude, &CUSTOM_AMPLITUDE, default.amplitude),
+            sea_level: self
+                .sea_level
+                .clamp(*CUSTOM_SEA_LEVEL.start(), *CUSTOM_SEA_LEVEL.end()),
+        }
+    }
+
+    fn settings(&self) -> TerrainSettings {
+        let custom = self.clamped();
+        TerrainSettings {
+            hills: NoiseSettings {
+                octaves: custom.octaves,
+                lacunarity: custom.lacunarity,
+                ..DEFAULT_TERRAIN.hills
+            },
+            ridges: NoiseSettings {
+                octaves: custom.octaves + 1,
+                lacunarity: custom.lacunarity,
+                ..DEFAULT_TERRAIN.ridges
+            },
+            amplitude: custom.amplitude,
+            sea_level: custom.sea_level,
+            ..DEFAULT_TERRAIN
+        }
+    }
+}
+
+//超平坦世界中的一层，从下往上排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlatLayer {
+    pub block: BlockType,
+    pub thickness: u32,
+}
+
+/*
+ * 创建世界时选择的生成器，保存在level.dat中
+ * 同一个世界之后生成的区块都使用相同的生成器
+ */
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum GeneratorPreset {
+    #[default]
+    Default,
+    //更高的山和更深的海
+    Amplified,
+    //保留群系和树木，地面都在海平面
+    Flat,
+    //没有群系，按自定义的层生成
+    Superflat(Vec<FlatLayer>),
+    //创建世界时填写的噪声参数
+    Custom(CustomTerrain),
+}
+
+impl GeneratorPreset {
+    //开始菜单中依次切换的顺序
+    pub const NAMES: [&'static str; 5] = ["默认", "放大化", "平坦", "超平坦", "自定义"];
+
+    //index为NAMES中的下标，超平坦使用给定的层，自定义使用默认参数
+    pub fn from_index(index: usize, layers: Vec<FlatLayer>) -> Self {
+        match index % Self::NAMES.len() {
+            0 => GeneratorPreset::Default,
+            1 => GeneratorPreset::Amplified,
+            2 => GeneratorPreset::Flat,
+            3 => GeneratorPreset::Superflat(layers),
+            _ => GeneratorPreset::Custom(CustomTerrain::default()),
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        match self {
+            GeneratorPreset::Default => 0,
+            GeneratorPreset::Amplified => 1,
+            GeneratorPreset::Flat => 2,
+            GeneratorPreset::Superflat(_) => 3,
+            GeneratorPreset::Custom(_) => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES[self.index()]
+    }
+
+    //超平坦没有噪声地形和矿石，只使用其中的海平面
+    pub fn settings(&self) -> TerrainSettings {
+        match self {
+            GeneratorPreset::Amplified => AMPLIFIED_TERRAIN,
+            GeneratorPreset::Flat => FLAT_TERRAIN,
+            GeneratorPreset::Default | GeneratorPreset::Superflat(_) => DEFAULT_TERRAIN,
+            GeneratorPreset::Custom(custom) => custom.settings(),
+        }
+    }
+}
+
+//超平坦的默认层：基岩、两层泥土、草方块
+pub fn default_flat_layers() -> Vec<FlatLayer> {
+    vec![
+        FlatLayer {
+            block: BlockType::UnderStone,
+            thickness: 1,
+        },
+        FlatLayer {
+            block: BlockType::Dirt,
+            thickness: 2,
+        },
+        FlatLayer {
+            block: BlockType::Grass,
+            thickness: 1,
+        },
+    ]
+}
+
+/*
+ * 解析超平坦层的文本，从下往上用逗号分隔，每层为 [数量*]方块名
+ * 例如 "bedrock,2*dirt,grass_block"
+ */
+pub fn parse_flat_layers(text: &str) -> anyhow::Result<Vec<FlatLayer>> {
+    let registry = block_registry::registry();
+    let mut layers = Vec::new();
+    let mut total: u32 = 0;
+    for part in text.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (thickness, name) = match part.split_once('*') {
+            Some((count, name)) => (
+                count
+                    .trim()
+                    .parse::<u32>()
+                    .with_context(|| format!("层数{}不是数字", count))?,
+                name.trim(),
+            ),
+            None => (1, part),
+        };
+        let Some(block) = registry.find(name) else {
+            bail!("没有名为{}的方块", name);
+        };
+        //层数是玩家输入的，相加时可能溢出
+        total = total
+            .checked_add(thickness)
+            .filter(|&total| total <= MAX_TERRAIN_HEIGHT as u32)
+            .with_context(|| format!("超平坦的总层数不能超过{}", MAX_TERRAIN_HEIGHT))?;
+        layers.push(FlatLayer { block, thickness });
+    }
+    if layers.is_empty() {
+        bail!("超平坦至少需要一层");
+    }
+    Ok(layers)
+}
+
+pub fn format_flat_layers(layers: &[FlatLayer]) -> String {
+    let registry = block_registry::registry();
+    layers
+        .iter()
+        .map(|layer| {
+            let name = &registry.get(layer.block).name;
+            if layer.thickness == 1 {
+                name.clone()
+            } else {
+                format!("{}*{}", layer.thickness, name)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/*
+ * 解析自定义生成器的参数，用逗号分隔，每项为 名称=值，没有写的参数使用默认值
+ * 例如 "octaves=6,lacunarity=2.5,amplitude=2,sea_level=48"
+ */
+pub fn parse_custom_terrain(text: &str) -> anyhow::Result<CustomTerrain> {
+    let mut custom = CustomTerrain::default();
+    for part in text.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((key, value)) = part.split_once('=') else {
+            bail!("参数{}缺少=", part);
+        };
+        let (key, value) = (key.trim(), value.trim());
+        match key {
+            "octaves" => custom.octaves = parse_in_range(key, value, &CUSTOM_OCTAVES)?,
+            "lacunarity" => custom.lacunarity = parse_in_range(key, value, &CUSTOM_LACUNARITY)?,
+            "amplitude" => custom.amplitude = parse_in_range(key, value, &CUSTOM_AMPLITUDE)?,
+            "sea_level" => custom.sea_level = parse_in_range(key, value, &CUSTOM_SEA_LEVEL)?,
+            _ => bail!("没有名为{}的参数", key),
+        }
+    }
+    Ok(custom)
+}
+
+fn parse_in_range<T>(key: &str, value: &str, range: &RangeInclusive<T>) -> anyhow::Result<T>
+where
+    T: FromStr + PartialOrd + Display,
+{
+    let parsed = value
+        .parse::<T>()
+        .ok()
+        .with_context(|| format!("参数{}的值{}不是数字", key, value))?;
+    if !range.contains(&parsed) {
+        bail!("参数{}需要在{}到{}之间", key, range.start(), range.end());
+    }
+    Ok(parsed)
+}
+
+pub fn format_custom_terrain(custom: &CustomTerrain) -> String {
+    format!(
+        "octaves={},lacunarity={},amplitude={},sea_level={}",
+        custom.octaves, custom.lacunarity, custom.amplitude, custom.sea_level
+    )
+}
+
+//多层Perlin噪声叠加，每层频率乘lacunarity、振幅乘persistence
+pub struct FractalNoise {
+    perlin: Perlin,
+    settings: NoiseSettings,
+}
+
+impl FractalNoise {
+    pub fn new(seed: u32, settings: NoiseSettings) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            settings,
+        }
+    }
+
+    //每层的(频率, 振幅)，振幅已经归一化，总和为1
+    fn octaves(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let NoiseSettings {
+            octaves,
+            scale,
+            lacunarity,
+            persistence,
+        } = self.settings;
+        let total: f64 = (0..octaves).map(|i| persistence.powi(i as i32)).sum();
+        (0..octaves).map(move |i| {
+            (
+                lacunarity.powi(i as i32) / scale,
+                persistence.powi(i as i32) / total,
+            )
+        })
+    }
+
+    //fBm噪声，结果在[-1, 1]
+    pub fn fbm(&self, x: f64, z: f64) -> f64 {
+        self.octaves()
+            .map(|(frequency, amplitude)| {
+                self.perlin.get([x * frequency, z * frequency]) * amplitude
+            })
+            .sum()
+    }
+
+    //山脊噪声，每层取(1-|n|)²，噪声过零的地方形成尖锐的山脊，结果在[-1, 1]
+    pub fn ridged(&self, x: f64, z: f64) -> f64 {
+        let sum: f64 = self
+            .octaves()
+            .map(|(frequency, amplitude)| {
+                let n = 1.0 - self.perlin.get([x * frequency, z * frequency]).abs();
+                n * n * amplitude
+            })
+            .sum();
+        sum * 2.0 - 1.0
+    }
+}
+
+/*
+ * 根据生成器参数和群系计算地面高度
+ * 几个噪声的种子都由世界种子得到，结果只和种子、生成器和坐标有关
+ */
+pub struct TerrainGenerator {
+    settings: TerrainSettings,
+    hills: FractalNoise,
+    ridges: FractalNoise,
+    pub biome_map: BiomeMap,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u32, preset: &GeneratorPreset) -> Self {
+        let settings = preset.settings();
+        Self {
+            settings,
+            hills: FractalNoise::new(seed.wrapping_add(10), settings.hills),
+            ridges: FractalNoise::new(seed.wrapping_add(11), settings.ridges),
+            biome_map: BiomeMap::new(seed),
+        }
+    }
+
+    pub fn settings(&self) -> &TerrainSettings {
+        &self.settings
+    }
+
+    //一列的地面高度，也就是最上面的方块的y+1
+    pub fn height(&self, x: i32, z: i32, profile: &HeightProfile) -> i32 {
+        let (x, z) = (x as f64, z as f64);
+        let detail = if profile.ridged <= 0.0 {
+            self.hills.fbm(x, z)
+        } else if profile.ridged >= 1.0 {
+            self.ridges.ridged(x, z)
+        } else {
+            self.hills.fbm(x, z) * (1.0 - profile.ridged)
+                + self.ridges.ridged(x, z) * profile.ridged
+        };
+        let offset =
+            (profile.base_height + detail * profile.height_scale) * self.settings.amplitude;
+        (self.settings.sea_level + offset as i32).clamp(1, MAX_TERRAIN_HEIGHT)
+    }
+
+    //从(area_x, area_z)开始、边长为size的范围内每一列的地面高度
+    pub fn area_heights(&self, area_x: i32, area_z: i32, size: i32) -> HeightMap {
+        let profiles = self.biome_map.blended_heights(area_x, area_z, size);
+        let mut heights = Vec::with_capacity(profiles.len());
+        for x in 0..size {
+            for z in 0..size {
+                heights.push(self.height(
+                    area_x + x,
+                    area_z + z,
+                    &profiles[(x * size + z) as usize],
+                ));
+            }
+        }
+        HeightMap {
+            area_x,
+            area_z,
+            size,
+            heights,
+        }
+    }
+
+    //区块及周围margin格以内每一列的地面高度
+    pub fn chunk_heights(&self, coord: &ChunkCoord, margin: i32) -> HeightMap {
+        self.area_heights(
+            coord.x * CHUNK_SIZE - margin,
+            coord.z * CHUNK_SIZE - margin,
+            CHUNK_SIZE + margin * 2,
+        )
+    }
+}
+
+//一块正方形范围内每一列的地面高度
+pub struct HeightMap {
+    area_x: i32,
+    area_z: i32,
+    size: i32,
+    //下标为 x * size + z
+    heights: Vec<i32>,
+}
+
+impl HeightMap {
+    //世界坐标，需要在范围内
+    pub fn get(&self, x: i32, z: i32) -> i32 {
+        let (x, z) = (x - self.area_x, z - self.area_z);
+        debug_assert!((0..self.size).contains(&x) && (0..self.size).contains(&z));
+        self.heights[(x * self.size + z) as usize]
+    }
+
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        (self.area_x..self.area_x + self.size).contains(&x)
+            && (self.area_z..self.area_z + self.size).contains(&z)
+    }
+}
+
+//出生点所在列的地面高度，玩家从这里出生时不会卡在地下
+pub fn surface_height(seed: u32, preset: &GeneratorPreset, x: i32, z: i32) -> i32 {
+    if let GeneratorPreset::Superflat(layers) = preset {
+        return layers.iter().map(|layer| layer.thickness as i32).sum();
+    }
+    TerrainGenerator::new(seed, preset)
+        .area_heights(x, z, 1)
+        .get(x, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fractal_noise_range() {
+        let noise = FractalNoise::new(3, DEFAULT_TERRAIN.ridges);
+        for i in 0..2000 {
+            let (x, z) = (i as f64 * 7.3, i as f64 * -3.1);
+            assert!((-1.0..=1.0).contains(&noise.fbm(x, z)));
+            assert!((-1.0..=1.0).contains(&noise.ridged(x, z)));
+        }
+    }
+
+    #[test]
+    fn test_presets() {
+        //平坦世界全部在海平面，放大化的高度范围比默认的大
+        let coords: Vec<ChunkCoord> = (-24..24)
+            .step_by(6)
+            .flat_map(|x| (-24..24).step_by(6).map(move |z| ChunkCoord::new(x, z)))
+            .collect();
+        let range = |preset: &GeneratorPreset| {
+            let generator = TerrainGenerator::new(42, preset);
+            let heights: Vec<i32> = coords
+                .iter()
+                .flat_map(|coord| generator.chunk_heights(coord, 0).heights)
+                .collect();
+            (
+                *heights.iter().min().unwrap(),
+                *heights.iter().max().unwrap(),
+            )
+        };
+        let sea_level = FLAT_TERRAIN.sea_level;
+        assert_eq!(range(&GeneratorPreset::Flat), (sea_level, sea_level));
+        let (default_min, default_max) = range(&GeneratorPreset::Default);
+        let (amplified_min, amplified_max) = range(&GeneratorPreset::Amplified);
+        assert!(amplified_max - amplified_min > (default_max - default_min) * 2);
+        assert!(amplified_max <= MAX_TERRAIN_HEIGHT);
+
+        //存档中保存的生成器读回来相同
+        let preset = GeneratorPreset::Superflat(default_flat_layers());
+        let bytes = bincode::serialize(&preset).unwrap();
+        assert_eq!(
+            bincode::deserialize::<GeneratorPreset>(&bytes).unwrap(),
+            preset
+        );
+        assert_eq!(surface_height(1, &preset, -5, 7), 4);
+        assert_eq!(
+            GeneratorPreset::from_index(preset.index(), Vec::new()).name(),
+            "超平坦"
+        );
+    }
+
+    #[test]
+    fn test_custom_terrain() {
+        let custom = parse_custom_terrain("octaves=6, amplitude=2.5,sea_level=48").unwrap();
+        assert_eq!(
+            custom,
+            CustomTerrain {
+                octaves: 6,
+                lacunarity: 2.0,
+                amplitude: 2.5,
+                sea_level: 48,
+            }
+        );
+        assert_eq!(
+            parse_custom_terrain(&format_custom_terrain(&custom)).unwrap(),
+            custom
+        );
+        assert_eq!(parse_custom_terrain("").unwrap(), CustomTerrain::default());
+
+        assert!(parse_custom_terrain("octaves").is_err());
+        assert!(parse_custom_terrain("height=3").is_err());
+        assert!(parse_custom_terrain("octaves=many").is_err());
+        assert!(parse_custom_terrain("octaves=100").is_err());
+        assert!(parse_custom_terrain("amplitude=NaN").is_err());
+        assert!(parse_custom_terrain("sea_level=0").is_err());
+
+        //存档中超出范围的参数被限制
+        let preset = GeneratorPreset::Custom(CustomTerrain {
+            octaves: 1000,
+            lacunarity: f64::NAN,
+            amplitude: -1.0,
+            sea_level: i32::MAX,
+        });
+        let settings = preset.settings();
+        assert_eq!(settings.hills.octaves, 8);
+        assert_eq!(settings.hills.lacunarity, 2.0);
+        assert_eq!(settings.amplitude, 0.0);
+        assert_eq!(settings.sea_level, MAX_TERRAIN_HEIGHT);
+        let bytes = bincode::serialize(&preset).unwrap();
+        assert!(matches!(
+            bincode::deserialize::<GeneratorPreset>(&bytes).unwrap(),
+            GeneratorPreset::Custom(_)
+        ));
+
+        //自定义的海平面和振幅用于生成地形
+        let flat = GeneratorPreset::Custom(CustomTerrain {
+            amplitude: 0.0,
+            sea_level: 60,
+            ..CustomTerrain::default()
+        });
+        assert_eq!(surface_height(7, &flat, 3, -9), 60);
+    }
+
+    #[test]
+    fn test_flat_layers_text() {
+        let layers = parse_flat_layers("bedrock, 3*stone,grass_block").unwrap();
+        assert_eq!(
+            layers,
+            vec![
+                FlatLayer {
+                    block: BlockType::UnderStone,
+                    thickness: 1
+                },
+                FlatLayer {
+                    block: BlockType::Stone,
+                    thickness: 3
+                },
+                FlatLayer {
+                    block: BlockType::Grass,
+                    thickness: 1
+                },
+            ]
+        );
+        assert_eq!(format_flat_layers(&layers), "bedrock,3*stone,grass_block");
+        assert_eq!(
+            parse_flat_layers(&format_flat_layers(&default_flat_layers())).unwrap(),
+            default_flat_layers()
+        );
+
+        assert!(parse_flat_layers("").is_err());
+        assert!(parse_flat_layers("x*dirt").is_err());
+        assert!(parse_flat_layers("not_a_block").is_err());
+        assert!(parse_flat_layers("1000*stone").is_err());
+        assert!(parse_flat_layers("4294967295*stone,stone").is_err());
+        assert!(parse_flat_layers("4294967295*stone").is_err());
+    }
+}
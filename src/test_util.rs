@@ -0,0 +1,8 @@
+use std::path::PathBuf;
+
+//测试用的临时目录，每个测试使用不同的名字，开始前清空
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("game_temp_tests").join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
@@ -3,7 +3,6 @@
 //items from traits can only be used if the trait is in scope
 use image::GenericImageView;
 
-use crate::realm;
 use anyhow::*;
 
 pub struct Texture {
@@ -14,8 +13,10 @@ pub struct Texture {
 }
 
 impl Texture {
+    //textures为贴图名，按顺序放入纹理数组的每一层
     pub fn load_blocks(
         file_path: &str,
+        textures: &[String],
         device: &wgpu::Device,
         queue: &wgpu::Queue,
     ) -> Result<Self> {
@@ -24,7 +25,7 @@ impl Texture {
         let size = wgpu::Extent3d {
             width: dimention.0,
             height: dimention.1,
-            depth_or_array_layers: realm::BLOCK_MATERIALS_NUM as u32,
+            depth_or_array_layers: textures.len() as u32,
         };
 
         let texture_array = device.create_texture(&wgpu::TextureDescriptor {
@@ -38,18 +39,31 @@ impl Texture {
             view_formats: &[],
         });
 
-        for z in 0..realm::BLOCK_MATERIALS_NUM {
-            let path = format!("{}/{}.png", file_path, materials_enum_to_string(z));
-            let bytes = std::fs::read(&path).unwrap();
-
-            let img = image::load_from_memory(&bytes).unwrap();
+        for (z, name) in textures.iter().enumerate() {
+            let path = format!("{}/{}.png", file_path, name);
+            let bytes = std::fs::read(&path).with_context(|| format!("读取贴图{}失败", path))?;
+
+            let mut img =
+                image::load_from_memory(&bytes).with_context(|| format!("贴图{}格式错误", path))?;
+            //尺寸不对的贴图缩放到数组的尺寸
+            if img.dimensions() != dimention {
+                img = img.resize_exact(
+                    dimention.0,
+                    dimention.1,
+                    image::imageops::FilterType::Nearest,
+                );
+            }
             let rgba = img.to_rgba8();
 
             queue.write_texture(
                 wgpu::TexelCopyTextureInfo {
                     texture: &texture_array,
                     mip_level: 0,
-                    origin: wgpu::Origin3d { x: 0, y: 0, z: z },
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: z as u32,
+                    },
                     aspect: wgpu::TextureAspect::All,
                 },
                 &rgba,
@@ -70,9 +84,10 @@ impl Texture {
             dimension: Some(wgpu::TextureViewDimension::D2Array),
             ..Default::default()
         });
+        //合并后的面跨越多个方块，贴图需要重复
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             mag_filter: wgpu::FilterMode::Nearest,
             min_filter: wgpu::FilterMode::Nearest,
@@ -87,6 +102,7 @@ impl Texture {
         })
     }
 
+    #[allow(unused)]
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -204,22 +220,3 @@ impl Texture {
         }
     }
 }
-
-fn materials_enum_to_string(i: u32) -> &'static str {
-    use num_traits::FromPrimitive;
-    use realm::BlockMaterials;
-    use realm::BlockMaterials::*;
-    match BlockMaterials::from_u32(i).unwrap() {
-        UnderStone => "bedrock",
-        Stone => "stone",
-        GrassBlockSide => "grass_block_side",
-        GrassBlockTop => "grass_block_top",
-        Dirt => "dirt",
-        BirchLog => "oak_log",
-        BirchLeaves => "oak_leaves",
-        BirchLogTop => "oak_log_top",
-        BirchPlank => "birch_planks",
-        TestBlock => "skin",
-        Empty => "",
-    }
-}
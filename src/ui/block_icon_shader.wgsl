@@ -22,10 +22,6 @@ struct ScreenSize {
 @group(0) @binding(0)
 var<uniform> screen_size: ScreenSize;
 
-struct MaterialUniform {
-    index: vec4<u32>,
-}
-
 //标记为vertex shader
 @vertex
 fn vs_main(
@@ -96,7 +92,7 @@ fn vs_main(
     );
 
     let material_index = instance.block_type * 6u + face_index;
-    out.layer = block_materials[material_index].index[0];
+    out.layer = block_materials[material_index];
     
     return out;
 }
@@ -107,9 +103,9 @@ var t_diffuse: texture_2d_array<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 
-// 为了符合步长要求 使用结构体
+// 每个方块每个面的纹理层，长度为方块数量*6
 @group(2) @binding(0)
-var<uniform> block_materials: array<MaterialUniform, 30>;
+var<storage, read> block_materials: array<u32>;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
@@ -354,7 +354,7 @@ impl BlockRenderer {
         let mut all_item_inventory_instances: Vec<realm::Instance> = Vec::new();
         for (i, items) in all_item_inventory.iter().enumerate() {
             for (j, item) in items.iter().enumerate() {
-                if item.item_type.get_type() == BlockType::Empty as u32 {
+                if item.item_type.get_type() == BlockType::Empty.0 {
                     return all_item_inventory_instances;
                 }
 
@@ -256,6 +256,10 @@ impl InventoryRenderer {
         self.iv_slot_instances = iv_slot_instances;
     }
 
+    pub fn shb_index(&self) -> i32 {
+        self.shb_index
+    }
+
     pub fn update_shb(&mut self, offset: i32, queue: &Queue) {
         let mut x = self.shb_index + offset;
         x += SLOTS_PER_ROW as i32;
@@ -0,0 +1,226 @@
+use glyphon::{Color, TextBounds};
+use wgpu::{util::DeviceExt, *};
+use winit::dpi::PhysicalSize;
+
+use crate::ui::{ui_text_renderer::UITextRenderer, UIInstance, UIVertex};
+
+pub const MENU_ITEM_WIDTH: f32 = 640.0;
+pub const MENU_ITEM_HEIGHT: f32 = 56.0;
+pub const MENU_ITEM_SPACING: f32 = 12.0;
+const MENU_TITLE_HEIGHT: f32 = 80.0;
+const MENU_TEXT_PADDING: f32 = 16.0;
+//文本id前缀，离开菜单时统一移除
+const MENU_TEXT_PREFIX: &str = "menu_";
+
+const MENU_BG_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const MENU_ITEM_COLOR: [f32; 4] = [0.2, 0.4, 0.3, 0.8];
+const MENU_SELECTED_COLOR: [f32; 4] = [0.19, 0.68, 0.58, 0.95];
+const MENU_TEXT_COLOR: Color = Color::rgb(255, 255, 255);
+const MENU_FOOTER_COLOR: Color = Color::rgb(200, 200, 200);
+
+/*
+ * 一页菜单：标题 + 竖直排列的选项 + 底部提示
+ * 开始菜单和暂停菜单都用它来描述要显示的内容
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MenuPage {
+    pub title: String,
+    pub items: Vec<String>,
+    pub selected: usize,
+    //底部的提示或错误信息
+    pub footer: String,
+}
+
+//菜单内容或窗口大小变化时才重新生成顶点和文本
+pub struct MenuRenderer {
+    page: Option<MenuPage>,
+    physical_size: PhysicalSize<u32>,
+    vertex_buffer: Option<Buffer>,
+    index_buffer: Option<Buffer>,
+    num_indices: u32,
+    instance_buffer: Buffer,
+}
+
+impl MenuRenderer {
+    pub fn new(device: &Device) -> Self {
+        let instance_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("Menu Instance Buffer"),
+            contents: bytemuck::cast_slice(&[UIInstance::new(0.0, 0.0)]),
+            usage: BufferUsages::VERTEX,
+        });
+
+        Self {
+            page: None,
+            physical_size: PhysicalSize::new(0, 0),
+            vertex_buffer: None,
+            index_buffer: None,
+            num_indices: 0,
+            instance_buffer,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        page: &MenuPage,
+        physical_size: PhysicalSize<u32>,
+        device: &Device,
+        text_renderer: &mut UITextRenderer,
+    ) {
+        if self.page.as_ref() == Some(page) && self.physical_size == physical_size {
+            return;
+        }
+        self.clear(text_renderer);
+
+        let mut vertices: Vec<UIVertex> = Vec::new();
+        let mut indices: Vec<u16> = Vec::new();
+        //整个屏幕变暗
+        push_rect(
+            &mut vertices,
+            &mut indices,
+            [
+                0.0,
+                0.0,
+                physical_size.width as f32,
+                physical_size.height as f32,
+            ],
+            MENU_BG_COLOR,
+        );
+
+        let title_top = menu_item_rect(0, page.items.len(), physical_size)[1] - MENU_TITLE_HEIGHT;
+        text_renderer.add_text(
+            &format!("{}title", MENU_TEXT_PREFIX),
+            &page.title,
+            physical_size.width as f32 / 2.0 - MENU_ITEM_WIDTH / 2.0,
+            title_top,
+            TextBounds::default(),
+            MENU_TEXT_COLOR,
+        );
+
+        for (i, item) in page.items.iter().enumerate() {
+            let rect = menu_item_rect(i, page.items.len(), physical_size);
+            let color = if i == page.selected {
+                MENU_SELECTED_COLOR
+            } else {
+                MENU_ITEM_COLOR
+            };
+            push_rect(&mut vertices, &mut indices, rect, color);
+
+            text_renderer.add_text(
+                &format!("{}item_{}", MENU_TEXT_PREFIX, i),
+                item,
+                rect[0] + MENU_TEXT_PADDING,
+                rect[1] + (MENU_ITEM_HEIGHT - super::ui_text_renderer::LINE_HEIGHT) / 2.0,
+                TextBounds {
+                    left: rect[0] as i32,
+                    top: rect[1] as i32,
+                    right: rect[2] as i32,
+                    bottom: rect[3] as i32,
+                },
+                MENU_TEXT_COLOR,
+            );
+        }
+
+        let footer_top = menu_item_rect(page.items.len(), page.items.len(), physical_size)[1];
+        text_renderer.add_text(
+            &format!("{}footer", MENU_TEXT_PREFIX),
+            &page.footer,
+            physical_size.width as f32 / 2.0 - MENU_ITEM_WIDTH / 2.0,
+            footer_top,
+            TextBounds::default(),
+            MENU_FOOTER_COLOR,
+        );
+
+        self.vertex_buffer = Some(device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("Menu Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: BufferUsages::VERTEX,
+        }));
+        self.index_buffer = Some(device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("Menu Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: BufferUsages::INDEX,
+        }));
+        self.num_indices = indices.len() as u32;
+        self.page = Some(page.clone());
+        self.physical_size = physical_size;
+    }
+
+    //离开菜单时移除菜单的文本
+    pub fn clear(&mut self, text_renderer: &mut UITextRenderer) {
+        text_renderer
+            .text_entries
+            .retain(|id, _| !id.starts_with(MENU_TEXT_PREFIX));
+        self.page = None;
+        self.vertex_buffer = None;
+        self.index_buffer = None;
+    }
+
+    //鼠标所在的选项
+    pub fn item_at(&self, x: f32, y: f32) -> Option<usize> {
+        let page = self.page.as_ref()?;
+        (0..page.items.len()).find(|&i| {
+            let rect = menu_item_rect(i, page.items.len(), self.physical_size);
+            x >= rect[0] && x <= rect[2] && y >= rect[1] && y <= rect[3]
+        })
+    }
+
+    //使用UI的纯色管线绘制
+    pub fn draw(&self, render_pass: &mut RenderPass, screen_size_uniform_bind_group: &BindGroup) {
+        let (Some(vertex_buffer), Some(index_buffer)) = (&self.vertex_buffer, &self.index_buffer)
+        else {
+            return;
+        };
+        render_pass.set_bind_group(0, screen_size_uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(index_buffer.slice(..), IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+//第index个选项的 [左, 上, 右, 下]，index等于选项数量时为底部提示的位置
+pub fn menu_item_rect(
+    index: usize,
+    item_count: usize,
+    physical_size: PhysicalSize<u32>,
+) -> [f32; 4] {
+    let total_height =
+        MENU_TITLE_HEIGHT + (item_count + 1) as f32 * (MENU_ITEM_HEIGHT + MENU_ITEM_SPACING);
+    let top = ((physical_size.height as f32 - total_height) / 2.0).max(0.0) + MENU_TITLE_HEIGHT;
+    let left = physical_size.width as f32 / 2.0 - MENU_ITEM_WIDTH / 2.0;
+    let item_top = top + index as f32 * (MENU_ITEM_HEIGHT + MENU_ITEM_SPACING);
+    [
+        left,
+        item_top,
+        left + MENU_ITEM_WIDTH,
+        item_top + MENU_ITEM_HEIGHT,
+    ]
+}
+
+//顺时针四个点 从左上角开始
+fn push_rect(
+    vertices: &mut Vec<UIVertex>,
+    indices: &mut Vec<u16>,
+    rect: [f32; 4],
+    color: [f32; 4],
+) {
+    let base = vertices.len() as u16;
+    let [left, top, right, bottom] = rect;
+    vertices.push(UIVertex {
+        position: [left, top],
+        color,
+    });
+    vertices.push(UIVertex {
+        position: [right, top],
+        color,
+    });
+    vertices.push(UIVertex {
+        position: [right, bottom],
+        color,
+    });
+    vertices.push(UIVertex {
+        position: [left, bottom],
+        color,
+    });
+    indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+}
@@ -12,6 +12,9 @@ use inventory_renderer::{InventoryRenderer, SLOTS_PER_COLUMN};
 
 mod block_renderer;
 pub mod inventory_renderer;
+pub mod menu_renderer;
+pub mod pause_menu;
+pub mod start_menu;
 pub mod ui_text_renderer;
 
 const CURSOR_HALF_SIZE: f32 = 12.0;
@@ -95,6 +98,7 @@ pub struct UI {
     instances: Vec<UIInstance>,
     inventory_renderer: InventoryRenderer,
     block_renderer: block_renderer::BlockRenderer,
+    menu_renderer: menu_renderer::MenuRenderer,
 
     cursor_position: cgmath::Point2<f32>,
 }
@@ -204,6 +208,8 @@ impl UI {
             texture_bind_group_layout,
         );
 
+        let menu_renderer = menu_renderer::MenuRenderer::new(device);
+
         let is_hotbar_open = true;
         let is_invenory_open = false;
         let is_debug_info_open = false;
@@ -219,6 +225,7 @@ impl UI {
             instance_buffer,
             instances,
             block_renderer,
+            menu_renderer,
             is_hotbar_open,
             is_invenory_open,
             is_debug_info_open,
@@ -401,10 +408,93 @@ impl UI {
             );
         }
 
+        //暂停菜单绘制在其他UI之上
+        render_pass.set_pipeline(&self.inventory_renderer.render_pipeline);
+        self.menu_renderer
+            .draw(&mut render_pass, &self.screen_size_uniform_bind_group);
+
         self.ui_text_renderer
             .draw_text(device, queue, &mut render_pass, self.is_debug_info_open);
     }
 
+    pub fn update_menu(
+        &mut self,
+        page: &menu_renderer::MenuPage,
+        physical_size: winit::dpi::PhysicalSize<u32>,
+        device: &wgpu::Device,
+    ) {
+        self.menu_renderer
+            .update(page, physical_size, device, &mut self.ui_text_renderer);
+    }
+
+    //只绘制菜单，load决定是否清空屏幕
+    pub fn draw_menu(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        load: LoadOp<wgpu::Color>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Menu Render Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        render_pass.set_pipeline(&self.inventory_renderer.render_pipeline);
+        self.menu_renderer
+            .draw(&mut render_pass, &self.screen_size_uniform_bind_group);
+        self.ui_text_renderer
+            .draw_text(device, queue, &mut render_pass, false);
+    }
+
+    pub fn close_menu(&mut self) {
+        self.menu_renderer.clear(&mut self.ui_text_renderer);
+    }
+
+    //暂停时关闭物品栏，放弃正在拖动的物品
+    pub fn close_inventory(&mut self) {
+        self.is_invenory_open = false;
+        self.is_hotbar_open = true;
+        self.inventory_renderer.is_dragging = false;
+    }
+
+    //记录鼠标位置，返回鼠标所在的菜单选项
+    pub fn hovered_menu_item(&mut self, event: &WindowEvent) -> Option<usize> {
+        if let WindowEvent::CursorMoved { position, .. } = event {
+            self.cursor_position.x = position.x as f32;
+            self.cursor_position.y = position.y as f32;
+        }
+        self.menu_renderer
+            .item_at(self.cursor_position.x, self.cursor_position.y)
+    }
+
+    //进入世界后物品栏变化，重新生成物品栏图标
+    pub fn reload_hotbar(
+        &mut self,
+        queue: &Queue,
+        player: &Player,
+        physical_size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        let block_renderer = &mut self.inventory_renderer.block_renderer;
+        block_renderer.recreate_hb_instance_buffer(queue, player);
+        block_renderer.update_iv_hb(player, physical_size, queue);
+        self.inventory_renderer.update_shb(
+            player.slected_hotbar - self.inventory_renderer.shb_index(),
+            queue,
+        );
+    }
+
     pub fn update_ui(&mut self, position: cgmath::Point3<f32>, dt: f64, realm: &realm::Realm) {
         self.ui_text_renderer.update_debug_info(position, dt, realm);
     }
@@ -485,7 +575,7 @@ impl UI {
                                 let tp = player.all_item_inventory[y as usize][x as usize]
                                     .item_type
                                     .get_type();
-                                if tp != realm::BlockType::Empty as u32 {
+                                if tp != realm::BlockType::Empty.0 {
                                     self.inventory_renderer.is_dragging = true;
                                     self.inventory_renderer.create_dragging_instance(
                                         tp,
@@ -516,8 +606,9 @@ impl UI {
                             .unwrap()
                             .block_type;
 
-                        let new_item =
-                            item::Item::new(item::ItemType::Block(all_block[selected_tp as usize]));
+                        let new_item = item::Item::new(item::ItemType::Block(
+                            all_block[selected_tp as usize].clone(),
+                        ));
                         //println!("set hotbar[{}] = {:?}", y, new_item);
                         player.hotbar[x as usize] = new_item;
                         self.inventory_renderer.is_dragging = false;
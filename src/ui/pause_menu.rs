@@ -0,0 +1,279 @@
+use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::game_config::{GameConfig, SENSITIVITY_MAX, SENSITIVITY_MIN};
+use crate::realm::{MAX_CHUNK_RAD, MIN_CHUNK_RAD};
+use crate::ui::menu_renderer::MenuPage;
+
+//可选的最大帧数，0表示无限制
+const MAX_FPS_OPTIONS: [u32; 6] = [0, 30, 60, 120, 144, 240];
+//鼠标灵敏度每次调整的倍数
+const SENSITIVITY_STEP: f32 = 0.1;
+
+pub enum PauseMenuAction {
+    Resume,
+    SaveAndQuit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Screen {
+    Main,
+    Settings,
+}
+
+/*
+ * 暂停时菜单处理的输入：键盘和鼠标
+ * 重绘、关闭窗口、改变大小等事件仍然交给窗口处理，否则暂停后画面停止、窗口无法关闭
+ */
+pub fn is_menu_input(event: &WindowEvent) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+    )
+}
+
+/*
+ * 暂停菜单：继续游戏、设置、保存并退出到标题
+ * 设置直接修改GameConfig
+ */
+pub struct PauseMenu {
+    screen: Screen,
+    selected: usize,
+}
+
+impl PauseMenu {
+    pub fn new() -> Self {
+        Self {
+            screen: Screen::Main,
+            selected: 0,
+        }
+    }
+
+    //每次暂停都从主页面开始
+    pub fn reset(&mut self) {
+        self.screen = Screen::Main;
+        self.selected = 0;
+    }
+
+    pub fn page(&self, game_config: &GameConfig) -> MenuPage {
+        let (title, items, footer) = match self.screen {
+            Screen::Main => (
+                "游戏暂停",
+                vec![
+                    "继续游戏".to_string(),
+                    "设置".to_string(),
+                    "保存并退出到标题".to_string(),
+                ],
+                "Esc继续游戏",
+            ),
+            Screen::Settings => (
+                "设置",
+                vec![
+                    format!(
+                        "鼠标灵敏度: {:.0}%",
+                        game_config.mouse_sensitivity_scale * 100.0
+                    ),
+                    match game_config.get_max_fps() {
+                        0 => "最大帧数: 无限制".to_string(),
+                        fps => format!("最大帧数: {}", fps),
+                    },
+                    format!("视距: {}区块", game_config.get_render_distance()),
+                    "返回".to_string(),
+                ],
+                "左右方向键调整  Esc返回",
+            ),
+        };
+        MenuPage {
+            title: title.to_string(),
+            items,
+            selected: self.selected,
+            footer: footer.to_string(),
+        }
+    }
+
+    //hovered为鼠标所在的选项
+    pub fn process_events(
+        &mut self,
+        event: &WindowEvent,
+        hovered: Option<usize>,
+        game_config: &mut GameConfig,
+    ) -> Option<PauseMenuAction> {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(keycode),
+                        ..
+                    },
+                ..
+            } => match keycode {
+                KeyCode::ArrowUp => {
+                    self.move_selection(-1);
+                    None
+                }
+                KeyCode::ArrowDown | KeyCode::Tab => {
+                    self.move_selection(1);
+                    None
+                }
+                KeyCode::ArrowLeft => {
+                    self.adjust(self.selected, -1, game_config);
+                    None
+                }
+                KeyCode::ArrowRight => {
+                    self.adjust(self.selected, 1, game_config);
+                    None
+                }
+                KeyCode::Enter | KeyCode::NumpadEnter => self.activate(self.selected, game_config),
+                KeyCode::Escape => match self.screen {
+                    Screen::Main => Some(PauseMenuAction::Resume),
+                    Screen::Settings => {
+                        self.reset();
+                        None
+                    }
+                },
+                _ => None,
+            },
+            WindowEvent::CursorMoved { .. } => {
+                if let Some(i) = hovered {
+                    self.selected = i;
+                }
+                None
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let i = hovered?;
+                self.selected = i;
+                self.activate(i, game_config)
+            }
+            _ => None,
+        }
+    }
+
+    fn move_selection(&mut self, offset: i32) {
+        let count = match self.screen {
+            Screen::Main => 3,
+            Screen::Settings => 4,
+        };
+        self.selected = (self.selected as i32 + offset).rem_euclid(count) as usize;
+    }
+
+    fn activate(&mut self, index: usize, game_config: &mut GameConfig) -> Option<PauseMenuAction> {
+        match (self.screen, index) {
+            (Screen::Main, 0) => return Some(PauseMenuAction::Resume),
+            (Screen::Main, 1) => {
+                self.screen = Screen::Settings;
+                self.selected = 0;
+            }
+            (Screen::Main, _) => return Some(PauseMenuAction::SaveAndQuit),
+            //点击设置项时循环调整
+            (Screen::Settings, 0) => {
+                if game_config.mouse_sensitivity_scale >= SENSITIVITY_MAX - f32::EPSILON {
+                    game_config.mouse_sensitivity_scale = SENSITIVITY_MIN;
+                } else {
+                    self.adjust(index, 1, game_config);
+                }
+            }
+            (Screen::Settings, 1) => self.adjust(index, 1, game_config),
+            (Screen::Settings, 2) => {
+                if game_config.get_render_distance() >= MAX_CHUNK_RAD {
+                    game_config.set_render_distance(MIN_CHUNK_RAD);
+                } else {
+                    self.adjust(index, 1, game_config);
+                }
+            }
+            (Screen::Settings, _) => self.reset(),
+        }
+        None
+    }
+
+    //调整第index个设置项，direction为1或-1
+    fn adjust(&mut self, index: usize, direction: i32, game_config: &mut GameConfig) {
+        if self.screen != Screen::Settings {
+            return;
+        }
+        match index {
+            0 => {
+                let scale =
+                    game_config.mouse_sensitivity_scale + SENSITIVITY_STEP * direction as f32;
+                //避免浮点误差累积
+                game_config.mouse_sensitivity_scale =
+                    ((scale * 10.0).round() / 10.0).clamp(SENSITIVITY_MIN, SENSITIVITY_MAX);
+            }
+            1 => {
+                let current = MAX_FPS_OPTIONS
+                    .iter()
+                    .position(|&fps| fps == game_config.get_max_fps())
+                    .unwrap_or(0) as i32;
+                let next = (current + direction).rem_euclid(MAX_FPS_OPTIONS.len() as i32);
+                game_config.set_max_fps(MAX_FPS_OPTIONS[next as usize]);
+            }
+            2 => game_config.set_render_distance(game_config.get_render_distance() + direction),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_menu_settings() {
+        let mut game_config = GameConfig::new();
+        let mut menu = PauseMenu::new();
+        assert!(matches!(
+            menu.activate(0, &mut game_config),
+            Some(PauseMenuAction::Resume)
+        ));
+
+        assert!(menu.activate(1, &mut game_config).is_none());
+        assert_eq!(menu.page(&game_config).title, "设置");
+
+        menu.adjust(0, 1, &mut game_config);
+        assert_eq!(game_config.mouse_sensitivity_scale, 1.1);
+        for _ in 0..100 {
+            menu.adjust(0, -1, &mut game_config);
+        }
+        assert_eq!(game_config.mouse_sensitivity_scale, SENSITIVITY_MIN);
+
+        menu.adjust(1, 1, &mut game_config);
+        assert_eq!(game_config.get_max_fps(), 30);
+        menu.adjust(1, -1, &mut game_config);
+        menu.adjust(1, -1, &mut game_config);
+        assert_eq!(game_config.get_max_fps(), 240);
+
+        let distance = game_config.get_render_distance();
+        menu.adjust(2, 1, &mut game_config);
+        assert_eq!(game_config.get_render_distance(), distance + 1);
+        game_config.set_render_distance(MAX_CHUNK_RAD);
+        assert!(menu.activate(2, &mut game_config).is_none());
+        assert_eq!(game_config.get_render_distance(), MIN_CHUNK_RAD);
+        //设置页面有4个选项
+        menu.move_selection(-1);
+        assert_eq!(menu.page(&game_config).selected, 3);
+
+        //返回主页面
+        assert!(menu.activate(3, &mut game_config).is_none());
+        assert!(matches!(
+            menu.activate(2, &mut game_config),
+            Some(PauseMenuAction::SaveAndQuit)
+        ));
+    }
+
+    #[test]
+    fn test_pause_menu_passes_window_events() {
+        assert!(!is_menu_input(&WindowEvent::RedrawRequested));
+        assert!(!is_menu_input(&WindowEvent::CloseRequested));
+        assert!(!is_menu_input(&WindowEvent::Resized(
+            winit::dpi::PhysicalSize::new(800, 600)
+        )));
+        assert!(!is_menu_input(&WindowEvent::Focused(false)));
+    }
+}
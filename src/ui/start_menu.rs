@@ -0,0 +1,483 @@
+use std::path::PathBuf;
+
+use winit::event::{ElementState, KeyEvent, MouseButton, WindowEvent};
+use winit::keyboard::{KeyCode, PhysicalKey};
+
+use crate::level::{self, WorldSummary};
+use crate::terrain::{self, CustomTerrain, GeneratorPreset};
+use crate::ui::menu_renderer::MenuPage;
+
+//GeneratorPreset::NAMES中超平坦和自定义的下标，只有这两个生成器有可以编辑的参数
+const SUPERFLAT: usize = 3;
+const CUSTOM: usize = 4;
+
+pub enum StartMenuAction {
+    //打开worlds目录下的这个世界
+    OpenWorld(String),
+}
+
+enum Screen {
+    WorldList,
+    CreateWorld {
+        name: String,
+        seed: String,
+        //GeneratorPreset::NAMES中的下标
+        generator: usize,
+        //超平坦的层，格式见terrain::parse_flat_layers
+        layers: String,
+        //自定义生成器的参数，格式见terrain::parse_custom_terrain
+        custom: String,
+    },
+    RenameWorld {
+        dir_name: String,
+        name: String,
+    },
+    DeleteWorld {
+        dir_name: String,
+    },
+}
+
+/*
+ * 开始菜单：列出已有世界，新建、重命名、删除世界
+ * 选中世界后由State创建Realm
+ */
+pub struct StartMenu {
+    worlds_dir: PathBuf,
+    worlds: Vec<WorldSummary>,
+    screen: Screen,
+    selected: usize,
+    //操作失败时显示在底部
+    message: String,
+}
+
+impl StartMenu {
+    pub fn new(worlds_dir: PathBuf) -> Self {
+        let worlds = level::list_worlds(&worlds_dir);
+        Self {
+            worlds_dir,
+            worlds,
+            screen: Screen::WorldList,
+            selected: 0,
+            message: String::new(),
+        }
+    }
+
+    //回到世界列表并重新读取
+    pub fn refresh(&mut self) {
+        self.worlds = level::list_worlds(&self.worlds_dir);
+        self.screen = Screen::WorldList;
+        self.selected = self.selected.min(self.worlds.len());
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    pub fn page(&self) -> MenuPage {
+        let (title, items, hint) = match &self.screen {
+            Screen::WorldList => {
+                let mut items: Vec<String> = self
+                    .worlds
+                    .iter()
+                    .map(|world| match &world.level {
+                        Some(level) => format!(
+                            "{}  种子:{}  生成器:{}  游戏时间:{}分钟",
+                            world.display_name(),
+                            level.seed,
+                            level.generator.name(),
+                            (level.game_time / 60.0) as u64
+                        ),
+                        None => format!("{}  (没有世界信息)", world.dir_name),
+                    })
+                    .collect();
+                items.push("创建新世界".to_string());
+                (
+                    "选择世界".to_string(),
+                    items,
+                    "Enter进入  N新建  R重命名  Delete删除",
+                )
+            }
+            Screen::CreateWorld {
+                name,
+                seed,
+                generator,
+                layers,
+                custom,
+            } => (
+                "创建新世界".to_string(),
+                vec![
+                    format!("名称: {}{}", name, self.text_cursor(0)),
+                    if seed.is_empty() && self.selected != 1 {
+                        "种子: (留空随机)".to_string()
+                    } else {
+                        format!("种子: {}{}", seed, self.text_cursor(1))
+                    },
+                    format!("生成器: {}", GeneratorPreset::NAMES[*generator]),
+                    match *generator {
+                        SUPERFLAT => format!("超平坦层: {}{}", layers, self.text_cursor(3)),
+                        CUSTOM => format!("地形参数: {}{}", custom, self.text_cursor(3)),
+                        _ => "生成器参数: (仅超平坦和自定义使用)".to_string(),
+                    },
+                    "创建".to_string(),
+                    "返回".to_string(),
+                ],
+                "输入文字编辑  Tab/方向键切换  Esc返回",
+            ),
+            Screen::RenameWorld { dir_name, name } => (
+                format!("重命名世界 {}", dir_name),
+                vec![
+                    format!("新名称: {}{}", name, self.text_cursor(0)),
+                    "确定".to_string(),
+                    "返回".to_string(),
+                ],
+                "输入文字编辑  Esc返回",
+            ),
+            Screen::DeleteWorld { dir_name } => (
+                format!("删除世界 {} ？此操作无法撤销", dir_name),
+                vec!["删除".to_string(), "返回".to_string()],
+                "Esc返回",
+            ),
+        };
+
+        let footer = if self.message.is_empty() {
+            hint.to_string()
+        } else {
+            self.message.clone()
+        };
+        MenuPage {
+            title,
+            items,
+            selected: self.selected,
+            footer,
+        }
+    }
+
+    //hovered为鼠标所在的选项
+    pub fn process_events(
+        &mut self,
+        event: &WindowEvent,
+        hovered: Option<usize>,
+    ) -> Option<StartMenuAction> {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        state: ElementState::Pressed,
+                        physical_key: PhysicalKey::Code(keycode),
+                        text,
+                        ..
+                    },
+                ..
+            } => {
+                match keycode {
+                    KeyCode::ArrowUp => self.move_selection(-1),
+                    KeyCode::ArrowDown | KeyCode::Tab => self.move_selection(1),
+                    KeyCode::Enter | KeyCode::NumpadEnter => return self.activate(self.selected),
+                    KeyCode::Escape => self.back(),
+                    KeyCode::Backspace => {
+                        if let Some(field) = self.selected_text_field() {
+                            field.pop();
+                        }
+                    }
+                    KeyCode::KeyN if matches!(self.screen, Screen::WorldList) => {
+                        self.open_create_world();
+                    }
+                    KeyCode::KeyR if matches!(self.screen, Screen::WorldList) => {
+                        self.open_rename_world();
+                    }
+                    KeyCode::Delete if matches!(self.screen, Screen::WorldList) => {
+                        self.open_delete_world();
+                    }
+                    _ => {
+                        if let Some(text) = text {
+                            self.input_text(text);
+                        }
+                    }
+                }
+                None
+            }
+            WindowEvent::CursorMoved { .. } => {
+                if let Some(i) = hovered {
+                    self.selected = i;
+                }
+                None
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let i = hovered?;
+                self.selected = i;
+                self.activate(i)
+            }
+            _ => None,
+        }
+    }
+
+    fn item_count(&self) -> usize {
+        match self.screen {
+            Screen::WorldList => self.worlds.len() + 1,
+            Screen::CreateWorld { .. } => 6,
+            Screen::RenameWorld { .. } => 3,
+            Screen::DeleteWorld { .. } => 2,
+        }
+    }
+
+    fn move_selection(&mut self, offset: i32) {
+        let count = self.item_count() as i32;
+        self.selected = (self.selected as i32 + offset).rem_euclid(count) as usize;
+    }
+
+    fn text_cursor(&self, index: usize) -> &'static str {
+        if self.selected == index {
+            "_"
+        } else {
+            ""
+        }
+    }
+
+    fn selected_text_field(&mut self) -> Option<&mut String> {
+        match (&mut self.screen, self.selected) {
+            (Screen::CreateWorld { name, .. }, 0) => Some(name),
+            (Screen::CreateWorld { seed, .. }, 1) => Some(seed),
+            (
+                Screen::CreateWorld {
+                    generator, layers, ..
+                },
+                3,
+            ) if *generator == SUPERFLAT => Some(layers),
+            (
+                Screen::CreateWorld {
+                    generator, custom, ..
+                },
+                3,
+            ) if *generator == CUSTOM => Some(custom),
+            (Screen::RenameWorld { name, .. }, 0) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn input_text(&mut self, text: &str) {
+        if let Some(field) = self.selected_text_field() {
+            field.extend(text.chars().filter(|c| !c.is_control()));
+        }
+    }
+
+    fn back(&mut self) {
+        if !matches!(self.screen, Screen::WorldList) {
+            self.message.clear();
+            self.refresh();
+        }
+    }
+
+    fn open_create_world(&mut self) {
+        //默认名称不与已有世界重复
+        let mut name = "新的世界".to_string();
+        let mut i = 1;
+        while self.worlds.iter().any(|w| w.dir_name == name) {
+            i += 1;
+            name = format!("新的世界{}", i);
+        }
+        self.screen = Screen::CreateWorld {
+            name,
+            seed: String::new(),
+            generator: GeneratorPreset::Default.index(),
+            layers: terrain::format_flat_layers(&terrain::default_flat_layers()),
+            custom: terrain::format_custom_terrain(&CustomTerrain::default()),
+        };
+        self.selected = 0;
+        self.message.clear();
+    }
+
+    fn open_rename_world(&mut self) {
+        if let Some(world) = self.worlds.get(self.selected) {
+            self.screen = Screen::RenameWorld {
+                dir_name: world.dir_name.clone(),
+                name: world.dir_name.clone(),
+            };
+            self.selected = 0;
+            self.message.clear();
+        }
+    }
+
+    fn open_delete_world(&mut self) {
+        if let Some(world) = self.worlds.get(self.selected) {
+            self.screen = Screen::DeleteWorld {
+                dir_name: world.dir_name.clone(),
+            };
+            self.selected = 1;
+            self.message.clear();
+        }
+    }
+
+    fn activate(&mut self, index: usize) -> Option<StartMenuAction> {
+        match &mut self.screen {
+            Screen::WorldList => {
+                if let Some(world) = self.worlds.get(index) {
+                    return Some(StartMenuAction::OpenWorld(world.dir_name.clone()));
+                }
+                self.open_create_world();
+            }
+            Screen::CreateWorld {
+                name,
+                seed,
+                generator,
+                layers,
+                custom,
+            } => match index {
+                0 | 1 | 3 => self.selected = index + 1,
+                2 => *generator = (*generator + 1) % GeneratorPreset::NAMES.len(),
+                4 => {
+                    let seed = level::parse_seed(seed);
+                    let preset = match *generator {
+                        SUPERFLAT => {
+                            terrain::parse_flat_layers(layers).map(GeneratorPreset::Superflat)
+                        }
+                        CUSTOM => {
+                            terrain::parse_custom_terrain(custom).map(GeneratorPreset::Custom)
+                        }
+                        _ => Ok(GeneratorPreset::from_index(*generator, Vec::new())),
+                    };
+                    let preset = match preset {
+                        Ok(preset) => preset,
+                        Err(e) => {
+                            self.message = format!("{}", e);
+                            return None;
+                        }
+                    };
+                    match level::create_world(&self.worlds_dir, name, seed, preset) {
+                        Ok(_) => {
+                            let name = name.clone();
+                            self.message.clear();
+                            self.refresh();
+                            return Some(StartMenuAction::OpenWorld(name));
+                        }
+                        Err(e) => self.message = format!("{}", e),
+                    }
+                }
+                _ => self.back(),
+            },
+            Screen::RenameWorld { dir_name, name } => match index {
+                0 => self.selected = 1,
+                1 => match level::rename_world(&self.worlds_dir, dir_name, name) {
+                    Ok(_) => self.back(),
+                    Err(e) => self.message = format!("{}", e),
+                },
+                _ => self.back(),
+            },
+            Screen::DeleteWorld { dir_name } => match index {
+                0 => match level::delete_world(&self.worlds_dir, dir_name) {
+                    Ok(_) => self.back(),
+                    Err(e) => self.message = format!("{}", e),
+                },
+                _ => self.back(),
+            },
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+
+    #[test]
+    fn test_start_menu_create_rename_delete() {
+        let mut menu = StartMenu::new(test_dir("start_menu"));
+        assert_eq!(menu.page().items, ["创建新世界"]);
+
+        //创建世界后直接进入
+        assert!(menu.activate(0).is_none());
+        menu.selected_text_field().unwrap().clear();
+        menu.input_text("我的世界");
+        menu.selected = 1;
+        menu.input_text("42");
+        let Some(StartMenuAction::OpenWorld(name)) = menu.activate(4) else {
+            panic!("创建世界失败:{}", menu.message);
+        };
+        assert_eq!(name, "我的世界");
+        assert_eq!(menu.worlds[0].level.as_ref().unwrap().seed, 42);
+
+        //重名时显示错误
+        menu.open_create_world();
+        menu.selected_text_field().unwrap().clear();
+        menu.input_text("我的世界");
+        assert!(menu.activate(4).is_none());
+        assert!(!menu.message.is_empty());
+        menu.back();
+
+        menu.selected = 0;
+        menu.open_rename_world();
+        menu.input_text("2");
+        menu.activate(1);
+        assert_eq!(menu.worlds[0].display_name(), "我的世界2");
+
+        menu.selected = 0;
+        menu.open_delete_world();
+        menu.activate(0);
+        assert!(menu.worlds.is_empty());
+        assert!(matches!(menu.screen, Screen::WorldList));
+    }
+
+    #[test]
+    fn test_start_menu_superflat() {
+        let mut menu = StartMenu::new(test_dir("start_menu_superflat"));
+        menu.open_create_world();
+        for _ in 0..3 {
+            menu.activate(2);
+        }
+        assert_eq!(menu.page().items[2], "生成器: 超平坦");
+        assert_eq!(
+            GeneratorPreset::from_index(SUPERFLAT, Vec::new()).name(),
+            "超平坦"
+        );
+
+        //层的格式错误时不创建世界
+        menu.selected = 3;
+        menu.input_text(",stone*");
+        assert!(menu.activate(4).is_none());
+        assert!(!menu.message.is_empty());
+
+        let field = menu.selected_text_field().unwrap();
+        field.clear();
+        field.push_str("bedrock,3*stone");
+        assert!(menu.activate(4).is_some());
+        let level = menu.worlds[0].level.as_ref().unwrap();
+        let GeneratorPreset::Superflat(layers) = &level.generator else {
+            panic!("生成器不是超平坦");
+        };
+        assert_eq!(layers.len(), 2);
+        assert_eq!(level.spawn[1], 5.0);
+    }
+
+    #[test]
+    fn test_start_menu_custom_terrain() {
+        let mut menu = StartMenu::new(test_dir("start_menu_custom"));
+        menu.open_create_world();
+        for _ in 0..4 {
+            menu.activate(2);
+        }
+        assert_eq!(menu.page().items[2], "生成器: 自定义");
+
+        //参数超出范围时不创建世界
+        menu.selected = 3;
+        let field = menu.selected_text_field().unwrap();
+        field.clear();
+        field.push_str("octaves=100");
+        assert!(menu.activate(4).is_none());
+        assert!(!menu.message.is_empty());
+
+        let field = menu.selected_text_field().unwrap();
+        field.clear();
+        field.push_str("amplitude=0,sea_level=50");
+        assert!(menu.activate(4).is_some());
+        let level = menu.worlds[0].level.as_ref().unwrap();
+        let GeneratorPreset::Custom(custom) = &level.generator else {
+            panic!("生成器不是自定义");
+        };
+        assert_eq!(custom.sea_level, 50);
+        assert_eq!(level.spawn[1], 51.0);
+    }
+}
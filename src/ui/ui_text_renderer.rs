@@ -105,7 +105,10 @@ impl UITextRenderer {
             .filter_map(|(id, entry)| {
                 // 如果调试信息面板关闭，且文本是调试信息，则跳过
                 if !is_debug_info_open
-                    && (id == "player_position" || id == "fps_info" || id == "chunk_info")
+                    && (id == "player_position"
+                        || id == "fps_info"
+                        || id == "chunk_info"
+                        || id == "worker_info")
                 {
                     return None; // 不渲染这个文本
                 }
@@ -237,6 +240,17 @@ impl UITextRenderer {
             TextBounds::default(),
             DEBUG_INFO_COLOR,
         );
+        self.add_text(
+            "worker_info",
+            &debug_info,
+            DEBUG_INFO_LEFT,
+            DEBUG_INFO_TOP
+                + inventory_renderer::HOTBAR_TOP
+                + inventory_renderer::SLOT_SIZE
+                + LINE_HEIGHT * 3.0,
+            TextBounds::default(),
+            DEBUG_INFO_COLOR,
+        );
     }
 
     pub fn update_debug_info(
@@ -263,14 +277,29 @@ impl UITextRenderer {
         self.update_text(
             "chunk_info",
             &format!(
-                "中心区块:({},{})  区块数量:{} pre_dx:{}, pre_dz:{} is_loading:{}",
+                "中心区块:({},{})  区块数量:{} 绘制:{} 剔除:{} 待加载:{}",
                 realm.data.center_chunk_pos.x,
                 realm.data.center_chunk_pos.z,
                 realm.data.chunk_map.len(),
-                realm.pre_dx,
-                realm.pre_dz,
-                realm.is_loading,
+                realm.drawn_chunks(),
+                realm.culled_chunks,
+                realm.pending_chunks(),
             ),
         );
+
+        //每个工作线程一行
+        let stats = realm.worker_stats();
+        let mut worker_info = format!("工作线程:{}", stats.len());
+        for (i, worker) in stats.iter().enumerate() {
+            worker_info.push_str(&format!(
+                "\n#{} 生成:{} 读取:{} 平均:{:.2}ms 最长:{:.2}ms",
+                i,
+                worker.generated,
+                worker.loaded,
+                worker.average_time().as_secs_f64() * 1000.0,
+                worker.max_time.as_secs_f64() * 1000.0,
+            ));
+        }
+        self.update_text("worker_info", &worker_info);
     }
 }